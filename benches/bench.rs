@@ -1,7 +1,7 @@
 #![feature(test)]
 extern crate test;
 
-use html_editor::parse;
+use html_editor::{parse, parse_borrowed};
 use test::Bencher;
 
 #[bench]
@@ -10,3 +10,10 @@ fn bench(b: &mut Bencher) {
         parse(include_str!("./bench.html")).unwrap();
     })
 }
+
+#[bench]
+fn bench_borrowed(b: &mut Bencher) {
+    b.iter(|| {
+        parse_borrowed(include_str!("./bench.html")).unwrap();
+    })
+}
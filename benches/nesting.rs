@@ -0,0 +1,55 @@
+#![feature(test)]
+extern crate test;
+
+use html_editor::{parse, try_parse};
+use test::Bencher;
+
+/// `<div>` nested `depth` times with some text in each level.
+fn nested(depth: usize) -> String {
+    format!(
+        "{}text{}",
+        "<div>text".repeat(depth),
+        "</div>".repeat(depth)
+    )
+}
+
+/// `<div><span>` opened `depth` times without being closed.
+fn unclosed(depth: usize) -> String {
+    "<div><span>text".repeat(depth)
+}
+
+#[bench]
+fn parse_nested_100(b: &mut Bencher) {
+    let html = nested(100);
+    b.iter(|| parse(&html).unwrap());
+}
+
+#[bench]
+fn parse_nested_1000(b: &mut Bencher) {
+    let html = nested(1000);
+    b.iter(|| parse(&html).unwrap());
+}
+
+#[bench]
+fn parse_nested_10000(b: &mut Bencher) {
+    let html = nested(10000);
+    b.iter(|| parse(&html).unwrap());
+}
+
+#[bench]
+fn try_parse_nested_1000(b: &mut Bencher) {
+    let html = nested(1000);
+    b.iter(|| try_parse(&html));
+}
+
+#[bench]
+fn try_parse_unclosed_1000(b: &mut Bencher) {
+    let html = unclosed(1000);
+    b.iter(|| try_parse(&html));
+}
+
+#[bench]
+fn try_parse_unclosed_10000(b: &mut Bencher) {
+    let html = unclosed(10000);
+    b.iter(|| try_parse(&html));
+}
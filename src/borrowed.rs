@@ -0,0 +1,293 @@
+//! DOM borrowing from the source html, created by
+//! [`parse_borrowed()`](crate::parse_borrowed) and
+//! [`try_parse_borrowed()`](crate::try_parse_borrowed).
+//!
+//! The names, attributes and text point into the source whenever they
+//! are the same as written, and are only copied if they are changed by
+//! parsing, like the decoded character references. It is cheaper for
+//! the read-only jobs, and can be converted to the owned
+//! [`Node`](crate::Node) by [`Node::to_owned()`].
+//!
+//! ```
+//! use std::borrow::Cow;
+//! use html_editor::parse_borrowed;
+//! use html_editor::operation::*;
+//!
+//! let html = r#"<p class="a">Tom &amp; Jerry</p>"#;
+//! let dom = parse_borrowed(html).unwrap();
+//! let p = dom.query(&Selector::from(".a")).unwrap();
+//! assert!(matches!(p.name, Cow::Borrowed("p")));
+//! assert_eq!(p.children[0].html(), "Tom &amp; Jerry");
+//!
+//! let owned: Vec<html_editor::Node> = dom.iter().map(|node| node.to_owned()).collect();
+//! assert_eq!(owned.html(), html);
+//! ```
+
+use std::borrow::Cow;
+
+use crate::{Doctype, ElementSpan, Namespace};
+
+/// Node of the borrowed DOM, like [`crate::Node`].
+#[derive(Debug, Clone)]
+pub enum Node<'a> {
+    Element(Element<'a>),
+    Text(Cow<'a, str>),
+    Comment(Cow<'a, str>),
+    Doctype(Doctype),
+    CData(Cow<'a, str>),
+    ProcessingInstruction {
+        target: Cow<'a, str>,
+        data: Cow<'a, str>,
+    },
+    ConditionalComment {
+        condition: Cow<'a, str>,
+        body: ConditionalBody<'a>,
+    },
+    DownlevelRevealed(Cow<'a, str>),
+    Passthrough(Cow<'a, str>),
+}
+
+/// Body of a [`Node::ConditionalComment`], like [`crate::ConditionalBody`].
+#[derive(Debug, Clone)]
+pub enum ConditionalBody<'a> {
+    Nodes(Vec<Node<'a>>),
+    Raw(Cow<'a, str>),
+}
+
+impl<'a> Node<'a> {
+    /// Check if it is an element node.
+    pub fn is_element(&self) -> bool {
+        matches!(self, Node::Element { .. })
+    }
+
+    /// Convert the node into an element.
+    ///
+    /// Returns `None` if the node is not an element.
+    pub fn as_element(&self) -> Option<&Element<'a>> {
+        match self {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// Convert the node into a mutable element.
+    ///
+    /// Returns `None` if the node is not an element.
+    pub fn as_element_mut(&mut self) -> Option<&mut Element<'a>> {
+        match self {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// Get where the element is in the source html. Like
+    /// [`crate::Node::element_span`], it returns `None` if the node is not
+    /// an element.
+    pub fn element_span(&self) -> Option<&ElementSpan> {
+        self.as_element().and_then(Element::span)
+    }
+
+    /// Copy the node into an owned [`crate::Node`].
+    pub fn to_owned(&self) -> crate::Node {
+        match self {
+            Node::Element(element) => crate::Node::Element(element.to_owned()),
+            Node::Text(text) => crate::Node::Text(text.to_string()),
+            Node::Comment(comment) => crate::Node::Comment(comment.to_string()),
+            Node::Doctype(doctype) => crate::Node::Doctype(doctype.clone()),
+            Node::CData(text) => crate::Node::CData(text.to_string()),
+            Node::ProcessingInstruction { target, data } => crate::Node::ProcessingInstruction {
+                target: target.to_string(),
+                data: data.to_string(),
+            },
+            Node::ConditionalComment { condition, body } => crate::Node::ConditionalComment {
+                condition: condition.to_string(),
+                body: match body {
+                    ConditionalBody::Nodes(nodes) => {
+                        crate::ConditionalBody::Nodes(nodes.iter().map(Node::to_owned).collect())
+                    }
+                    ConditionalBody::Raw(raw) => crate::ConditionalBody::Raw(raw.to_string()),
+                },
+            },
+            Node::DownlevelRevealed(text) => crate::Node::DownlevelRevealed(text.to_string()),
+            Node::Passthrough(raw) => crate::Node::Passthrough(raw.to_string()),
+        }
+    }
+}
+
+/// Element of the borrowed DOM, like [`crate::Element`].
+#[derive(Debug)]
+pub struct Element<'a> {
+    pub name: Cow<'a, str>,
+    pub attrs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    pub children: Vec<Node<'a>>,
+    pub namespace: Namespace,
+    /// The content of a parsed `<template>`, see
+    /// [`crate::Element::template_content`].
+    pub template_content: Option<Vec<Node<'a>>>,
+    span: Option<ElementSpan>,
+    /// The attributes whose values are written as they are while
+    /// unchanged, see [`crate::Element`].
+    pub(crate) raw_attrs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> Element<'a> {
+    /// Create an element without children.
+    pub(crate) fn from_parts(
+        name: Cow<'a, str>,
+        attrs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+        span: Option<ElementSpan>,
+    ) -> Self {
+        Element {
+            name,
+            attrs,
+            children: Vec::new(),
+            namespace: Namespace::Html,
+            template_content: None,
+            span,
+            raw_attrs: Vec::new(),
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> Option<&mut ElementSpan> {
+        self.span.as_mut()
+    }
+
+    /// Get where the element is in the source html. See
+    /// [`crate::Element::span`].
+    pub fn span(&self) -> Option<&ElementSpan> {
+        self.span.as_ref()
+    }
+
+    pub fn into_node(self) -> Node<'a> {
+        Node::Element(self)
+    }
+
+    /// Copy the element into an owned [`crate::Element`].
+    ///
+    /// The descendants are copied one by one like `drop()`, instead of
+    /// recursively.
+    pub fn to_owned(&self) -> crate::Element {
+        // The elements being copied, with the nodes left to copy.
+        let mut stack = vec![(self, self.owned_parts(), self.nodes())];
+        while let Some((element, copy, nodes)) = stack.last_mut() {
+            match nodes.next() {
+                Some(Node::Element(child)) => {
+                    stack.push((child, child.owned_parts(), child.nodes()));
+                }
+                Some(node) => copy.push_node(element.children.len(), node.to_owned()),
+                None => {
+                    let Some((_, copy, _)) = stack.pop() else {
+                        break;
+                    };
+                    match stack.last_mut() {
+                        Some((element, parent, _)) => {
+                            parent.push_node(element.children.len(), copy.into_node())
+                        }
+                        None => return copy,
+                    }
+                }
+            }
+        }
+        unreachable!("the element is returned when it is done")
+    }
+
+    /// Copy the element into an owned [`crate::Element`], without its
+    /// children and template content.
+    fn owned_parts(&self) -> crate::Element {
+        let attrs = self
+            .attrs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let mut element =
+            crate::Element::from_parts(self.name.to_string(), attrs, self.span.clone());
+        element.namespace = self.namespace.clone();
+        element.template_content = self.template_content.as_ref().map(|_| Vec::new());
+        element.raw_attrs = self
+            .raw_attrs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        element
+    }
+
+    /// Clone the element without its children and template content.
+    fn clone_parts(&self) -> Self {
+        Element {
+            name: self.name.clone(),
+            attrs: self.attrs.clone(),
+            children: Vec::with_capacity(self.children.len()),
+            namespace: self.namespace.clone(),
+            template_content: self
+                .template_content
+                .as_ref()
+                .map(|content| Vec::with_capacity(content.len())),
+            span: self.span.clone(),
+            raw_attrs: self.raw_attrs.clone(),
+        }
+    }
+
+    /// The children, and the template content after them.
+    fn nodes(&self) -> impl Iterator<Item = &Node<'a>> {
+        self.children
+            .iter()
+            .chain(self.template_content.iter().flatten())
+    }
+
+    /// Push the copy of a node, like [`crate::Element::push_node`].
+    fn push_node(&mut self, children_len: usize, node: Node<'a>) {
+        if self.children.len() < children_len {
+            self.children.push(node);
+        } else if let Some(content) = &mut self.template_content {
+            content.push(node);
+        }
+    }
+}
+
+impl Clone for Element<'_> {
+    /// Clone the descendants one by one like [`crate::Element`].
+    fn clone(&self) -> Self {
+        // The elements being cloned, with the nodes left to clone.
+        let mut stack = vec![(self, self.clone_parts(), self.nodes())];
+        while let Some((element, clone, nodes)) = stack.last_mut() {
+            match nodes.next() {
+                Some(Node::Element(child)) => {
+                    stack.push((child, child.clone_parts(), child.nodes()));
+                }
+                Some(node) => clone.push_node(element.children.len(), node.clone()),
+                None => {
+                    let Some((_, clone, _)) = stack.pop() else {
+                        break;
+                    };
+                    match stack.last_mut() {
+                        Some((element, parent, _)) => {
+                            parent.push_node(element.children.len(), Node::Element(clone))
+                        }
+                        None => return clone,
+                    }
+                }
+            }
+        }
+        unreachable!("the element is returned when it is done")
+    }
+}
+
+impl<'a> From<Element<'a>> for Node<'a> {
+    fn from(element: Element<'a>) -> Self {
+        Node::Element(element)
+    }
+}
+
+impl Drop for Element<'_> {
+    /// Drop the descendants one by one like [`crate::Element`].
+    fn drop(&mut self) {
+        let mut nodes = std::mem::take(&mut self.children);
+        nodes.extend(self.template_content.take().unwrap_or_default());
+        while let Some(mut node) = nodes.pop() {
+            if let Node::Element(element) = &mut node {
+                nodes.append(&mut element.children);
+                nodes.extend(element.template_content.take().unwrap_or_default());
+            }
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Detect the encoding of html bytes by the BOM and the `<meta>` tags,
+//! following the [encoding sniffing algorithm](https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm),
+//! and decode them.
+
+use crate::escape::C1_REPLACEMENTS;
+
+/// The encodings which html bytes can be decoded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Also used for `ISO-8859-1` and `US-ASCII`, like browsers do.
+    Windows1252,
+}
+
+/// Labels of the encodings in the [Encoding standard](https://encoding.spec.whatwg.org/#names-and-labels).
+const LABELS: [(&str, Encoding); 33] = [
+    ("unicode-1-1-utf-8", Encoding::Utf8),
+    ("unicode11utf8", Encoding::Utf8),
+    ("unicode20utf8", Encoding::Utf8),
+    ("utf-8", Encoding::Utf8),
+    ("utf8", Encoding::Utf8),
+    ("x-unicode20utf8", Encoding::Utf8),
+    ("csunicode", Encoding::Utf16Le),
+    ("iso-10646-ucs-2", Encoding::Utf16Le),
+    ("ucs-2", Encoding::Utf16Le),
+    ("unicode", Encoding::Utf16Le),
+    ("unicodefeff", Encoding::Utf16Le),
+    ("utf-16", Encoding::Utf16Le),
+    ("utf-16le", Encoding::Utf16Le),
+    ("unicodefffe", Encoding::Utf16Be),
+    ("utf-16be", Encoding::Utf16Be),
+    ("ansi_x3.4-1968", Encoding::Windows1252),
+    ("ascii", Encoding::Windows1252),
+    ("cp1252", Encoding::Windows1252),
+    ("cp819", Encoding::Windows1252),
+    ("csisolatin1", Encoding::Windows1252),
+    ("ibm819", Encoding::Windows1252),
+    ("iso-8859-1", Encoding::Windows1252),
+    ("iso-ir-100", Encoding::Windows1252),
+    ("iso8859-1", Encoding::Windows1252),
+    ("iso88591", Encoding::Windows1252),
+    ("iso_8859-1", Encoding::Windows1252),
+    ("iso_8859-1:1987", Encoding::Windows1252),
+    ("l1", Encoding::Windows1252),
+    ("latin1", Encoding::Windows1252),
+    ("us-ascii", Encoding::Windows1252),
+    ("windows-1252", Encoding::Windows1252),
+    ("x-cp1252", Encoding::Windows1252),
+    // It is decoded as windows-1252 when declared by `<meta>`.
+    ("x-user-defined", Encoding::Windows1252),
+];
+
+/// How many bytes are pre-scanned for the `<meta>` tags.
+const PRESCAN_LEN: usize = 1024;
+
+impl Encoding {
+    /// Get the encoding by its label like `utf-8` or `latin1`, which is
+    /// matched case-insensitively without the surrounding whitespace.
+    ///
+    /// Returns `None` if the encoding is unknown or not supported, like
+    /// `Shift_JIS`.
+    ///
+    /// ```
+    /// use html_editor::Encoding;
+    ///
+    /// assert_eq!(Encoding::for_label(" Latin1 "), Some(Encoding::Windows1252));
+    /// assert_eq!(Encoding::for_label("shift_jis"), None);
+    /// ```
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        let label = label.trim_matches(is_whitespace);
+        LABELS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(label))
+            .map(|(_, encoding)| *encoding)
+    }
+
+    /// Get the name of the encoding, like `UTF-8`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Detect the encoding of the html bytes.
+    ///
+    /// The BOM is checked first, and then the `<meta charset>` or
+    /// `<meta http-equiv="content-type">` in the first 1024 bytes, whose
+    /// unsupported encodings are ignored. Otherwise it is UTF-8 if the
+    /// bytes are valid UTF-8, or windows-1252 for the legacy pages.
+    ///
+    /// ```
+    /// use html_editor::Encoding;
+    ///
+    /// assert_eq!(Encoding::sniff(b"\xEF\xBB\xBF<p>"), Encoding::Utf8);
+    /// assert_eq!(
+    ///     Encoding::sniff(b"<meta charset=iso-8859-1><p>caf\xE9"),
+    ///     Encoding::Windows1252
+    /// );
+    /// ```
+    pub fn sniff(bytes: &[u8]) -> Encoding {
+        if let Some((encoding, _)) = bom(bytes) {
+            return encoding;
+        }
+        if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LEN)]) {
+            return encoding;
+        }
+        if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Windows1252
+        }
+    }
+
+    /// Decode the bytes without the BOM of this encoding, replacing the
+    /// invalid ones with `U+FFFD`.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let bytes = match bom(bytes) {
+            Some((encoding, len)) if encoding == *self => &bytes[len..],
+            _ => bytes,
+        };
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+            Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+            Encoding::Windows1252 => bytes.iter().map(|&byte| windows_1252(byte)).collect(),
+        }
+    }
+}
+
+fn is_whitespace(ch: char) -> bool {
+    matches!(ch, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+/// Get the encoding and the length of the BOM at the start of `bytes`.
+fn bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        Some((Encoding::Utf8, 3))
+    } else if bytes.starts_with(b"\xFE\xFF") {
+        Some((Encoding::Utf16Be, 2))
+    } else if bytes.starts_with(b"\xFF\xFE") {
+        Some((Encoding::Utf16Le, 2))
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+    let mut text: String = char::decode_utf16(units)
+        .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    if bytes.len() % 2 == 1 {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
+
+fn windows_1252(byte: u8) -> char {
+    match C1_REPLACEMENTS.binary_search_by_key(&u32::from(byte), |(code, _)| *code) {
+        Ok(index) => C1_REPLACEMENTS[index].1,
+        Err(_) => char::from(byte),
+    }
+}
+
+/// Pre-scan the bytes for the encoding declared by `<meta>`, following
+/// the [prescan algorithm](https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding).
+fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let is_letter = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_alphabetic);
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        if rest.starts_with(b"<!--") {
+            // The `-->` may share the dashes with `<!--`, like `<!-->`.
+            i += 2 + find(&rest[2..], b"-->")? + 2;
+        } else if rest.len() > 5
+            && rest[..5].eq_ignore_ascii_case(b"<meta")
+            && (is_space(rest[5]) || rest[5] == b'/')
+        {
+            i += 6;
+            if let Some(encoding) = meta(bytes, &mut i) {
+                return Some(encoding);
+            }
+        } else if rest[0] == b'<'
+            && (is_letter(i + 1) || rest.get(1) == Some(&b'/') && is_letter(i + 2))
+        {
+            // Skip the tag with its attributes.
+            while i < bytes.len() && !is_space(bytes[i]) && bytes[i] != b'>' {
+                i += 1;
+            }
+            while attribute(bytes, &mut i).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            i += find(rest, b">")?;
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Read the attributes of the `<meta>` tag, and get the encoding it
+/// declares.
+fn meta(bytes: &[u8], i: &mut usize) -> Option<Encoding> {
+    let mut names = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    // `Some(None)` if the declared encoding is not supported.
+    let mut charset = None;
+    while let Some((name, value)) = attribute(bytes, i) {
+        if names.contains(&name) {
+            continue;
+        }
+        match name.as_str() {
+            "http-equiv" => got_pragma |= value == "content-type",
+            "content" => {
+                if let (None, Some(encoding)) = (charset, content_charset(&value)) {
+                    charset = Some(Some(encoding));
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = Some(Encoding::for_label(&value));
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        names.push(name);
+    }
+    match (need_pragma, charset) {
+        (Some(true), _) if !got_pragma => None,
+        (Some(_), Some(Some(Encoding::Utf16Le | Encoding::Utf16Be))) => Some(Encoding::Utf8),
+        (Some(_), Some(encoding)) => encoding,
+        _ => None,
+    }
+}
+
+/// Read the next attribute of the tag at `i`, whose name and value are
+/// lowercased, following the [algorithm](https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing)
+/// for sniffing.
+///
+/// Returns `None` at the `>` or the end of `bytes`.
+fn attribute(bytes: &[u8], i: &mut usize) -> Option<(String, String)> {
+    let byte_at = |i: usize| bytes.get(i).map(u8::to_ascii_lowercase);
+    while byte_at(*i).is_some_and(|byte| is_space(byte) || byte == b'/') {
+        *i += 1;
+    }
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    loop {
+        match byte_at(*i)? {
+            b'=' if !name.is_empty() => break,
+            b'>' if name.is_empty() => return None,
+            b'/' | b'>' => return Some(to_string(name, value)),
+            byte if is_space(byte) => {
+                while byte_at(*i).is_some_and(is_space) {
+                    *i += 1;
+                }
+                if byte_at(*i)? != b'=' {
+                    return Some(to_string(name, value));
+                }
+                break;
+            }
+            byte => name.push(byte),
+        }
+        *i += 1;
+    }
+    // Skip the `=` and the whitespace after it.
+    *i += 1;
+    while byte_at(*i).is_some_and(is_space) {
+        *i += 1;
+    }
+    match byte_at(*i)? {
+        quote @ (b'"' | b'\'') => loop {
+            *i += 1;
+            match byte_at(*i)? {
+                byte if byte == quote => {
+                    *i += 1;
+                    return Some(to_string(name, value));
+                }
+                byte => value.push(byte),
+            }
+        },
+        b'>' => Some(to_string(name, value)),
+        _ => {
+            while let Some(byte) = byte_at(*i) {
+                if is_space(byte) || byte == b'>' {
+                    break;
+                }
+                value.push(byte);
+                *i += 1;
+            }
+            Some(to_string(name, value))
+        }
+    }
+}
+
+fn to_string(name: Vec<u8>, value: Vec<u8>) -> (String, String) {
+    (
+        String::from_utf8_lossy(&name).into_owned(),
+        String::from_utf8_lossy(&value).into_owned(),
+    )
+}
+
+/// Get the encoding in the `content` of `<meta http-equiv>`, like
+/// `text/html; charset=utf-8`.
+fn content_charset(content: &str) -> Option<Encoding> {
+    let mut rest = content;
+    loop {
+        let index = rest.find("charset")?;
+        rest = rest[index + "charset".len()..].trim_start_matches(is_whitespace);
+        let Some(value) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start_matches(is_whitespace);
+        let label = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &value[1..];
+                &value[..value.find(quote)?]
+            }
+            _ => value
+                .split(|ch| is_whitespace(ch) || ch == ';')
+                .next()
+                .unwrap_or_default(),
+        };
+        return Encoding::for_label(label);
+    }
+}
+
+fn find(bytes: &[u8], pattern: &[u8]) -> Option<usize> {
+    bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+}
@@ -0,0 +1,240 @@
+//! Decode and escape the character references like `&amp;` and `&#x27;`.
+
+use std::borrow::Cow;
+
+use crate::data::NAMED_REFERENCES;
+
+/// The longest name in [`NAMED_REFERENCES`], which is
+/// `CounterClockwiseContourIntegral;`.
+const MAX_NAME_LEN: usize = 32;
+
+/// Replacements for the numeric references to C1 controls, which are
+/// treated as windows-1252 by browsers, like `&#x80;` for `€`. The bytes
+/// `0x80` to `0x9F` of windows-1252 are decoded by it too.
+pub const C1_REPLACEMENTS: [(u32, char); 27] = [
+    (0x80, '\u{20AC}'),
+    (0x82, '\u{201A}'),
+    (0x83, '\u{0192}'),
+    (0x84, '\u{201E}'),
+    (0x85, '\u{2026}'),
+    (0x86, '\u{2020}'),
+    (0x87, '\u{2021}'),
+    (0x88, '\u{02C6}'),
+    (0x89, '\u{2030}'),
+    (0x8A, '\u{0160}'),
+    (0x8B, '\u{2039}'),
+    (0x8C, '\u{0152}'),
+    (0x8E, '\u{017D}'),
+    (0x91, '\u{2018}'),
+    (0x92, '\u{2019}'),
+    (0x93, '\u{201C}'),
+    (0x94, '\u{201D}'),
+    (0x95, '\u{2022}'),
+    (0x96, '\u{2013}'),
+    (0x97, '\u{2014}'),
+    (0x98, '\u{02DC}'),
+    (0x99, '\u{2122}'),
+    (0x9A, '\u{0161}'),
+    (0x9B, '\u{203A}'),
+    (0x9C, '\u{0153}'),
+    (0x9E, '\u{017E}'),
+    (0x9F, '\u{0178}'),
+];
+
+/// Decode the character references in `text`.
+///
+/// `in_attribute` should be `true` when decoding attribute values, in
+/// which the named references without `;` are not decoded if they are
+/// followed by `=` or an alphanumeric char, like `?a=1&copy=2`.
+///
+/// The text is only copied if it has any reference.
+pub fn decode(text: &str, in_attribute: bool) -> Cow<'_, str> {
+    let mut decoded = String::new();
+    // Byte offset of the text not copied to `decoded` yet, which is only
+    // copied once a reference is found.
+    let mut copied = 0;
+    let mut offset = 0;
+    while let Some(index) = text[offset..].find('&') {
+        let start = offset + index;
+        match decode_reference(&text[start + 1..], in_attribute) {
+            Some((replacement, len)) => {
+                if copied == 0 {
+                    decoded.reserve(text.len());
+                }
+                decoded.push_str(&text[copied..start]);
+                decoded.push_str(&replacement);
+                offset = start + 1 + len;
+                copied = offset;
+            }
+            None => offset = start + 1,
+        }
+    }
+    if copied == 0 {
+        return Cow::Borrowed(text);
+    }
+    decoded.push_str(&text[copied..]);
+    Cow::Owned(decoded)
+}
+
+/// Decode the reference at the beginning of `text`, which is right
+/// after a `&`.
+///
+/// Returns the replacement and the byte length of the reference
+/// (excluding the `&`), or `None` if it is not a reference.
+fn decode_reference(text: &str, in_attribute: bool) -> Option<(Cow<'static, str>, usize)> {
+    if let Some(numeric) = text.strip_prefix('#') {
+        let (digits, radix, prefix_len) = match numeric.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (numeric, 10, 1),
+        };
+        let digits_len = digits
+            .find(|ch: char| !ch.is_digit(radix))
+            .unwrap_or(digits.len());
+        if digits_len == 0 {
+            return None;
+        }
+        // Too large numbers are replaced with U+FFFD below, so it does
+        // not matter if they overflow.
+        let code = u32::from_str_radix(&digits[..digits_len], radix).unwrap_or(u32::MAX);
+        let semicolon_len = usize::from(digits[digits_len..].starts_with(';'));
+        let ch = numeric_reference(code);
+        return Some((
+            Cow::Owned(ch.to_string()),
+            prefix_len + digits_len + semicolon_len,
+        ));
+    }
+
+    // Find the longest name matched, as `&notin;` is `∉` rather than
+    // `¬in;`.
+    let max_len = text
+        .char_indices()
+        .take(MAX_NAME_LEN)
+        .take_while(|(_, ch)| ch.is_ascii_alphanumeric() || *ch == ';')
+        .map(|(i, ch)| i + ch.len_utf8())
+        .last()?;
+    for len in (1..=max_len).rev() {
+        let name = &text[..len];
+        if let Ok(index) = NAMED_REFERENCES.binary_search_by(|(n, _)| n.cmp(&name)) {
+            if !name.ends_with(';') && in_attribute {
+                let next = text[len..].chars().next();
+                if matches!(next, Some(ch) if ch == '=' || ch.is_ascii_alphanumeric()) {
+                    return None;
+                }
+            }
+            return Some((Cow::Borrowed(NAMED_REFERENCES[index].1), len));
+        }
+    }
+    None
+}
+
+/// Decode the character references in the text or attribute value of
+/// XML, which are only the five predefined entities like `&amp;` and the
+/// numeric references.
+///
+/// Returns the reason and the byte offset of the first thing which is
+/// not well-formed instead: a `&` not starting a reference, an undefined
+/// entity, a `<` in the attribute value, or a `]]>` in the text.
+///
+/// The text is only copied if it has any reference.
+pub fn decode_xml(text: &str, in_attribute: bool) -> Result<Cow<'_, str>, (String, usize)> {
+    let forbidden = if in_attribute { "<" } else { "]]>" };
+    if let Some(index) = text.find(forbidden) {
+        return Err((format!("`{}` is not escaped", forbidden), index));
+    }
+    if !text.contains('&') {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut offset = 0;
+    while let Some(index) = text[offset..].find('&') {
+        let start = offset + index;
+        decoded.push_str(&text[offset..start]);
+        let rest = &text[start + 1..];
+        let Some(name) = rest
+            .find(|ch: char| ch == ';' || ch == '&' || ch.is_whitespace())
+            .filter(|end| rest[*end..].starts_with(';'))
+            .map(|end| &rest[..end])
+        else {
+            return Err(("`&` is not escaped".to_string(), start));
+        };
+        let ch = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => name.strip_prefix('#').and_then(|numeric| {
+                let (digits, radix) = match numeric.strip_prefix('x') {
+                    Some(hex) => (hex, 16),
+                    None => (numeric, 10),
+                };
+                // `from_str_radix` accepts a sign, which is not allowed.
+                if !digits.chars().all(|ch| ch.is_digit(radix)) {
+                    return None;
+                }
+                u32::from_str_radix(digits, radix)
+                    .ok()
+                    .filter(|code| *code != 0)
+                    .and_then(char::from_u32)
+            }),
+        };
+        let Some(ch) = ch else {
+            let kind = if name.starts_with('#') {
+                "Invalid character reference"
+            } else {
+                "Undefined entity"
+            };
+            return Err((format!("{} `&{};`", kind, name), start));
+        };
+        decoded.push(ch);
+        offset = start + 1 + name.len() + 1;
+    }
+    decoded.push_str(&text[offset..]);
+    Ok(Cow::Owned(decoded))
+}
+
+fn numeric_reference(code: u32) -> char {
+    if let Ok(index) = C1_REPLACEMENTS.binary_search_by_key(&code, |(c, _)| *c) {
+        return C1_REPLACEMENTS[index].1;
+    }
+    match code {
+        0 => '\u{FFFD}',
+        // `char::from_u32` returns `None` for surrogates and the numbers
+        // beyond the range of Unicode.
+        _ => char::from_u32(code).unwrap_or('\u{FFFD}'),
+    }
+}
+
+/// Escape `&`, `<`, `>` and no-break spaces in text.
+pub fn escape_text(text: &str) -> String {
+    escape(text, false, true)
+}
+
+/// Escape `&`, `"`, `<`, `>` and no-break spaces in attribute values,
+/// which are always quoted by `"`.
+pub fn escape_attr(value: &str) -> String {
+    escape(value, true, true)
+}
+
+/// Escape the text or attribute value like [`escape_text()`] and
+/// [`escape_attr()`] except the no-break spaces, as `&nbsp;` is not
+/// defined in XML.
+pub fn escape_xml(text: &str, in_attribute: bool) -> String {
+    escape(text, in_attribute, false)
+}
+
+fn escape(text: &str, in_attribute: bool, nbsp: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\u{A0}' if nbsp => escaped.push_str("&nbsp;"),
+            '"' if in_attribute => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
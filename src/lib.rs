@@ -34,18 +34,128 @@
 //! ```
 
 mod data;
+mod encoding;
+mod escape;
 mod parse;
+mod span;
 
+pub mod borrowed;
 pub mod operation;
 
+pub use encoding::Encoding;
 pub use parse::parse;
+pub use parse::parse_bytes;
+pub use parse::parse_document;
+pub use parse::parse_fragment;
+pub use parse::parse_xml;
 pub use parse::try_parse;
+pub use parse::try_parse_with_diagnostics;
+pub use parse::try_parse_with_options_and_diagnostics;
+pub use parse::{events, events_with, Event, Events};
+pub use parse::{parse_borrowed, try_parse_borrowed};
+pub use parse::{parse_with, try_parse_with};
+pub use parse::{
+    DuplicateAttrs, Limit, Limits, NameCase, ParseError, ParseOptions, Parser, Position,
+};
+pub use span::{ElementSpan, Span};
 
 /// Doctype of Html or Xml
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Doctype {
+    /// `<!DOCTYPE html>`
     Html,
-    Xml { version: String, encoding: String },
+    /// Doctype with the public or system identifiers, or a name other
+    /// than `html`, like the legacy
+    /// `<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd">`.
+    ///
+    /// The name is kept as written.
+    Dtd {
+        name: String,
+        public_id: Option<String>,
+        system_id: Option<String>,
+    },
+    /// The XML declaration like `<?xml version="1.0" encoding="UTF-8"?>`,
+    /// whose `encoding` and `standalone` are optional.
+    Xml {
+        version: String,
+        encoding: Option<String>,
+        standalone: Option<String>,
+        /// The declaration as written by the parsed html, which is written
+        /// back as is unless the other fields are changed.
+        raw: Option<String>,
+    },
+}
+
+/// Rendering mode of a document decided by its doctype.
+///
+/// See [the HTML standard](https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+impl Doctype {
+    /// Get the quirks mode of the document with this doctype.
+    ///
+    /// ```
+    /// use html_editor::{Doctype, QuirksMode};
+    ///
+    /// assert_eq!(Doctype::Html.quirks_mode(), QuirksMode::NoQuirks);
+    ///
+    /// let doctype = Doctype::Dtd {
+    ///     name: "html".to_string(),
+    ///     public_id: Some("-//W3C//DTD HTML 4.01 Transitional//EN".to_string()),
+    ///     system_id: None,
+    /// };
+    /// assert_eq!(doctype.quirks_mode(), QuirksMode::Quirks);
+    /// ```
+    pub fn quirks_mode(&self) -> QuirksMode {
+        let (name, public_id, system_id) = match self {
+            Doctype::Html | Doctype::Xml { .. } => return QuirksMode::NoQuirks,
+            Doctype::Dtd {
+                name,
+                public_id,
+                system_id,
+            } => (name, public_id.as_deref(), system_id.as_deref()),
+        };
+        let starts_with = |id: &str, prefixes: &[&str]| {
+            prefixes.iter().any(|prefix| {
+                id.get(..prefix.len())
+                    .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+            })
+        };
+
+        if !name.eq_ignore_ascii_case("html") {
+            return QuirksMode::Quirks;
+        }
+        if let Some(system_id) = system_id {
+            if system_id.eq_ignore_ascii_case(data::QUIRKS_SYSTEM_ID) {
+                return QuirksMode::Quirks;
+            }
+        }
+        let Some(public_id) = public_id else {
+            return QuirksMode::NoQuirks;
+        };
+        if data::QUIRKS_PUBLIC_IDS
+            .iter()
+            .any(|id| id.eq_ignore_ascii_case(public_id))
+            || starts_with(public_id, &data::QUIRKS_PUBLIC_ID_PREFIXES)
+        {
+            return QuirksMode::Quirks;
+        }
+        if starts_with(public_id, &data::HTML4_PUBLIC_ID_PREFIXES) {
+            return match system_id {
+                Some(_) => QuirksMode::LimitedQuirks,
+                None => QuirksMode::Quirks,
+            };
+        }
+        if starts_with(public_id, &data::LIMITED_QUIRKS_PUBLIC_ID_PREFIXES) {
+            return QuirksMode::LimitedQuirks;
+        }
+        QuirksMode::NoQuirks
+    }
 }
 
 /// Node of DOM
@@ -55,6 +165,62 @@ pub enum Node {
     Text(String),
     Comment(String),
     Doctype(Doctype),
+    /// CDATA section like `<![CDATA[x < y]]>`, whose text is kept as is.
+    CData(String),
+    /// Processing instruction like `<?xml-stylesheet href="a.css"?>`,
+    /// except the XML declaration which is a [`Doctype`].
+    ProcessingInstruction {
+        target: String,
+        /// The text after the target with the whitespace before it,
+        /// like ` href="a.css"`, which is kept to be written back as is.
+        data: String,
+    },
+    /// Conditional comment of Internet Explorer and Outlook like
+    /// `<!--[if mso]><table><tr><td><![endif]-->`, which is a comment to
+    /// the other browsers.
+    ///
+    /// The comments like `<!--[if !mso]><!-->` revealing the following
+    /// html to the other browsers are kept as [`Node::Comment`].
+    ConditionalComment {
+        /// The condition like `mso` or `gte mso 9`.
+        condition: String,
+        body: ConditionalBody,
+    },
+    /// Downlevel-revealed conditional comment like `<![if !mso]>` or
+    /// `<![endif]>`, whose html in between is read by all the browsers
+    /// but IE, with the text between `<!` and `>` like `[if !mso]`.
+    DownlevelRevealed(String),
+    /// Region of the template syntax like `{{ value }}` or `<?php ... ?>`
+    /// with its delimiters, which is kept as written, see
+    /// [`ParseOptions::passthrough`].
+    Passthrough(String),
+}
+
+/// Body of a [`Node::ConditionalComment`].
+///
+/// ```
+/// use html_editor::{parse, operation::*, ConditionalBody, Node};
+///
+/// let html = "<!--[if mso]><p>Outlook</p><![endif]--><!--[if IE]><table><tr><td><![endif]-->";
+/// let dom = parse(html).unwrap();
+/// let Node::ConditionalComment { condition, body: ConditionalBody::Nodes(nodes) } = &dom[0] else {
+///     panic!("not parsed");
+/// };
+/// assert_eq!(condition, "mso");
+/// assert_eq!(nodes.html(), "<p>Outlook</p>");
+/// assert!(matches!(
+///     &dom[1],
+///     Node::ConditionalComment { body: ConditionalBody::Raw(raw), .. } if raw == "<table><tr><td>"
+/// ));
+/// assert_eq!(dom.html(), html);
+/// ```
+#[derive(Debug, Clone)]
+pub enum ConditionalBody {
+    /// The nodes parsed from the body by [`parse()`].
+    Nodes(Vec<Node>),
+    /// The body as written, if it is not well-formed alone, like the
+    /// unclosed tags which are closed by another conditional comment.
+    Raw(String),
 }
 
 impl Node {
@@ -134,24 +300,128 @@ impl Node {
     /// );
     /// ```
     pub fn new_element(name: &str, attrs: Vec<(&str, &str)>, children: Vec<Node>) -> Node {
-        Element {
-            name: name.to_string(),
-            attrs: attrs
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect(),
-            children,
+        Element::new(name, attrs, children).into_node()
+    }
+
+    /// Get where the element is in the source html, see [`Element::span`].
+    ///
+    /// Only the elements have spans in the DOM, so it returns `None` for
+    /// the other nodes like the text and the comments, whose spans are
+    /// reported by [`events()`] instead. It returns `None` for the
+    /// elements not created by parsing too.
+    ///
+    /// ```
+    /// use html_editor::{events, parse, Event};
+    ///
+    /// let html = "<p>Hello</p>world";
+    /// let dom = parse(html).unwrap();
+    /// assert_eq!(dom[0].element_span().unwrap().outer.range(), 0..12);
+    /// assert!(dom[1].element_span().is_none());
+    ///
+    /// let (text, span) = events(html).last().unwrap().unwrap();
+    /// assert_eq!(text, Event::Text("world".to_string()));
+    /// assert_eq!(&html[span.range()], "world");
+    /// ```
+    pub fn element_span(&self) -> Option<&ElementSpan> {
+        self.as_element().and_then(Element::span)
+    }
+}
+
+/// Namespace of an element, which is decided by where it is when
+/// parsing, like the elements in `<svg>` are SVG, or by the `xmlns`
+/// attributes in XML.
+///
+/// ```
+/// use html_editor::{parse, operation::*, Namespace};
+///
+/// let dom = parse("<p><svg><title>Logo</title></svg></p>").unwrap();
+/// let title = dom.query(&Selector::from("title")).unwrap();
+/// assert_eq!(title.namespace, Namespace::Svg);
+/// assert_eq!(title.namespace.url(), "http://www.w3.org/2000/svg");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    #[default]
+    Html,
+    Svg,
+    MathMl,
+    /// Any other namespace of XML, by its URL.
+    Other(String),
+    /// No namespace, for the XML elements without a default namespace.
+    Null,
+}
+
+impl Namespace {
+    /// Get the namespace of the URL.
+    ///
+    /// ```
+    /// use html_editor::Namespace;
+    ///
+    /// assert_eq!(Namespace::from_url("http://www.w3.org/2000/svg"), Namespace::Svg);
+    /// assert_eq!(Namespace::from_url(""), Namespace::Null);
+    /// ```
+    pub fn from_url(url: &str) -> Self {
+        match url {
+            "http://www.w3.org/1999/xhtml" => Namespace::Html,
+            "http://www.w3.org/2000/svg" => Namespace::Svg,
+            "http://www.w3.org/1998/Math/MathML" => Namespace::MathMl,
+            "" => Namespace::Null,
+            _ => Namespace::Other(url.to_string()),
+        }
+    }
+
+    /// Get the URL of the namespace, like `http://www.w3.org/2000/svg`,
+    /// which is empty for [`Namespace::Null`].
+    pub fn url(&self) -> &str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::Other(url) => url,
+            Namespace::Null => "",
         }
-        .into_node()
     }
 }
 
 /// HTML Element
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Element {
     pub name: String,
     pub attrs: Vec<(String, String)>,
     pub children: Vec<Node>,
+    /// The namespace, which is [`Namespace::Html`] for the elements
+    /// created by [`Element::new`].
+    pub namespace: Namespace,
+    /// The content of a parsed `<template>`, which is kept apart from the
+    /// children like browsers do, so that it is skipped by
+    /// [`Queryable`](operation::Queryable) and
+    /// [`Editable`](operation::Editable) unless reached explicitly.
+    ///
+    /// It is `None` for the other elements and the ones created by
+    /// [`Element::new`], and written after the children by
+    /// [`Htmlifiable`](operation::Htmlifiable).
+    ///
+    /// ```
+    /// use html_editor::{parse, operation::*, Node};
+    ///
+    /// let html = r#"<template><p class="a">A</p></template><p class="b">B</p>"#;
+    /// let mut dom = parse(html).unwrap();
+    /// assert_eq!(dom.query_all(&Selector::from("p")).len(), 1);
+    ///
+    /// dom.execute_for(&Selector::from("template"), |template| {
+    ///     if let Some(content) = &mut template.template_content {
+    ///         content.remove_by(&Selector::from(".a"));
+    ///         content.push(Node::Text("C".to_string()));
+    ///     }
+    /// });
+    /// assert_eq!(dom.html(), r#"<template>C</template><p class="b">B</p>"#);
+    /// ```
+    pub template_content: Option<Vec<Node>>,
+    span: Option<ElementSpan>,
+    /// The attributes whose values have regions of
+    /// [`ParseOptions::passthrough`], which are written as they are while
+    /// unchanged.
+    raw_attrs: Vec<(String, String)>,
 }
 
 impl Element {
@@ -164,8 +434,51 @@ impl Element {
                 .map(|(k, v)| (k.to_string(), v.to_string()))
                 .collect(),
             children,
+            namespace: Namespace::Html,
+            template_content: None,
+            span: None,
+            raw_attrs: Vec::new(),
+        }
+    }
+
+    /// Create an element without children.
+    pub(crate) fn from_parts(
+        name: String,
+        attrs: Vec<(String, String)>,
+        span: Option<ElementSpan>,
+    ) -> Self {
+        Self {
+            name,
+            attrs,
+            children: Vec::new(),
+            namespace: Namespace::Html,
+            template_content: None,
+            span,
+            raw_attrs: Vec::new(),
         }
     }
+
+    /// Get where the element is in the source html.
+    ///
+    /// Returns `None` if the element is not created by parsing, like
+    /// [`Element::new`] and [`Node::new_element`].
+    ///
+    /// The span keeps pointing to the source even if the element is
+    /// edited later, so be careful to use it after editing.
+    ///
+    /// ```
+    /// use html_editor::{parse, Node};
+    ///
+    /// let html = "<p>Hello</p>";
+    /// let dom = parse(html).unwrap();
+    /// assert_eq!(dom[0].as_element().unwrap().span().unwrap().outer.range(), 0..12);
+    ///
+    /// let node = Node::new_element("p", vec![], vec![]);
+    /// assert!(node.element_span().is_none());
+    /// ```
+    pub fn span(&self) -> Option<&ElementSpan> {
+        self.span.as_ref()
+    }
 }
 
 impl Element {
@@ -179,3 +492,82 @@ impl From<Element> for Node {
         Node::Element(element)
     }
 }
+
+impl Clone for Element {
+    /// Clone the descendants one by one like `drop()`, instead of
+    /// recursively.
+    fn clone(&self) -> Self {
+        // The elements being cloned, with the nodes left to clone.
+        let mut stack = vec![(self, self.clone_parts(), self.nodes())];
+        while let Some((element, clone, nodes)) = stack.last_mut() {
+            match nodes.next() {
+                Some(Node::Element(child)) => {
+                    stack.push((child, child.clone_parts(), child.nodes()));
+                }
+                Some(node) => clone.push_node(element.children.len(), node.clone()),
+                None => {
+                    let Some((_, clone, _)) = stack.pop() else {
+                        break;
+                    };
+                    match stack.last_mut() {
+                        Some((element, parent, _)) => {
+                            parent.push_node(element.children.len(), Node::Element(clone))
+                        }
+                        None => return clone,
+                    }
+                }
+            }
+        }
+        unreachable!("the element is returned when it is done")
+    }
+}
+
+impl Element {
+    /// Clone the element without its children and template content.
+    fn clone_parts(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            attrs: self.attrs.clone(),
+            children: Vec::with_capacity(self.children.len()),
+            namespace: self.namespace.clone(),
+            template_content: self
+                .template_content
+                .as_ref()
+                .map(|content| Vec::with_capacity(content.len())),
+            span: self.span.clone(),
+            raw_attrs: self.raw_attrs.clone(),
+        }
+    }
+
+    /// The children, and the template content after them.
+    fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children
+            .iter()
+            .chain(self.template_content.iter().flatten())
+    }
+
+    /// Push the copy of a node of the element with `children_len`
+    /// children, to the children or the template content after them.
+    pub(crate) fn push_node(&mut self, children_len: usize, node: Node) {
+        if self.children.len() < children_len {
+            self.children.push(node);
+        } else if let Some(content) = &mut self.template_content {
+            content.push(node);
+        }
+    }
+}
+
+impl Drop for Element {
+    /// Drop the descendants one by one instead of recursively, which
+    /// would overflow the stack if they are nested too deeply.
+    fn drop(&mut self) {
+        let mut nodes = std::mem::take(&mut self.children);
+        nodes.extend(self.template_content.take().unwrap_or_default());
+        while let Some(mut node) = nodes.pop() {
+            if let Node::Element(element) = &mut node {
+                nodes.append(&mut element.children);
+                nodes.extend(element.template_content.take().unwrap_or_default());
+            }
+        }
+    }
+}
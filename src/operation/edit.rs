@@ -1,3 +1,5 @@
+use std::iter::once;
+
 use super::Selector;
 use crate::{Element, Node};
 
@@ -124,99 +126,141 @@ pub trait Editable {
     fn execute_for(&mut self, selector: &Selector, f: impl FnMut(&mut Element));
 }
 
-// We meed this function to allow the trait interface to use `impl FnMut(&mut Element)` instead of `&mut impl FnMut(&mut Element)`
-fn nodes_execute_for_internal(
-    nodes: &mut Vec<Node>,
+/// Call `f` for `nodes` and the children of every element in them, before
+/// visiting the children of the elements left by `f`.
+///
+/// A stack is used instead of recursion, which would overflow the stack
+/// if the elements are nested too deeply.
+fn for_each_children(nodes: &mut Vec<Node>, mut f: impl FnMut(&mut Vec<Node>)) {
+    let mut stack = vec![nodes];
+    while let Some(nodes) = stack.pop() {
+        f(nodes);
+        stack.extend(
+            nodes
+                .iter_mut()
+                .filter_map(Node::as_element_mut)
+                .map(|el| &mut el.children),
+        );
+    }
+}
+
+/// Call `f` for the `elements` and their descendants matching the
+/// selector in the document order, using a stack like
+/// [`for_each_children()`].
+fn execute_for_all<'a>(
+    elements: impl DoubleEndedIterator<Item = &'a mut Element>,
     selector: &Selector,
     f: &mut impl FnMut(&mut Element),
 ) {
-    for node in nodes {
-        if let Some(element) = node.as_element_mut() {
-            // Recursively traverse the descendants nodes
-            element_execute_for_internal(element, selector, f);
+    let mut stack: Vec<&mut Element> = elements.rev().collect();
+    while let Some(el) = stack.pop() {
+        if selector.matches(el) {
+            f(el);
         }
+        stack.extend(
+            el.children
+                .iter_mut()
+                .rev()
+                .filter_map(Node::as_element_mut),
+        );
     }
 }
 
-// We meed this function to allow the trait interface to use `impl FnMut(&mut Element)` instead of `&mut impl FnMut(&mut Element)`
-fn element_execute_for_internal(
-    element: &mut Element,
+/// Push the `target` to the children of the `elements` and their
+/// descendants matching the selector, using a stack like
+/// [`for_each_children()`].
+fn insert_to_all<'a>(
+    elements: impl Iterator<Item = &'a mut Element>,
     selector: &Selector,
-    f: &mut impl FnMut(&mut Element),
+    target: &Node,
 ) {
-    if selector.matches(element) {
-        f(element);
+    let mut stack: Vec<&mut Element> = elements.collect();
+    while let Some(el) = stack.pop() {
+        // The inserted node is not a descendant to insert to.
+        let len = el.children.len();
+        if selector.matches(el) {
+            el.children.push(target.clone());
+        }
+        stack.extend(
+            el.children[..len]
+                .iter_mut()
+                .filter_map(Node::as_element_mut),
+        );
     }
-    nodes_execute_for_internal(&mut element.children, selector, f);
 }
 
 impl Editable for Vec<Node> {
     fn trim(&mut self) -> &mut Self {
-        self.retain(|node| match node {
-            Node::Doctype(..) => true,
-            Node::Comment(..) => false,
-            Node::Text(text) => !text.trim().is_empty(),
-            Node::Element { .. } => true,
+        for_each_children(self, |nodes| {
+            nodes.retain(|node| match node {
+                Node::Doctype(..) => true,
+                // Except the ones revealing the html to the browsers other
+                // than Outlook and IE, like `<!--[if !mso]><!-->` and
+                // `<!--<![endif]-->`.
+                Node::Comment(comment) => {
+                    comment.starts_with("[if") && comment.ends_with("<!") || comment == "<![endif]"
+                }
+                Node::CData(..) | Node::ProcessingInstruction { .. } => true,
+                // Unlike the comments, they are read by Outlook and IE.
+                Node::ConditionalComment { .. } | Node::DownlevelRevealed(..) => true,
+                Node::Passthrough(..) => true,
+                Node::Text(text) => !text.trim().is_empty(),
+                Node::Element { .. } => true,
+            })
         });
-        for node in self.iter_mut() {
-            if let Node::Element(el) = node {
-                el.children.trim();
-            }
-        }
         self
     }
 
     fn insert_to(&mut self, selector: &Selector, target: Node) -> &mut Self {
-        for node in self.iter_mut() {
-            if let Node::Element(el) = node {
-                el.children.insert_to(selector, target.clone());
-                if selector.matches(&Element {
-                    name: el.name.clone(),
-                    attrs: el.attrs.clone(),
-                    children: vec![],
-                }) {
-                    el.children.push(target.clone());
-                }
-            }
-        }
+        insert_to_all(
+            self.iter_mut().filter_map(Node::as_element_mut),
+            selector,
+            &target,
+        );
         self
     }
 
     fn remove_by(&mut self, selector: &Selector) -> &mut Self {
-        self.retain(|node| {
-            if let Node::Element(el) = node {
-                let element = Element {
-                    name: el.name.clone(),
-                    attrs: el.attrs.clone(),
-                    children: vec![],
-                };
-                return !selector.matches(&element);
-            }
-            true
+        for_each_children(self, |nodes| {
+            nodes.retain(|node| !node.as_element().is_some_and(|el| selector.matches(el)))
         });
-        for node in self.iter_mut() {
-            if let Node::Element(el) = node {
-                el.remove_by(selector);
-            }
-        }
         self
     }
 
     fn replace_with(&mut self, selector: &Selector, f: fn(el: &Element) -> Node) -> &mut Self {
-        for node in self.iter_mut() {
-            if let Node::Element(ref mut el) = node {
-                if selector.matches(el) {
-                    *node = f(el);
-                } else {
-                    el.replace_with(selector, f);
-                }
+        let mut stack = vec![&mut *self];
+        while let Some(nodes) = stack.pop() {
+            let mut replaced = Vec::with_capacity(nodes.len());
+            for node in nodes.iter_mut() {
+                let replacement = match node {
+                    Node::Element(el) if selector.matches(el) => f(el),
+                    _ => {
+                        replaced.push(false);
+                        continue;
+                    }
+                };
+                *node = replacement;
+                replaced.push(true);
             }
+            // The nodes replacing the elements are not visited.
+            stack.extend(
+                nodes
+                    .iter_mut()
+                    .zip(replaced)
+                    .filter(|(_, replaced)| !replaced)
+                    .filter_map(|(node, _)| node.as_element_mut())
+                    .map(|el| &mut el.children),
+            );
         }
         self
     }
 
     fn execute_for(&mut self, selector: &Selector, mut f: impl FnMut(&mut Element)) {
-        nodes_execute_for_internal(self, selector, &mut f);
+        execute_for_all(
+            self.iter_mut().filter_map(Node::as_element_mut),
+            selector,
+            &mut f,
+        );
     }
 }
 
@@ -227,10 +271,7 @@ impl Editable for Element {
     }
 
     fn insert_to(&mut self, selector: &Selector, target: Node) -> &mut Self {
-        self.children.insert_to(selector, target.clone());
-        if selector.matches(self) {
-            self.children.push(target);
-        }
+        insert_to_all(once(&mut *self), selector, &target);
         self
     }
 
@@ -245,6 +286,6 @@ impl Editable for Element {
     }
 
     fn execute_for(&mut self, selector: &Selector, mut f: impl FnMut(&mut Element)) {
-        element_execute_for_internal(self, selector, &mut f);
+        execute_for_all(once(self), selector, &mut f);
     }
 }
@@ -1,9 +1,21 @@
-use crate::{data::VOID_TAGS, Doctype, Element, Node};
+use std::iter::once;
+
+use crate::{
+    borrowed,
+    data::{is_raw_text, VOID_TAGS},
+    escape::{escape_attr, escape_text, escape_xml},
+    parse::parse_xml_declaration,
+    ConditionalBody, Doctype, Element, Namespace, Node,
+};
 
 /// Stringify into html.
 pub trait Htmlifiable {
     /// Convert the object to html string.
     ///
+    /// The text and attribute values are escaped, except the text in
+    /// raw text elements like `<script>` and `<style>`. The SVG and MathML
+    /// elements are never void or raw text, like `<svg><style></style>`.
+    ///
     /// ```
     /// use html_editor::{Node, Element};
     /// use html_editor::operation::*;
@@ -29,62 +41,330 @@ pub trait Htmlifiable {
 
 impl Htmlifiable for Element {
     fn html(&self) -> String {
-        if self.attrs.is_empty() {
-            return if VOID_TAGS.contains(&self.name.as_str()) {
-                format!("<{}>", self.name)
-            } else {
-                format!("<{}>{}</{}>", self.name, self.children.html(), self.name)
-            };
+        write(once(element_part(self)), false)
+    }
+}
+
+impl Htmlifiable for Node {
+    fn html(&self) -> String {
+        write(once(self.part()), false)
+    }
+}
+
+impl Htmlifiable for borrowed::Element<'_> {
+    fn html(&self) -> String {
+        write(once(borrowed_element_part(self)), false)
+    }
+}
+
+impl Htmlifiable for borrowed::Node<'_> {
+    fn html(&self) -> String {
+        write(once(self.part()), false)
+    }
+}
+
+impl Htmlifiable for Vec<borrowed::Node<'_>> {
+    fn html(&self) -> String {
+        write(self.iter().map(Markup::part), false)
+    }
+}
+
+/// A node of the owned or the borrowed DOM, split into the parts to
+/// write.
+pub(super) enum Part<'a, N> {
+    Element {
+        name: &'a str,
+        namespace: &'a Namespace,
+        /// The attributes with whether their values are written as they
+        /// are, like the ones with regions of the template syntax.
+        attrs: Vec<(&'a str, &'a str, bool)>,
+        children: &'a [N],
+        template_content: Option<&'a [N]>,
+    },
+    Text(&'a str),
+    /// The conditional comment with the parsed body, or the raw one.
+    ConditionalComment {
+        condition: &'a str,
+        body: Result<&'a [N], &'a str>,
+    },
+    /// The other nodes, which are written the same in html and XML.
+    Markup(String),
+}
+
+/// Node of the owned or the borrowed DOM.
+pub(super) trait Markup: Sized {
+    fn part(&self) -> Part<'_, Self>;
+}
+
+impl Markup for Node {
+    fn part(&self) -> Part<'_, Self> {
+        match self {
+            Node::Element(element) => element_part(element),
+            Node::Text(text) => Part::Text(text),
+            Node::Comment(comment) => Part::Markup(format!("<!--{}-->", comment)),
+            Node::Doctype(doctype) => Part::Markup(doctype_html(doctype)),
+            Node::CData(text) => Part::Markup(format!("<![CDATA[{}]]>", text)),
+            Node::ProcessingInstruction { target, data } => {
+                Part::Markup(processing_instruction_html(target, data))
+            }
+            Node::ConditionalComment { condition, body } => Part::ConditionalComment {
+                condition,
+                body: match body {
+                    ConditionalBody::Nodes(nodes) => Ok(nodes),
+                    ConditionalBody::Raw(raw) => Err(raw),
+                },
+            },
+            Node::DownlevelRevealed(text) => Part::Markup(format!("<!{}>", text)),
+            Node::Passthrough(raw) => Part::Markup(raw.to_string()),
         }
-        let attrs = self
+    }
+}
+
+impl Markup for borrowed::Node<'_> {
+    fn part(&self) -> Part<'_, Self> {
+        match self {
+            borrowed::Node::Element(element) => borrowed_element_part(element),
+            borrowed::Node::Text(text) => Part::Text(text),
+            borrowed::Node::Comment(comment) => Part::Markup(format!("<!--{}-->", comment)),
+            borrowed::Node::Doctype(doctype) => Part::Markup(doctype_html(doctype)),
+            borrowed::Node::CData(text) => Part::Markup(format!("<![CDATA[{}]]>", text)),
+            borrowed::Node::ProcessingInstruction { target, data } => {
+                Part::Markup(processing_instruction_html(target, data))
+            }
+            borrowed::Node::ConditionalComment { condition, body } => Part::ConditionalComment {
+                condition,
+                body: match body {
+                    borrowed::ConditionalBody::Nodes(nodes) => Ok(nodes),
+                    borrowed::ConditionalBody::Raw(raw) => Err(raw),
+                },
+            },
+            borrowed::Node::DownlevelRevealed(text) => Part::Markup(format!("<!{}>", text)),
+            borrowed::Node::Passthrough(raw) => Part::Markup(raw.to_string()),
+        }
+    }
+}
+
+pub(super) fn element_part(element: &Element) -> Part<'_, Node> {
+    Part::Element {
+        name: &element.name,
+        namespace: &element.namespace,
+        attrs: element
             .attrs
             .iter()
             .map(|(k, v)| {
-                if v.is_empty() {
-                    k.to_string()
-                } else {
-                    format!(r#"{}="{}""#, k, v)
-                }
+                let raw = element.raw_attrs.iter().any(|(rk, rv)| rk == k && rv == v);
+                (k.as_str(), v.as_str(), raw)
+            })
+            .collect(),
+        children: &element.children,
+        template_content: element.template_content.as_deref(),
+    }
+}
+
+pub(super) fn borrowed_element_part<'a, 'b>(
+    element: &'a borrowed::Element<'b>,
+) -> Part<'a, borrowed::Node<'b>> {
+    Part::Element {
+        name: &element.name,
+        namespace: &element.namespace,
+        attrs: element
+            .attrs
+            .iter()
+            .map(|(k, v)| {
+                let raw = element.raw_attrs.iter().any(|(rk, rv)| rk == k && rv == v);
+                (k.as_ref(), v.as_ref(), raw)
             })
-            .collect::<Vec<_>>()
-            .join(" ");
-
-        if VOID_TAGS.contains(&self.name.as_str()) {
-            format!("<{} {}>", self.name, attrs,)
-        } else {
-            format!(
-                "<{} {}>{}</{}>",
-                self.name,
+            .collect(),
+        children: &element.children,
+        template_content: element.template_content.as_deref(),
+    }
+}
+
+/// What is left to write.
+enum Step<'a, N> {
+    Part(Part<'a, N>),
+    /// The text of a raw text element like `<script>`.
+    RawText(&'a str),
+    /// The end tag of the element, whose start tag ends at the offset.
+    EndTag(&'a str, usize),
+    EndConditional,
+}
+
+/// Write the `parts` into html, or XML if `xml` is true, one by one with
+/// a stack instead of recursion, which would overflow the stack if the
+/// elements are nested too deeply.
+///
+/// In XML, the elements without children are written like `<x/>`, every
+/// attribute has a quoted value, and the text is always escaped.
+pub(super) fn write<'a, N: Markup + 'a>(
+    parts: impl DoubleEndedIterator<Item = Part<'a, N>>,
+    xml: bool,
+) -> String {
+    let mut out = String::new();
+    let mut steps: Vec<Step<N>> = parts.rev().map(Step::Part).collect();
+    while let Some(step) = steps.pop() {
+        let part = match step {
+            Step::Part(part) => part,
+            Step::RawText(text) => {
+                out.push_str(text);
+                continue;
+            }
+            // The `>` of the start tag is the last char if it is empty.
+            Step::EndTag(_, start_tag_end) if xml && out.len() == start_tag_end => {
+                out.pop();
+                out.push_str("/>");
+                continue;
+            }
+            Step::EndTag(name, _) => {
+                out.push_str(&format!("</{}>", name));
+                continue;
+            }
+            Step::EndConditional => {
+                out.push_str("<![endif]-->");
+                continue;
+            }
+        };
+        match part {
+            Part::Element {
+                name,
+                namespace,
                 attrs,
-                self.children.html(),
-                self.name
-            )
+                children,
+                template_content,
+            } => {
+                out.push_str(&start_tag(name, attrs, xml));
+                let is_html = !xml && *namespace == Namespace::Html;
+                if is_html && VOID_TAGS.contains(&name) {
+                    continue;
+                }
+                steps.push(Step::EndTag(name, out.len()));
+                let raw_text = is_html && is_raw_text(name);
+                let nodes = children.iter().chain(template_content.unwrap_or_default());
+                steps.extend(nodes.rev().map(|node| match node.part() {
+                    Part::Text(text) if raw_text => Step::RawText(text),
+                    part => Step::Part(part),
+                }));
+            }
+            Part::Text(text) if xml => out.push_str(&escape_xml(text, false)),
+            Part::Text(text) => out.push_str(&escape_text(text)),
+            Part::ConditionalComment { condition, body } => {
+                out.push_str(&format!("<!--[if {}]>", condition));
+                match body {
+                    Ok(nodes) => {
+                        steps.push(Step::EndConditional);
+                        steps.extend(nodes.iter().rev().map(|node| Step::Part(node.part())));
+                    }
+                    Err(raw) => out.push_str(&format!("{}<![endif]-->", raw)),
+                }
+            }
+            Part::Markup(markup) => out.push_str(&markup),
         }
     }
+    out
 }
 
-impl Htmlifiable for Node {
-    fn html(&self) -> String {
-        match self {
-            Node::Element(element) => element.html(),
-            Node::Text(text) => text.to_string(),
-            Node::Comment(comment) => format!("<!--{}-->", comment),
-            Node::Doctype(doctype) => match &doctype {
-                Doctype::Html => "<!DOCTYPE html>".to_string(),
-                Doctype::Xml { version, encoding } => {
-                    format!(r#"<?xml version="{}" encoding="{}"?>"#, version, encoding)
+fn start_tag(name: &str, attrs: Vec<(&str, &str, bool)>, xml: bool) -> String {
+    if xml {
+        let attrs: String = attrs
+            .into_iter()
+            .map(|(k, v, raw)| {
+                if raw {
+                    format!(" {}={}", k, quote(v))
+                } else {
+                    format!(r#" {}="{}""#, k, escape_xml(v, true))
                 }
-            },
+            })
+            .collect();
+        return format!("<{}{}>", name, attrs);
+    }
+    let attrs = attrs
+        .into_iter()
+        .map(|(k, v, raw)| {
+            if v.is_empty() {
+                k.to_string()
+            } else if raw {
+                format!("{}={}", k, quote(v))
+            } else {
+                format!(r#"{}="{}""#, k, escape_attr(v))
+            }
+        })
+        .collect::<Vec<_>>();
+    if attrs.is_empty() {
+        format!("<{}>", name)
+    } else {
+        format!("<{} {}>", name, attrs.join(" "))
+    }
+}
+
+fn doctype_html(doctype: &Doctype) -> String {
+    match doctype {
+        Doctype::Html => "<!DOCTYPE html>".to_string(),
+        Doctype::Dtd {
+            name,
+            public_id,
+            system_id,
+        } => {
+            let mut html = format!("<!DOCTYPE {}", name);
+            match (public_id, system_id) {
+                (Some(public_id), _) => {
+                    html.push_str(&format!(" PUBLIC {}", quote(public_id)));
+                }
+                (None, Some(_)) => html.push_str(" SYSTEM"),
+                (None, None) => {}
+            }
+            if let Some(system_id) = system_id {
+                html.push_str(&format!(" {}", quote(system_id)));
+            }
+            html.push('>');
+            html
         }
+        // The declaration as written, if it still has the same fields.
+        Doctype::Xml { raw: Some(raw), .. }
+            if parse_xml_declaration(raw).as_ref() == Some(doctype) =>
+        {
+            raw.to_string()
+        }
+        Doctype::Xml {
+            version,
+            encoding,
+            standalone,
+            ..
+        } => {
+            let mut html = format!(r#"<?xml version="{}""#, version);
+            if let Some(encoding) = encoding {
+                html.push_str(&format!(r#" encoding="{}""#, encoding));
+            }
+            if let Some(standalone) = standalone {
+                html.push_str(&format!(r#" standalone="{}""#, standalone));
+            }
+            html.push_str("?>");
+            html
+        }
+    }
+}
+
+fn processing_instruction_html(target: &str, data: &str) -> String {
+    // The data of the parsed html starts with the whitespace, and the
+    // other data is separated from the target by a space.
+    if data.is_empty() || data.starts_with(|ch: char| ch.is_ascii_whitespace()) {
+        format!("<?{}{}?>", target, data)
+    } else {
+        format!("<?{} {}?>", target, data)
+    }
+}
+
+/// Quote the identifier of a doctype or the attribute value written as
+/// it is, which cannot be escaped.
+fn quote(id: &str) -> String {
+    if id.contains('"') {
+        format!("'{}'", id)
+    } else {
+        format!(r#""{}""#, id)
     }
 }
 
 impl Htmlifiable for Vec<Node> {
     fn html(&self) -> String {
-        let mut html = String::new();
-        for node in self {
-            html.push_str(node.html().as_str());
-        }
-        html
+        write(self.iter().map(Markup::part), false)
     }
 }
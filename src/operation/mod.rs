@@ -4,8 +4,10 @@ mod edit;
 mod html;
 mod query;
 mod selector;
+mod xml;
 
 pub use edit::Editable;
 pub use html::Htmlifiable;
 pub use query::Queryable;
 pub use selector::Selector;
+pub use xml::Xmlifiable;
@@ -1,8 +1,16 @@
+use std::iter::once;
+use std::ops::Deref;
+
 use super::Selector;
-use crate::{Element, Node};
+use crate::{borrowed, Element, Node};
 
 /// Query the specific element(s) by [`Selector`].
 pub trait Queryable {
+    /// The type of the elements found, which is [`Element`] for the DOM
+    /// returned by [`parse()`](crate::parse), or [`borrowed::Element`]
+    /// for [`parse_borrowed()`](crate::parse_borrowed).
+    type Element;
+
     /// Query the node in `self` for the given selector.
     ///
     /// ```
@@ -24,7 +32,7 @@ pub trait Queryable {
     /// let selector: Selector = Selector::from("#app");
     /// let app: &Element = parse(html).unwrap().query(&selector).unwrap();
     /// ```
-    fn query(&self, selector: &Selector) -> Option<&Element>;
+    fn query(&self, selector: &Selector) -> Option<&Self::Element>;
 
     /// Query all the nodes in `self` for the given selector.
     ///
@@ -49,7 +57,7 @@ pub trait Queryable {
     /// let selector: Selector = Selector::from(".btn");
     /// let buttons: Vec<&Element> = parse(html).unwrap().query_all(&selector);
     /// ```
-    fn query_all(&self, selector: &Selector) -> Vec<&Element>;
+    fn query_all(&self, selector: &Selector) -> Vec<&Self::Element>;
 
     /// Query the node in `self` as mutable for the given selector.
     ///
@@ -72,73 +80,183 @@ pub trait Queryable {
     /// let selector: Selector = Selector::from("#app");
     /// let app: &mut Element = parse(html).unwrap().query_mut(&selector).unwrap();
     /// ```
-    fn query_mut(&mut self, selector: &Selector) -> Option<&mut Element>;
+    fn query_mut(&mut self, selector: &Selector) -> Option<&mut Self::Element>;
 }
 
 impl Queryable for Vec<Node> {
+    type Element = Element;
+
     fn query(&self, selector: &Selector) -> Option<&Element> {
-        for node in self {
-            if let Some(element) = node.as_element() {
-                if let Some(elem) = element.query(selector) {
-                    return Some(elem);
-                }
-            }
-        }
-        None
+        first(
+            self.iter().filter_map(Node::as_element),
+            |element| selector.matches(element),
+            |element| element.children.iter().filter_map(Node::as_element),
+        )
     }
 
     fn query_all(&self, selector: &Selector) -> Vec<&Element> {
-        let mut elements = Vec::new();
-        for node in self {
-            if let Some(element) = node.as_element() {
-                // Recursively traverse the descendants nodes
-                let sub_elements = element.query_all(selector);
-                elements.extend(sub_elements);
-            }
-        }
-        elements
+        all(
+            self.iter().filter_map(Node::as_element),
+            |element| selector.matches(element),
+            |element| element.children.iter().filter_map(Node::as_element),
+        )
     }
 
     fn query_mut(&mut self, selector: &Selector) -> Option<&mut Element> {
-        for node in self {
-            if let Some(element) = node.as_element_mut() {
-                if let Some(elem) = element.query_mut(selector) {
-                    return Some(elem);
-                }
-            }
-        }
-        None
+        first(
+            self.iter_mut().filter_map(Node::as_element_mut),
+            |element| selector.matches(element),
+            |element| element.children.iter_mut().filter_map(Node::as_element_mut),
+        )
     }
 }
 
 impl Queryable for Element {
+    type Element = Element;
+
     fn query(&self, selector: &Selector) -> Option<&Element> {
-        if selector.matches(self) {
-            Some(self)
+        first(
+            once(self),
+            |element| selector.matches(element),
+            |element| element.children.iter().filter_map(Node::as_element),
+        )
+    }
+
+    fn query_all(&self, selector: &Selector) -> Vec<&Element> {
+        all(
+            once(self),
+            |element| selector.matches(element),
+            |element| element.children.iter().filter_map(Node::as_element),
+        )
+    }
+
+    fn query_mut(&mut self, selector: &Selector) -> Option<&mut Element> {
+        first(
+            once(self),
+            |element| selector.matches(element),
+            |element| element.children.iter_mut().filter_map(Node::as_element_mut),
+        )
+    }
+}
+
+impl Queryable for Node {
+    type Element = Element;
+
+    fn query(&self, selector: &Selector) -> Option<&Element> {
+        if let Some(element) = self.as_element() {
+            element.query(selector)
         } else {
-            self.children.query(selector)
+            None
         }
     }
 
     fn query_all(&self, selector: &Selector) -> Vec<&Element> {
-        let mut elements = self.children.query_all(selector);
-        if selector.matches(self) {
-            elements.push(self);
+        if let Some(element) = self.as_element() {
+            element.query_all(selector)
+        } else {
+            Vec::new()
         }
-        elements
     }
 
     fn query_mut(&mut self, selector: &Selector) -> Option<&mut Element> {
-        if selector.matches(self) {
-            Some(self)
+        if let Some(element) = self.as_element_mut() {
+            element.query_mut(selector)
         } else {
-            self.children.query_mut(selector)
+            None
         }
     }
 }
 
-impl Queryable for Node {
-    fn query(&self, selector: &Selector) -> Option<&Element> {
+impl<'a> Queryable for Vec<borrowed::Node<'a>> {
+    type Element = borrowed::Element<'a>;
+
+    fn query(&self, selector: &Selector) -> Option<&borrowed::Element<'a>> {
+        first(
+            self.iter().filter_map(borrowed::Node::as_element),
+            |element| matches_borrowed(selector, element),
+            |element| {
+                element
+                    .children
+                    .iter()
+                    .filter_map(borrowed::Node::as_element)
+            },
+        )
+    }
+
+    fn query_all(&self, selector: &Selector) -> Vec<&borrowed::Element<'a>> {
+        all(
+            self.iter().filter_map(borrowed::Node::as_element),
+            |element| matches_borrowed(selector, element),
+            |element| {
+                element
+                    .children
+                    .iter()
+                    .filter_map(borrowed::Node::as_element)
+            },
+        )
+    }
+
+    fn query_mut(&mut self, selector: &Selector) -> Option<&mut borrowed::Element<'a>> {
+        first(
+            self.iter_mut().filter_map(borrowed::Node::as_element_mut),
+            |element| matches_borrowed(selector, element),
+            |element| {
+                element
+                    .children
+                    .iter_mut()
+                    .filter_map(borrowed::Node::as_element_mut)
+            },
+        )
+    }
+}
+
+impl<'a> Queryable for borrowed::Element<'a> {
+    type Element = borrowed::Element<'a>;
+
+    fn query(&self, selector: &Selector) -> Option<&borrowed::Element<'a>> {
+        first(
+            once(self),
+            |element| matches_borrowed(selector, element),
+            |element| {
+                element
+                    .children
+                    .iter()
+                    .filter_map(borrowed::Node::as_element)
+            },
+        )
+    }
+
+    fn query_all(&self, selector: &Selector) -> Vec<&borrowed::Element<'a>> {
+        all(
+            once(self),
+            |element| matches_borrowed(selector, element),
+            |element| {
+                element
+                    .children
+                    .iter()
+                    .filter_map(borrowed::Node::as_element)
+            },
+        )
+    }
+
+    fn query_mut(&mut self, selector: &Selector) -> Option<&mut borrowed::Element<'a>> {
+        first(
+            once(self),
+            |element| matches_borrowed(selector, element),
+            |element| {
+                element
+                    .children
+                    .iter_mut()
+                    .filter_map(borrowed::Node::as_element_mut)
+            },
+        )
+    }
+}
+
+impl<'a> Queryable for borrowed::Node<'a> {
+    type Element = borrowed::Element<'a>;
+
+    fn query(&self, selector: &Selector) -> Option<&borrowed::Element<'a>> {
         if let Some(element) = self.as_element() {
             element.query(selector)
         } else {
@@ -146,7 +264,7 @@ impl Queryable for Node {
         }
     }
 
-    fn query_all(&self, selector: &Selector) -> Vec<&Element> {
+    fn query_all(&self, selector: &Selector) -> Vec<&borrowed::Element<'a>> {
         if let Some(element) = self.as_element() {
             element.query_all(selector)
         } else {
@@ -154,7 +272,7 @@ impl Queryable for Node {
         }
     }
 
-    fn query_mut(&mut self, selector: &Selector) -> Option<&mut Element> {
+    fn query_mut(&mut self, selector: &Selector) -> Option<&mut borrowed::Element<'a>> {
         if let Some(element) = self.as_element_mut() {
             element.query_mut(selector)
         } else {
@@ -162,3 +280,70 @@ impl Queryable for Node {
         }
     }
 }
+
+/// Find the first element matching in the `elements` and their
+/// descendants, in the document order.
+///
+/// A stack is used instead of recursion, which would overflow the stack
+/// if the elements are nested too deeply. `R` is `&Element` or
+/// `&mut Element`.
+fn first<R, I>(
+    elements: impl DoubleEndedIterator<Item = R>,
+    matches: impl Fn(&R::Target) -> bool,
+    children: impl Fn(R) -> I,
+) -> Option<R>
+where
+    R: Deref,
+    I: DoubleEndedIterator<Item = R>,
+{
+    let mut stack: Vec<R> = elements.rev().collect();
+    while let Some(element) = stack.pop() {
+        if matches(&element) {
+            return Some(element);
+        }
+        stack.extend(children(element).rev());
+    }
+    None
+}
+
+/// Find all the elements matching in the `elements` and their
+/// descendants, with each element after its descendants, using a stack
+/// like [`first()`].
+fn all<'a, E, I>(
+    elements: impl Iterator<Item = &'a E>,
+    matches: impl Fn(&E) -> bool,
+    children: impl Fn(&'a E) -> I,
+) -> Vec<&'a E>
+where
+    I: Iterator<Item = &'a E>,
+{
+    let mut found = Vec::new();
+    let mut elements = elements;
+    // The elements whose descendants are being visited.
+    let mut stack: Vec<(&E, I)> = Vec::new();
+    loop {
+        let next = match stack.last_mut() {
+            Some((_, children)) => children.next(),
+            None => elements.next(),
+        };
+        if let Some(element) = next {
+            stack.push((element, children(element)));
+        } else if let Some((element, _)) = stack.pop() {
+            if matches(element) {
+                found.push(element);
+            }
+        } else {
+            return found;
+        }
+    }
+}
+
+fn matches_borrowed(selector: &Selector, element: &borrowed::Element) -> bool {
+    selector.matches_parts(&element.name, &element.namespace, |key| {
+        element
+            .attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_ref())
+    })
+}
@@ -1,6 +1,7 @@
 use std::vec;
 
 use super::simple::SimpleSelector;
+use crate::Namespace;
 
 /// A sequence of simple selectors that are not separated by a
 /// combinator. A compound selector represents a set of
@@ -16,19 +17,28 @@ impl From<&str> for CompoundSelector {
         let mut start = 0;
         let mut end;
 
-        while start < selector.len() {
-            end = selector[start + 1..]
-                .find(|c| c == '.' || c == '#')
-                .map(|n| n + start + 1)
+        while let Some(start_char) = selector[start..].chars().next() {
+            let name_start = start + start_char.len_utf8();
+            end = selector[name_start..]
+                .find(['.', '#'])
+                .map(|n| n + name_start)
                 .unwrap_or(selector.len());
-            let start_char = selector.chars().nth(start).unwrap();
 
             use SimpleSelector::*;
-            simple_selectors.push(match start_char {
-                '.' => Class(selector[start + 1..end].to_string()),
-                '#' => Id(selector[start + 1..end].to_string()),
-                _ => Tag(selector[start..end].to_string()),
-            });
+            let simple_selector = match start_char {
+                '.' => Class(selector[name_start..end].to_string()),
+                '#' => Id(selector[name_start..end].to_string()),
+                _ => match selector[start..end].split_once('|') {
+                    // `*|rect` matches the elements in any namespace.
+                    Some(("*", tag)) => Tag(tag.to_string()),
+                    Some((prefix, tag)) => {
+                        simple_selectors.push(Namespace(namespace(prefix)));
+                        Tag(tag.to_string())
+                    }
+                    None => Tag(selector[start..end].to_string()),
+                },
+            };
+            simple_selectors.push(simple_selector);
 
             start = end;
         }
@@ -36,3 +46,13 @@ impl From<&str> for CompoundSelector {
         CompoundSelector(simple_selectors)
     }
 }
+
+/// Get the namespace of the prefix in the selectors like `svg|rect`.
+fn namespace(prefix: &str) -> Option<Namespace> {
+    match prefix {
+        "html" => Some(Namespace::Html),
+        "svg" => Some(Namespace::Svg),
+        "math" => Some(Namespace::MathMl),
+        _ => None,
+    }
+}
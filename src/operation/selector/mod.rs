@@ -1,7 +1,7 @@
 mod compound;
 mod simple;
 
-use crate::Element;
+use crate::{Element, Namespace};
 
 use self::{compound::CompoundSelector, simple::SimpleSelector};
 
@@ -30,16 +30,27 @@ impl Selector {
     /// assert_eq!(selector.matches(&element), true);
     /// ```
     pub fn matches(&self, element: &Element) -> bool {
-        let element_classes = element
-            .attrs
-            .iter()
-            .find(|(key, _)| key == "class")
-            .map(|(_, v)| v.split(' ').map(|name| name.trim()).collect::<Vec<_>>());
-        let element_id = element
-            .attrs
-            .iter()
-            .find(|(key, _)| key == "id")
-            .map(|(_, v)| v);
+        self.matches_parts(&element.name, &element.namespace, |key| {
+            element
+                .attrs
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        })
+    }
+
+    /// Check if the element with the `name`, the `namespace` and the
+    /// attributes got by `attr` matches the `selector`, for the borrowed
+    /// elements too.
+    pub(crate) fn matches_parts<'a>(
+        &self,
+        name: &str,
+        namespace: &Namespace,
+        attr: impl Fn(&str) -> Option<&'a str>,
+    ) -> bool {
+        let element_classes =
+            attr("class").map(|v| v.split(' ').map(|name| name.trim()).collect::<Vec<_>>());
+        let element_id = attr("id");
 
         self.0.iter().any(|compound_selector| {
             compound_selector
@@ -56,7 +67,10 @@ impl Selector {
                         Some(element_id) => element_id == selector_id,
                         None => false,
                     },
-                    SimpleSelector::Tag(tag) => tag == &element.name,
+                    SimpleSelector::Tag(tag) => tag == "*" || tag == name,
+                    SimpleSelector::Namespace(selector_namespace) => {
+                        selector_namespace.as_ref() == Some(namespace)
+                    }
                 })
         })
     }
@@ -74,6 +88,10 @@ impl From<&str> for Selector {
     ///
     /// // Type Selector
     /// Selector::from("span");
+    /// // Universal selector
+    /// Selector::from("*");
+    /// // Type selector with a namespace: `html`, `svg`, `math` or `*`
+    /// Selector::from("svg|a");
     /// // Class selector
     /// Selector::from(".class");
     /// // ID selector
@@ -1,3 +1,5 @@
+use crate::Namespace;
+
 /// A selector with a single component, such as a single
 /// id selector or type selector, that's not used in combination
 /// with or contains any other selector component or combinator.
@@ -5,5 +7,9 @@
 pub enum SimpleSelector {
     Class(String),
     Id(String),
+    /// The tag name, or `*` for any element.
     Tag(String),
+    /// The namespace prefix like `svg` in `svg|rect`, which is `None` if
+    /// the prefix is unknown and matches nothing.
+    Namespace(Option<Namespace>),
 }
@@ -0,0 +1,62 @@
+use std::iter::once;
+
+use super::html::{borrowed_element_part, element_part, write, Markup};
+use crate::{borrowed, Element, Node};
+
+/// Stringify into XML.
+pub trait Xmlifiable {
+    /// Convert the object to XML string.
+    ///
+    /// Unlike [`Htmlifiable::html()`](super::Htmlifiable::html), the
+    /// elements without children are written like `<x/>`, every
+    /// attribute has a quoted value, and the text is always escaped, even
+    /// in `<script>`.
+    ///
+    /// ```
+    /// use html_editor::{parse_xml, operation::*};
+    ///
+    /// let xml = r#"<urlset><url><loc>https://example.com/?a=1&amp;b=2</loc><priority></priority></url></urlset>"#;
+    /// let dom = parse_xml(xml).unwrap();
+    /// assert_eq!(
+    ///     dom.xml(),
+    ///     r#"<urlset><url><loc>https://example.com/?a=1&amp;b=2</loc><priority/></url></urlset>"#
+    /// );
+    /// ```
+    fn xml(&self) -> String;
+}
+
+impl Xmlifiable for Element {
+    fn xml(&self) -> String {
+        write(once(element_part(self)), true)
+    }
+}
+
+impl Xmlifiable for Node {
+    fn xml(&self) -> String {
+        write(once(self.part()), true)
+    }
+}
+
+impl Xmlifiable for Vec<Node> {
+    fn xml(&self) -> String {
+        write(self.iter().map(Markup::part), true)
+    }
+}
+
+impl Xmlifiable for borrowed::Element<'_> {
+    fn xml(&self) -> String {
+        write(once(borrowed_element_part(self)), true)
+    }
+}
+
+impl Xmlifiable for borrowed::Node<'_> {
+    fn xml(&self) -> String {
+        write(once(self.part()), true)
+    }
+}
+
+impl Xmlifiable for Vec<borrowed::Node<'_>> {
+    fn xml(&self) -> String {
+        write(self.iter().map(Markup::part), true)
+    }
+}
@@ -7,279 +7,115 @@
 // If you have any idea, feel free to post an issue.
 
 mod attrs;
+mod builder;
+mod error;
+mod event;
+mod foreign;
+mod implied;
+mod options;
+mod parser;
 mod token;
+mod tokenizer;
+mod tree;
 
-use crate::{data::VOID_TAGS, Element, Node};
-use token::Token;
+use std::borrow::Cow;
 
-fn html_to_stack(html: &str) -> Result<Vec<Token>, String> {
-    let mut chars_stack = Vec::<char>::new();
-    let mut token_stack = Vec::<Token>::new();
-    let mut in_quotes: Option<char> = None;
-    // More precisely: is in angle brackets
-    let mut in_brackets = false;
-    let mut in_comment = false;
-    let mut in_script = false;
-    let mut in_style = false;
-    for ch in html.chars() {
-        if let Some(quote) = in_quotes {
-            if ch == quote {
-                let previous_char = *chars_stack
-                    .last()
-                    .expect("cannot get the last char in chars stack");
-                if previous_char != '\\' {
-                    in_quotes = None;
-                }
-            }
-            chars_stack.push(ch);
-        } else if in_comment {
-            chars_stack.push(ch);
+use crate::{borrowed, Element, Encoding, Node};
+use builder::{BorrowedDom, Dom, DomBuilder, OwnedDom};
+pub(crate) use token::parse_xml_declaration;
+use token::{Located, Token};
+use tokenizer::Tokens;
 
-            if ends_with(&chars_stack, &['-', '-', '>']) {
-                let comment = String::from_iter(chars_stack);
-                chars_stack = Vec::new();
-                token_stack.push(Token::from_comment(comment));
-                in_comment = false;
-                in_brackets = false;
-            }
-        } else if in_script {
-            chars_stack.push(ch);
-            let len = chars_stack.len();
+pub use error::{ParseError, Position};
+pub use event::{events, events_with, Event, Events};
+pub use options::{DuplicateAttrs, Limit, Limits, NameCase, ParseOptions};
+pub use parser::Parser;
 
-            if ends_with(&chars_stack, &['<', '/', 's', 'c', 'r', 'i', 'p', 't', '>']) {
-                let script = String::from_iter(chars_stack[..len - 9].to_vec());
-                chars_stack = Vec::new();
-                token_stack.push(Token::Text(script));
-                token_stack.push(Token::End("script".to_string()));
-                in_script = false;
-            }
-        } else if in_style {
-            chars_stack.push(ch);
-            let len = chars_stack.len();
-
-            if ends_with(&chars_stack, &['<', '/', 's', 't', 'y', 'l', 'e', '>']) {
-                let style = String::from_iter(chars_stack[..len - 8].to_vec());
-                chars_stack = Vec::new();
-                token_stack.push(Token::Text(style));
-                token_stack.push(Token::End("style".to_string()));
-                in_style = false;
-            }
-        } else {
-            match ch {
-                '<' => {
-                    in_brackets = true;
-                    // In case of pushing empty text tokens to the stack
-                    if !chars_stack.is_empty() {
-                        // Turn the chars in `chars_stack` into `String`
-                        // and clean the chars stack.
-                        let txt_text = String::from_iter(chars_stack);
-                        chars_stack = Vec::new();
-                        // Push the text we just got to the token stack.
-                        token_stack.push(Token::Text(txt_text));
-                    }
-                    chars_stack.push(ch);
-                }
-                '>' => {
-                    in_brackets = false;
-                    chars_stack.push(ch);
-                    // Turn the chars in `chars_stack` in to `String`
-                    // and clean the chars stack.
-                    let tag_text = String::from_iter(chars_stack);
-                    chars_stack = Vec::new();
-                    // Push the tag with the text we just got to the token stack.
-                    let tag = Token::from(tag_text.clone())?;
-                    token_stack.push(tag.clone());
-                    // Handle special tags
-                    if let Token::Start(tag_name, _) = tag {
-                        let tag_name = tag_name.as_str();
-                        match tag_name {
-                            "script" => in_script = true,
-                            "style" => in_style = true,
-                            _ => {}
-                        }
-                    }
-                }
-                '-' => {
-                    chars_stack.push(ch);
-                    if chars_stack == ['<', '!', '-', '-'] {
-                        in_comment = true;
-                    }
-                }
-                _ => {
-                    if in_brackets {
-                        match ch {
-                            '\'' => in_quotes = Some('\''),
-                            '\"' => in_quotes = Some('\"'),
-                            _ => {}
-                        }
-                    }
-                    chars_stack.push(ch)
-                }
-            }
-        }
-    }
-    if !chars_stack.is_empty() {
-        let text = String::from_iter(chars_stack);
-        token_stack.push(Token::Text(text));
-    }
-    Ok(token_stack)
+fn html_to_stack<'a>(
+    html: &'a str,
+    options: &ParseOptions,
+) -> Result<Vec<Located<'a>>, ParseError> {
+    Tokens::new(html, options, false).collect()
 }
 
-fn stack_to_dom(token_stack: Vec<Token>) -> Result<Vec<Node>, String> {
-    let mut nodes: Vec<Node> = Vec::new();
-    let mut start_tags_stack: Vec<Token> = Vec::new();
-    let mut start_tag_index = 0;
-    for (i, token) in token_stack.iter().enumerate() {
-        match token {
-            Token::Start(tag, attrs) => {
-                let is_void_tag = VOID_TAGS.contains(&tag.as_str());
-                if is_void_tag {
-                    if start_tags_stack.is_empty() {
-                        nodes.push(
-                            Element {
-                                name: tag.clone(),
-                                attrs: attrs.clone(),
-                                children: Vec::new(),
-                            }
-                            .into_node(),
-                        );
-                    } else {
-                        // You do not need to push the void tag to the stack
-                        // like above, because it must be inside the the
-                        // element of the first start tag, and this element
-                        // will then be pushed to the stack recursively.
-                    }
-                } else {
-                    if start_tags_stack.is_empty() {
-                        start_tag_index = i;
-                    }
-                    start_tags_stack.push(Token::Start(tag.clone(), attrs.clone()));
-                }
-            }
-            Token::End(tag) => {
-                let start_tag = match start_tags_stack.pop() {
-                    Some(token) => token.into_element(),
-                    None => return Err(format!("No start tag matches </{}>", tag)),
-                };
-                if start_tags_stack.is_empty() {
-                    if tag != &start_tag.name {
-                        return Err(format!(
-                            "<{}> does not match the </{}>",
-                            start_tag.name, tag
-                        ));
-                    }
-                    nodes.push(
-                        Element {
-                            name: start_tag.name,
-                            attrs: start_tag.attrs,
-                            children: stack_to_dom(token_stack[start_tag_index + 1..i].to_vec())?,
-                        }
-                        .into_node(),
-                    )
-                }
-            }
-            _ => {
-                if start_tags_stack.is_empty() {
-                    nodes.push(token.node());
-                }
-            }
-        }
+/// Build the DOM from the tokens of `html` in a single pass, with a
+/// stack of the open elements instead of recursion, so that it takes
+/// linear time and never overflows the stack however deep the html is.
+fn build<'a, D: Dom<'a>>(
+    dom: D,
+    html: &'a str,
+    options: &ParseOptions,
+) -> Result<Vec<D::Node>, ParseError> {
+    let mut builder = DomBuilder::new(dom, false).with_options(options);
+    for token in html_to_stack(html, options)? {
+        builder.push(token)?;
     }
+    builder.finish()
+}
 
-    match start_tags_stack.pop() {
-        Some(token) => {
-            let start_tag_name = token.into_element().name;
-            Err(format!("<{}> is not closed", start_tag_name))
-        }
-        None => Ok(nodes),
+/// Alternative for [`build()`] which fixes the illegal html instead of
+/// returning errors.
+fn try_build<'a, D: Dom<'a>>(dom: D, html: &'a str, options: &ParseOptions) -> Vec<D::Node> {
+    let mut builder = DomBuilder::new(dom, true).with_options(options);
+    // The fault tolerant tokenizer and builder never return errors.
+    for token in Tokens::new(html, options, true).flatten() {
+        let _ = builder.push(token);
     }
+    builder.finish().unwrap_or_default()
 }
 
-fn try_stack_to_dom(token_stack: Vec<Token>) -> Vec<Node> {
-    let mut nodes: Vec<Node> = Vec::new();
-    let mut start_tags_stack: Vec<Token> = Vec::new();
-    let mut start_tag_index = 0;
-
-    for (i, token) in token_stack.iter().enumerate() {
-        match token {
-            Token::Start(tag, attrs) => {
-                let is_void_tag = VOID_TAGS.contains(&tag.as_str());
-                if is_void_tag {
-                    if start_tags_stack.is_empty() {
-                        nodes.push(
-                            Element {
-                                name: tag.clone(),
-                                attrs: attrs.clone(),
-                                children: Vec::new(),
-                            }
-                            .into_node(),
-                        );
-                    } else {
-                        // You do not need to push the void tag to the stack
-                        // like above, because it must be inside the the
-                        // element of the first start tag, and this element
-                        // will then be pushed to the stack recursively.
-                    }
-                } else {
-                    if start_tags_stack.is_empty() {
-                        start_tag_index = i;
-                    }
-                    start_tags_stack.push(Token::Start(tag.clone(), attrs.clone()));
-                }
-            }
-            Token::End(tag) => {
-                let start_tag = match start_tags_stack.pop() {
-                    Some(token) => token.into_element(),
-                    // It means the end tag is redundant, so we will omit
-                    // it and just start the next loop.
-                    None => continue,
-                };
-
-                if tag != &start_tag.name {
-                    // The tags do not match, so let's put it back to
-                    // pretend we never come here and then continue
-                    // the next loop.
-                    start_tags_stack.push(Token::Start(start_tag.name, start_tag.attrs));
-                    continue;
-                }
+/// The nodes in the body of a conditional comment, with their count.
+type Body<N> = (Vec<N>, usize);
 
-                if start_tags_stack.is_empty() {
-                    nodes.push(
-                        Element {
-                            name: start_tag.name,
-                            attrs: start_tag.attrs,
-                            children: try_stack_to_dom(
-                                token_stack[start_tag_index + 1..i].to_vec(),
-                            ),
-                        }
-                        .into_node(),
-                    )
-                }
-            }
-            _ => {
-                if start_tags_stack.is_empty() {
-                    nodes.push(token.node());
-                }
-            }
-        }
-    }
-
-    if !start_tags_stack.is_empty() {
-        if let Token::Start(name, attrs) = start_tags_stack[0].clone() {
-            nodes.push(Node::Element(Element {
-                name,
-                attrs,
-                children: try_stack_to_dom(token_stack[start_tag_index + 1..].to_vec()),
-            }));
-        } else {
-            unreachable!()
-        }
+/// Parse the body of the conditional comment with the `options` of the
+/// html around it, and get the nodes with their count, or `None` if it
+/// is not a conditional comment or its body is not well-formed alone.
+///
+/// The body is only an error if it exceeds the `limits` left by the html
+/// around it.
+fn parse_body<'a, D: Dom<'a>>(
+    dom: D,
+    located: &Located<'a>,
+    options: &ParseOptions,
+    limits: Limits,
+) -> Result<Option<Body<D::Node>>, ParseError> {
+    let Token::Comment(comment) = &located.token else {
+        return Ok(None);
+    };
+    let Some((_, body)) = token::split_conditional(comment) else {
+        return Ok(None);
+    };
+    let start = token::body_start(located.start, comment, body.start);
+    let options = ParseOptions {
+        limits,
+        ..options.clone()
+    };
+    let mut builder = DomBuilder::new(dom, false).with_options(&options);
+    // The body borrows from the source like the comment, unless the
+    // comment is copied.
+    let result = match comment {
+        Cow::Borrowed(comment) => Tokens::new(&comment[body], &options, false)
+            .starting_at(start)
+            .try_for_each(|token| builder.push(token?)),
+        Cow::Owned(comment) => Tokens::new(&comment[body], &options, false)
+            .starting_at(start)
+            .try_for_each(|token| builder.push(token?.into_owned())),
+    };
+    let node_count = builder.node_count();
+    match result.and_then(|()| builder.finish()) {
+        Ok(nodes) => Ok(Some((nodes, node_count))),
+        Err(error @ ParseError::LimitExceeded { .. }) => Err(error),
+        Err(_) => Ok(None),
     }
-    nodes
 }
 
 /// Parse the html string and return a `Vector` of `Node`.
 ///
+/// Like browsers, a `<` only starts a tag if it is followed by an ASCII
+/// letter, `/`, `!` or `?`, so the `<` and `>` in `if a < b and c > d`
+/// are text. A `<` in a tag is a part of it, like in `<img alt=1<2>`.
+/// Only the name of an end tag matters, so `</div/>` is `</div>`.
+///
 /// Example:
 /// ```
 /// use html_editor::parse;
@@ -328,18 +164,41 @@ fn try_stack_to_dom(token_stack: Vec<Token>) -> Vec<Node> {
 ///     },
 /// ]
 /// ```
-pub fn parse(html: &str) -> Result<Vec<Node>, String> {
-    let stack = html_to_stack(html)?;
+pub fn parse(html: &str) -> Result<Vec<Node>, ParseError> {
+    parse_with(html, &ParseOptions::default())
+}
 
-    stack_to_dom(stack)
+/// Alternative for [`parse()`](parse) with the [`ParseOptions`].
+///
+/// ```
+/// use html_editor::{parse_with, NameCase, ParseOptions};
+/// use html_editor::operation::*;
+///
+/// let options = ParseOptions {
+///     name_case: NameCase::Html,
+///     ..Default::default()
+/// };
+/// let dom = parse_with("<P>Hello<BR>World</P>", &options).unwrap();
+/// assert_eq!(dom.html(), "<p>Hello<br>World</p>");
+/// ```
+pub fn parse_with(html: &str, options: &ParseOptions) -> Result<Vec<Node>, ParseError> {
+    build(OwnedDom, html, options)
 }
 
 /// Alternative for [`parse()`](parse) with fault tolerance
 /// feature.
 ///
-/// Whatever the input is, it will try to return a vector of nodes
-/// without errors. It can parse some illegal html code like `<div><a>Ipsum`
-/// or `<div>Ipsum</a>`.
+/// Whatever the input is, it will return a vector of nodes without
+/// errors or panics. It fixes the illegal html code like browsers do:
+///
+/// - The unclosed elements like `<div><a>Ipsum` are closed at the end of
+///   their parents.
+/// - The end tags which close nothing like the `</a>` in
+///   `<div>Ipsum</a>` are ignored, except that `</p>` and `</br>` become
+///   `<p></p>` and `<br>`.
+/// - The tag with an unterminated quoted attribute value like
+///   `<a href="x>y` is dropped, as the value would take the rest of the
+///   html, and the missing value like `<a href=>` is empty.
 ///
 /// But we still suggest you to use [`parse()`](parse) unless neccessary for better
 /// error handling.
@@ -351,19 +210,297 @@ pub fn parse(html: &str) -> Result<Vec<Node>, String> {
 /// assert_eq!(result, "<div><a>Ipsum</a></div>");
 /// ```
 pub fn try_parse(html: &str) -> Vec<Node> {
-    let stack = html_to_stack(html).unwrap_or_default();
+    try_parse_with(html, &ParseOptions::default())
+}
 
-    try_stack_to_dom(stack)
+/// Alternative for [`try_parse()`](try_parse) with the [`ParseOptions`].
+pub fn try_parse_with(html: &str, options: &ParseOptions) -> Vec<Node> {
+    try_build(OwnedDom, html, options)
 }
 
-// Use `&[char]` instead of `&str` to improve performance.
-fn ends_with(chars: &Vec<char>, end: &[char]) -> bool {
-    let chars_len = chars.len();
-    let end_len = end.len();
-    for i in 1..(end_len + 1) {
-        if chars[chars_len - i] != end[end.len() - i] {
-            return false;
+/// Alternative for [`try_parse()`](try_parse) which also returns how the
+/// illegal html is fixed, in the order of the positions.
+///
+/// Each fix is described by the [`ParseError`] that [`parse()`](parse)
+/// would return for it:
+///
+/// - [`ParseError::UnclosedTag`] for the element closed implicitly by a
+///   start tag or the end of the html.
+/// - [`ParseError::MismatchedEndTag`] for the element closed implicitly
+///   by the end tag of its ancestor.
+/// - [`ParseError::StrayEndTag`] for the end tag which closes nothing.
+/// - [`ParseError::InvalidTag`] for the `</` without a tag name after it,
+///   like `</ p>` which is a bogus comment.
+/// - [`ParseError::MalformedAttribute`] and
+///   [`ParseError::DuplicateAttribute`] for the fixed attributes.
+///
+/// The elements whose end tags can be omitted, like `<li>` and `<p>`,
+/// are closed implicitly without diagnostics.
+///
+/// ```
+/// use html_editor::{try_parse_with_diagnostics, operation::Htmlifiable};
+///
+/// let (dom, diagnostics) = try_parse_with_diagnostics("<div><a href=>Ipsum</div></p>");
+/// assert_eq!(dom.html(), "<div><a href>Ipsum</a></div><p></p>");
+/// let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+/// assert_eq!(
+///     messages,
+///     [
+///         "Missing value of attribute `href` in <a href=> at 1:6",
+///         "<a> does not match the </div> at 1:20",
+///         "No start tag matches </p> at 1:26",
+///     ]
+/// );
+/// ```
+pub fn try_parse_with_diagnostics(html: &str) -> (Vec<Node>, Vec<ParseError>) {
+    try_parse_with_options_and_diagnostics(html, &ParseOptions::default())
+}
+
+/// Alternative for [`try_parse_with_diagnostics()`] with the
+/// [`ParseOptions`], which also reports the input truncated by the
+/// [`Limits`] as [`ParseError::LimitExceeded`].
+///
+/// ```
+/// use html_editor::{try_parse_with_options_and_diagnostics, Limits, ParseOptions};
+/// use html_editor::operation::*;
+///
+/// let options = ParseOptions {
+///     limits: Limits {
+///         max_nodes: Some(3),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// let html = "<ul><li>1</li><li>2</li></ul>";
+/// let (dom, diagnostics) = try_parse_with_options_and_diagnostics(html, &options);
+/// assert_eq!(dom.html(), "<ul><li>1</li></ul>");
+/// let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+/// assert_eq!(
+///     messages,
+///     [
+///         "<ul> is not closed at 1:1",
+///         "Node count exceeds the limit of 3 at 1:15",
+///     ]
+/// );
+/// ```
+pub fn try_parse_with_options_and_diagnostics(
+    html: &str,
+    options: &ParseOptions,
+) -> (Vec<Node>, Vec<ParseError>) {
+    let mut builder = DomBuilder::new(OwnedDom, true)
+        .with_options(options)
+        .with_diagnostics();
+    let mut tokens = Tokens::new(html, options, true).with_diagnostics();
+    for token in tokens.by_ref().flatten() {
+        let _ = builder.push(token);
+    }
+    let mut diagnostics = tokens.take_diagnostics();
+    let (nodes, fixed) = builder.finish_with_diagnostics();
+    diagnostics.extend(fixed);
+    diagnostics.sort_by_key(|error| error.position().offset);
+    (nodes, diagnostics)
+}
+
+/// Alternative for [`parse()`](parse) returning the
+/// [borrowed DOM](crate::borrowed), whose names, attributes and text
+/// point into `html` instead of being copied.
+///
+/// ```
+/// use html_editor::{parse_borrowed, operation::*};
+///
+/// let html = r#"<ul><li class="done">Eat</li><li>Sleep</li></ul>"#;
+/// let dom = parse_borrowed(html).unwrap();
+/// let done = dom.query(&Selector::from(".done")).unwrap();
+/// assert_eq!(done.html(), r#"<li class="done">Eat</li>"#);
+/// ```
+pub fn parse_borrowed(html: &str) -> Result<Vec<borrowed::Node<'_>>, ParseError> {
+    build(BorrowedDom, html, &ParseOptions::default())
+}
+
+/// Alternative for [`try_parse()`](try_parse) returning the
+/// [borrowed DOM](crate::borrowed).
+pub fn try_parse_borrowed(html: &str) -> Vec<borrowed::Node<'_>> {
+    try_build(BorrowedDom, html, &ParseOptions::default())
+}
+
+/// Parse the html bytes in the encoding detected by
+/// [`Encoding::sniff()`], and return the nodes with the encoding.
+///
+/// The spans are the byte offsets in the decoded html rather than in
+/// `bytes`. To fix the illegal html instead, decode the bytes by
+/// [`Encoding::decode()`] and parse them by [`try_parse()`](try_parse).
+///
+/// ```
+/// use html_editor::{parse_bytes, operation::Htmlifiable, Encoding};
+///
+/// let html = b"<meta charset=windows-1252><p>Caf\xE9 \x80 5</p>";
+/// let (dom, encoding) = parse_bytes(html).unwrap();
+/// assert_eq!(encoding, Encoding::Windows1252);
+/// assert_eq!(dom.html(), r#"<meta charset="windows-1252"><p>Café € 5</p>"#);
+/// ```
+pub fn parse_bytes(bytes: &[u8]) -> Result<(Vec<Node>, Encoding), ParseError> {
+    let encoding = Encoding::sniff(bytes);
+    let nodes = parse(&encoding.decode(bytes))?;
+    Ok((nodes, encoding))
+}
+
+/// Parse the well-formed XML, like RSS feeds, sitemaps and SVG files.
+///
+/// Unlike [`parse()`](parse), every tag is generic: no element is void
+/// or has raw text content, `<x/>` closes any element, and the names are
+/// case-sensitive. The namespace of each element is resolved from the
+/// `xmlns` and `xmlns:prefix` attributes in scope.
+///
+/// Only the five predefined entities of XML and the numeric character
+/// references are decoded.
+///
+/// It returns [`ParseError::NotWellFormed`] for the unquoted attribute
+/// values, the unescaped `<` and `&`, the undefined entities, the
+/// undeclared namespace prefixes, and the document which does not have
+/// exactly one root element, besides the errors of `parse()`. The
+/// duplicate attributes are errors too.
+///
+/// ```
+/// use html_editor::{parse_xml, operation::*, Namespace};
+///
+/// let xml = r#"<rss xmlns:atom="http://www.w3.org/2005/Atom"><channel>
+///     <link>https://example.com</link>
+///     <atom:link href="https://example.com/rss"/>
+/// </channel></rss>"#;
+/// let dom = parse_xml(xml).unwrap();
+/// let link = dom.query(&Selector::from("link")).unwrap();
+/// assert_eq!(link.html(), "<link>https://example.com</link>");
+/// let atom = dom.query(&Selector::from("atom:link")).unwrap();
+/// assert_eq!(atom.namespace.url(), "http://www.w3.org/2005/Atom");
+/// assert_eq!(atom.xml(), r#"<atom:link href="https://example.com/rss"/>"#);
+///
+/// assert!(parse_xml("<a><b></a>").is_err());
+/// assert!(parse_xml("<a x=1/>").is_err());
+/// assert!(parse_xml("<a>&nbsp;</a>").is_err());
+/// ```
+pub fn parse_xml(xml: &str) -> Result<Vec<Node>, ParseError> {
+    let options = ParseOptions {
+        name_case: NameCase::Preserve,
+        duplicate_attrs: DuplicateAttrs::Error,
+        ..Default::default()
+    };
+    let mut builder = DomBuilder::new(OwnedDom, false).xml();
+    let mut depth = 0usize;
+    let mut has_root = false;
+    for token in Tokens::new(xml, &options, false).xml() {
+        let token = token?;
+        let not_well_formed = |reason: String| ParseError::NotWellFormed {
+            reason,
+            position: token.start,
+        };
+        // Only one element is at the top level, with the whitespace,
+        // comments, processing instructions and doctypes around it.
+        match &token.token {
+            Token::Start(name, ..) | Token::Closing(name, ..) if depth == 0 => {
+                if has_root {
+                    return Err(not_well_formed(format!(
+                        "Root element is followed by <{}>",
+                        name
+                    )));
+                }
+                has_root = true;
+            }
+            Token::Text(text) if depth == 0 && !text.trim().is_empty() => {
+                return Err(not_well_formed(
+                    "Text outside of the root element".to_string(),
+                ));
+            }
+            Token::CData(_) if depth == 0 => {
+                return Err(not_well_formed(
+                    "CDATA section outside of the root element".to_string(),
+                ));
+            }
+            _ => {}
         }
+        match &token.token {
+            Token::Start(..) => depth += 1,
+            Token::End(..) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        builder.push(token)?;
+    }
+    let nodes = builder.finish()?;
+    if !has_root {
+        return Err(ParseError::NotWellFormed {
+            reason: "No root element".to_string(),
+            position: Position::start(),
+        });
+    }
+    Ok(nodes)
+}
+
+/// Parse the html as a whole document following the tree construction
+/// of the [HTML standard](https://html.spec.whatwg.org/multipage/parsing.html#tree-construction),
+/// like browsers do.
+///
+/// Unlike [`try_parse()`](try_parse), the result always has the `html`,
+/// `head` and `body` elements, the misnested formatting elements are
+/// fixed by the adoption agency algorithm, and the content misplaced in
+/// tables is moved before them.
+///
+/// The names of the tags and attributes are lowercased like
+/// [`NameCase::Html`].
+///
+/// ```
+/// use html_editor::{parse_document, operation::Htmlifiable};
+///
+/// let result: String = parse_document("<title>Hi</title><b><i>a</b>b</i>").html();
+/// assert_eq!(
+///     result,
+///     "<html><head><title>Hi</title></head><body><b><i>a</i></b><i>b</i></body></html>"
+/// );
+/// ```
+pub fn parse_document(html: &str) -> Vec<Node> {
+    let options = ParseOptions {
+        name_case: NameCase::Html,
+        ..Default::default()
+    };
+    let mut builder = tree::TreeBuilder::new(&options);
+    for token in Tokens::new(html, &options, true).flatten() {
+        builder.push(token);
+    }
+    builder.finish()
+}
+
+/// Parse the html as the content of the `context` element, like setting
+/// its `innerHTML` in browsers, with the same tree construction as
+/// [`parse_document()`](parse_document).
+///
+/// So the rows are kept in a `tbody`, the tags other than `option` and
+/// `optgroup` are dropped in a `select`, and the content of a `textarea`
+/// or `script` is text. Only the name and attributes of `context` are
+/// used, and it is not in the result.
+///
+/// ```
+/// use html_editor::{parse_fragment, operation::Htmlifiable, Element};
+///
+/// let tbody = Element::new("tbody", vec![], vec![]);
+/// let rows = parse_fragment(&tbody, "<tr><td>a<td>b<tr><td>c");
+/// assert_eq!(
+///     rows.html(),
+///     "<tr><td>a</td><td>b</td></tr><tr><td>c</td></tr>"
+/// );
+///
+/// let textarea = Element::new("textarea", vec![], vec![]);
+/// let text = parse_fragment(&textarea, "<b>a &amp; b</b>");
+/// assert_eq!(text.html(), "&lt;b&gt;a &amp; b&lt;/b&gt;");
+/// ```
+pub fn parse_fragment(context: &Element, html: &str) -> Vec<Node> {
+    let options = ParseOptions {
+        name_case: NameCase::Html,
+        ..Default::default()
+    };
+    let mut builder = tree::TreeBuilder::fragment(context, &options);
+    for token in Tokens::new(html, &options, true)
+        .in_context(context)
+        .flatten()
+    {
+        builder.push(token);
     }
-    true
+    builder.finish()
 }
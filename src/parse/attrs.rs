@@ -1,126 +1,305 @@
+//! Tokenize the attributes of a tag, following the attribute states of
+//! the [HTML standard](https://html.spec.whatwg.org/multipage/parsing.html#before-attribute-name-state).
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use super::DuplicateAttrs;
+use crate::{escape::decode, Span};
+
+/// Attributes and the span of each attribute.
+pub type Attrs<'a> = (Vec<(Cow<'a, str>, Cow<'a, str>)>, Vec<Span>);
+
 // Let's take `<img src="example.png" alt=image>` for example.
 enum AttrPos {
+    /// Includes ` ` before `src` and `alt`
+    BeforeKey,
     /// Includes `src`, `alt`
     Key,
-    /// Includes `=`
-    Equal,
+    /// Includes the whitespace between the key and `=`
+    AfterKey,
+    /// Includes `=` and the whitespace after it
+    BeforeValue,
     /// Includes `example.png`, `image`
     Value(Option<char>),
-    /// Includes ` `
-    Space,
+}
+
+/// The attribute being read, whose key and value are the ranges of
+/// `attr_str`.
+#[derive(Default)]
+struct Attr {
+    key: Range<usize>,
+    value: Range<usize>,
+    start: usize,
+    end: usize,
+    // Whether it has a region of the template syntax out of its value,
+    // in which case it is kept as written
+    opaque: bool,
+    // Whether its value has a region of the template syntax, in which
+    // case the value is kept as written
+    raw: bool,
 }
 
 /// Valid `attr_str` like: `src="example.png" alt=example disabled`
-pub fn parse(attr_str: String) -> Vec<(String, String)> {
-    let mut chars_stack: Vec<char> = Vec::new();
-    let mut key_stack: Vec<String> = Vec::new();
-    let mut value_stack: Vec<String> = Vec::new();
-    let mut attr_pos = AttrPos::Key;
-    for ch in attr_str.chars() {
+///
+/// The attributes are separated by any ASCII whitespace, and kept in
+/// the source order. Backslashes are not escapes in HTML, so `"a\"` is a
+/// complete quoted value.
+///
+/// `offset` is the byte offset of `attr_str` in the source, which is
+/// used to compute the span of each attribute.
+///
+/// The value with a region between the `passthrough` delimiters, like
+/// `href="/{{ id }}"`, is kept as written without decoding the character
+/// references in it. An attribute with a region elsewhere, like
+/// `{% if x %}checked{% endif %}`, is an opaque fragment until the
+/// whitespace outside of the quotes and the regions. It is kept as
+/// written in the key, and its value is empty.
+///
+/// The keys and values borrow from `attr_str`, unless the values have
+/// character references to decode.
+///
+/// Returns the reason too if the attributes are malformed, in which case
+/// they are fixed like browsers do: the unterminated quoted value takes
+/// the rest of `attr_str`, and the missing value is empty.
+pub fn parse<'a>(
+    attr_str: &'a str,
+    offset: usize,
+    passthrough: &[(String, String)],
+) -> (Attrs<'a>, Option<String>) {
+    let mut attrs = Vec::new();
+    let mut spans = Vec::new();
+    let mut attr = Attr::default();
+    let mut attr_pos = AttrPos::BeforeKey;
+    let mut push = |attr: &mut Attr| {
+        let attr = std::mem::take(attr);
+        let key = Cow::Borrowed(&attr_str[attr.key]);
+        let value = &attr_str[attr.value];
+        if attr.opaque {
+            let fragment = &attr_str[attr.start..attr.end];
+            attrs.push((Cow::Borrowed(fragment), Cow::Borrowed("")));
+        } else if attr.raw {
+            attrs.push((key, Cow::Borrowed(value)));
+        } else {
+            attrs.push((key, decode(value, true)));
+        }
+        spans.push(Span::new(attr.start + offset, attr.end + offset));
+    };
+    // The end of the region being skipped
+    let mut skip_to = 0;
+    for (i, ch) in attr_str.char_indices() {
+        let end = i + ch.len_utf8();
+        if i < skip_to {
+            continue;
+        }
+        // The regions are found like the tokenizer does, which is when
+        // their start delimiters are completed.
+        let region = passthrough.iter().find_map(|(start, close)| {
+            let region_start = attr_str[..end].strip_suffix(start.as_str())?.len();
+            let region_end = attr_str[end..]
+                .find(close.as_str())
+                .map_or(attr_str.len(), |index| end + index + close.len());
+            Some((region_start, region_end))
+        });
+        if let Some((region_start, region_end)) = region {
+            if let (AttrPos::BeforeValue | AttrPos::Value(_), false) = (&attr_pos, attr.opaque) {
+                // The chars of the start delimiter before `ch` are in
+                // the value already.
+                if let AttrPos::BeforeValue = attr_pos {
+                    attr_pos = AttrPos::Value(None);
+                }
+                extend(&mut attr.value, i..region_end);
+                attr.raw = true;
+                attr.end = region_end;
+                skip_to = region_end;
+                continue;
+            }
+            match attr_pos {
+                AttrPos::BeforeKey | AttrPos::AfterKey => {
+                    if let AttrPos::AfterKey = attr_pos {
+                        push(&mut attr);
+                    }
+                    attr = Attr {
+                        start: region_start,
+                        ..Default::default()
+                    };
+                    attr_pos = AttrPos::Key;
+                }
+                AttrPos::BeforeValue => attr_pos = AttrPos::Value(None),
+                _ => {}
+            }
+            attr.start = attr.start.min(region_start);
+            attr.opaque = true;
+            attr.end = region_end;
+            skip_to = region_end;
+            continue;
+        }
         match attr_pos {
+            AttrPos::BeforeKey => {
+                if !ch.is_ascii_whitespace() && ch != '/' {
+                    attr = Attr {
+                        key: i..end,
+                        start: i,
+                        end,
+                        ..Default::default()
+                    };
+                    attr_pos = AttrPos::Key;
+                }
+            }
             AttrPos::Key => match ch {
-                '=' => {
-                    attr_pos = AttrPos::Equal;
-                    let key = String::from_iter(chars_stack);
-                    chars_stack = Vec::new();
-                    key_stack.push(key)
+                '=' => attr_pos = AttrPos::BeforeValue,
+                '/' if !attr.opaque => {
+                    push(&mut attr);
+                    attr_pos = AttrPos::BeforeKey;
                 }
-                ' ' => {
-                    attr_pos = AttrPos::Space;
-                    let key = String::from_iter(chars_stack);
-                    chars_stack = Vec::new();
-                    key_stack.push(key);
-                    value_stack.push(String::new())
+                _ if ch.is_ascii_whitespace() && attr.opaque => {
+                    push(&mut attr);
+                    attr_pos = AttrPos::BeforeKey;
                 }
-                _ => chars_stack.push(ch),
-            },
-            AttrPos::Equal => match ch {
-                '\'' => attr_pos = AttrPos::Value(Some('\'')),
-                '\"' => attr_pos = AttrPos::Value(Some('\"')),
+                _ if ch.is_ascii_whitespace() => attr_pos = AttrPos::AfterKey,
                 _ => {
-                    attr_pos = AttrPos::Value(None);
-                    chars_stack.push(ch)
+                    extend(&mut attr.key, i..end);
+                    attr.end = end;
                 }
             },
-            AttrPos::Value(delimiter) => match delimiter {
-                None => {
-                    if ch == ' ' {
-                        attr_pos = AttrPos::Space;
-                        let value = String::from_iter(chars_stack);
-                        chars_stack = Vec::new();
-                        value_stack.push(value)
+            AttrPos::AfterKey => match ch {
+                '=' => attr_pos = AttrPos::BeforeValue,
+                _ if ch.is_ascii_whitespace() => {}
+                _ => {
+                    // The attribute before has no value, like `disabled`.
+                    push(&mut attr);
+                    if ch == '/' {
+                        attr_pos = AttrPos::BeforeKey;
                     } else {
-                        chars_stack.push(ch);
+                        attr = Attr {
+                            key: i..end,
+                            start: i,
+                            end,
+                            ..Default::default()
+                        };
+                        attr_pos = AttrPos::Key;
                     }
                 }
-                Some(quote) => {
-                    if ch == quote {
-                        if chars_stack.is_empty() {
-                            value_stack.push(String::new());
-                            attr_pos = AttrPos::Space;
-                            continue;
-                        }
-                        let last_char = chars_stack
-                            .last()
-                            .expect("cannot accesss the last char in `chars_stack`");
-                        if last_char == &'\\' {
-                            chars_stack.push(ch);
-                            continue;
-                        } else {
-                            attr_pos = AttrPos::Space;
-                            let value = String::from_iter(chars_stack);
-                            chars_stack = Vec::new();
-                            value_stack.push(value)
-                        }
-                    } else {
-                        chars_stack.push(ch)
-                    }
+            },
+            AttrPos::BeforeValue => match ch {
+                '\'' | '"' => attr_pos = AttrPos::Value(Some(ch)),
+                _ if ch.is_ascii_whitespace() => {}
+                _ => {
+                    extend(&mut attr.value, i..end);
+                    attr.end = end;
+                    attr_pos = AttrPos::Value(None);
                 }
             },
-            AttrPos::Space => {
-                if ch != ' ' {
-                    attr_pos = AttrPos::Key;
-                    chars_stack.push(ch);
+            AttrPos::Value(Some(quote)) => {
+                if ch == quote {
+                    attr.end = end;
+                    if attr.opaque {
+                        // The fragment goes on after the quotes, like
+                        // `{% if x %}class="a"{% endif %}`.
+                        attr_pos = AttrPos::Key;
+                    } else {
+                        push(&mut attr);
+                        attr_pos = AttrPos::BeforeKey;
+                    }
+                } else {
+                    extend(&mut attr.value, i..end);
                 }
             }
-        }
-    }
-
-    let err_info = format!("cannot parse the attributes: {}", attr_str);
-    let err_info = err_info.as_str();
-
-    if !chars_stack.is_empty() {
-        let str = String::from_iter(chars_stack);
-        match attr_pos {
-            AttrPos::Key => {
-                key_stack.push(str);
-                value_stack.push(String::new());
-            }
-            AttrPos::Value(delimiter) => {
-                if delimiter.is_none() {
-                    value_stack.push(str);
+            AttrPos::Value(None) => {
+                if ch.is_ascii_whitespace() {
+                    push(&mut attr);
+                    attr_pos = AttrPos::BeforeKey;
                 } else {
-                    panic!("{}", err_info)
+                    extend(&mut attr.value, i..end);
+                    attr.end = end;
                 }
             }
-            _ => {}
         }
     }
 
-    if key_stack.len() != value_stack.len() {
-        panic!(
-            "{}\nkey:\t{:?}\nvalue:\t{:?}",
-            err_info, key_stack, value_stack
-        )
-    }
+    let error = match attr_pos {
+        AttrPos::BeforeKey => None,
+        AttrPos::Key | AttrPos::AfterKey | AttrPos::Value(None) => {
+            push(&mut attr);
+            None
+        }
+        AttrPos::BeforeValue => {
+            let error = format!(
+                "Missing value of attribute `{}`",
+                &attr_str[attr.key.clone()]
+            );
+            push(&mut attr);
+            Some(error)
+        }
+        AttrPos::Value(Some(_)) => {
+            let error = format!(
+                "Unterminated attribute value `{}`",
+                &attr_str[attr.value.clone()]
+            );
+            attr.end = attr_str.len();
+            push(&mut attr);
+            Some(error)
+        }
+    };
+    ((attrs, spans), error)
+}
 
-    let mut attrs = Vec::new();
-    let len = key_stack.len();
-    for _ in 0..len {
-        attrs.push((
-            key_stack.pop().expect(err_info),
-            value_stack.pop().expect(err_info),
-        ));
+/// Extend the `range` of a key or value to the chars at `next`, which
+/// follow it right away unless it is empty.
+fn extend(range: &mut Range<usize>, next: Range<usize>) {
+    if range.start == range.end {
+        *range = next;
+    } else {
+        range.end = next.end;
     }
-    attrs
+}
+
+/// Check if the attribute is an opaque fragment parsed by [`parse()`],
+/// which is never renamed or deduplicated.
+pub fn is_opaque(key: &str, passthrough: &[(String, String)]) -> bool {
+    has_region(key, passthrough)
+}
+
+/// Check if the attribute value parsed by [`parse()`] has a region,
+/// which is kept as written.
+pub fn is_raw(value: &str, passthrough: &[(String, String)]) -> bool {
+    has_region(value, passthrough)
+}
+
+fn has_region(text: &str, passthrough: &[(String, String)]) -> bool {
+    passthrough
+        .iter()
+        .any(|(start, _)| text.contains(start.as_str()))
+}
+
+/// Remove the duplicate attributes and their spans by the `policy`, which
+/// keeps the first ones for [`DuplicateAttrs::Error`] too.
+///
+/// Returns the name of the first duplicate attribute if any.
+pub fn dedup(
+    attrs: &mut Vec<(Cow<str>, Cow<str>)>,
+    spans: &mut Vec<Span>,
+    policy: DuplicateAttrs,
+    passthrough: &[(String, String)],
+) -> Option<String> {
+    let mut seen = HashSet::new();
+    let mut keep = |key| is_opaque(key, passthrough) || seen.insert(key);
+    let keep: Vec<bool> = match policy {
+        DuplicateAttrs::First | DuplicateAttrs::Error => {
+            attrs.iter().map(|(key, _)| keep(key)).collect()
+        }
+        DuplicateAttrs::Last => {
+            let mut keep: Vec<bool> = attrs.iter().rev().map(|(key, _)| keep(key)).collect();
+            keep.reverse();
+            keep
+        }
+    };
+    let index = keep.iter().position(|keep| !keep)?;
+    let name = attrs[index].0.to_string();
+    let mut keep_attrs = keep.iter();
+    attrs.retain(|_| keep_attrs.next() == Some(&true));
+    let mut keep_spans = keep.iter();
+    spans.retain(|_| keep_spans.next() == Some(&true));
+    Some(name)
 }
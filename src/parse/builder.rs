@@ -0,0 +1,646 @@
+//! Build the DOM from the tokens one by one, for [`parse()`](super::parse)
+//! and [`try_parse()`](super::try_parse).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::foreign::Content;
+use super::implied::{self, OpenNames};
+use super::parse_body;
+use super::token::{close_element, is_template, raw_attrs, split_conditional, Located, Token};
+use super::{Limit, Limits, ParseError, ParseOptions, Position};
+use crate::data::{OPTIONAL_END_TAGS, VOID_TAGS};
+use crate::{borrowed, Element, ElementSpan, Namespace, Node, Span};
+
+/// The types of the DOM built from the tokens, which borrow from the
+/// source for `'a`.
+pub trait Dom<'a>: Clone {
+    type Node;
+    type Element;
+
+    /// Convert the start tag into an element without children.
+    fn element(&self, located: Located<'a>) -> Self::Element;
+    /// Convert the token into a node.
+    fn node(&self, located: Located<'a>) -> Self::Node;
+    /// Convert the comment into a node, which is a conditional comment
+    /// with the parsed `body` if any, or with the raw body otherwise.
+    fn comment(&self, located: Located<'a>, body: Option<Vec<Self::Node>>) -> Self::Node;
+    /// Create an element without attributes and children, which is not
+    /// in the source.
+    fn new_element(&self, name: &str) -> Self::Node;
+    fn name(element: &Self::Element) -> &str;
+    fn namespace(element: &Self::Element) -> &Namespace;
+    fn append(element: &mut Self::Element, node: Self::Node);
+    /// Close the element at `end`, with the span of its end tag if any.
+    fn close(element: Self::Element, end: usize, end_tag: Option<Span>) -> Self::Node;
+}
+
+/// The owned [`Node`].
+#[derive(Debug, Clone, Default)]
+pub struct OwnedDom;
+
+impl<'a> Dom<'a> for OwnedDom {
+    type Node = Node;
+    type Element = Element;
+
+    fn element(&self, located: Located<'a>) -> Element {
+        located.into_element()
+    }
+
+    fn node(&self, located: Located<'a>) -> Node {
+        located.into_node()
+    }
+
+    fn comment(&self, located: Located<'a>, body: Option<Vec<Node>>) -> Node {
+        located.into_comment(body)
+    }
+
+    fn new_element(&self, name: &str) -> Node {
+        Node::new_element(name, vec![], vec![])
+    }
+
+    fn name(element: &Element) -> &str {
+        &element.name
+    }
+
+    fn namespace(element: &Element) -> &Namespace {
+        &element.namespace
+    }
+
+    fn append(element: &mut Element, node: Node) {
+        element.children.push(node);
+    }
+
+    fn close(mut element: Element, end: usize, end_tag: Option<Span>) -> Node {
+        close_element(&mut element, end, end_tag);
+        if is_template(&element.name, &element.namespace) {
+            element.template_content = Some(std::mem::take(&mut element.children));
+        }
+        element.into_node()
+    }
+}
+
+/// The [`borrowed::Node`], which takes the names and text of the tokens
+/// borrowing from the source as they are.
+#[derive(Debug, Clone, Default)]
+pub struct BorrowedDom;
+
+impl<'a> Dom<'a> for BorrowedDom {
+    type Node = borrowed::Node<'a>;
+    type Element = borrowed::Element<'a>;
+
+    fn element(&self, located: Located<'a>) -> Self::Element {
+        let span = located.span();
+        let namespace = located.namespace;
+        let (name, attrs, attr_spans) = match located.token {
+            Token::Start(name, attrs, spans) | Token::Closing(name, attrs, spans) => {
+                (name, attrs, spans)
+            }
+            Token::End(name) => (name, Vec::new(), Vec::new()),
+            // Only the tags are converted into elements.
+            _ => (Cow::Borrowed(""), Vec::new(), Vec::new()),
+        };
+        let raw_attrs = raw_attrs(&located.raw_attrs, &attrs);
+        let mut element = borrowed::Element::from_parts(
+            name,
+            attrs,
+            Some(ElementSpan {
+                outer: span,
+                start_tag: span,
+                end_tag: None,
+                attrs: attr_spans,
+            }),
+        );
+        element.namespace = namespace;
+        element.raw_attrs = raw_attrs;
+        element
+    }
+
+    fn node(&self, located: Located<'a>) -> Self::Node {
+        match located.token {
+            Token::Start(..) | Token::End(..) | Token::Closing(..) => {
+                self.element(located).into_node()
+            }
+            Token::Text(text) => borrowed::Node::Text(text),
+            Token::Comment(_) => self.comment(located, None),
+            Token::Doctype(doctype) => borrowed::Node::Doctype(doctype),
+            Token::CData(text) => borrowed::Node::CData(text),
+            Token::ProcessingInstruction(target, data) => {
+                borrowed::Node::ProcessingInstruction { target, data }
+            }
+            Token::DownlevelRevealed(text) => borrowed::Node::DownlevelRevealed(text),
+            Token::Passthrough(raw) => borrowed::Node::Passthrough(raw),
+        }
+    }
+
+    fn comment(&self, located: Located<'a>, body: Option<Vec<Self::Node>>) -> Self::Node {
+        let Token::Comment(comment) = located.token else {
+            return self.node(located);
+        };
+        // The comments read from the whole html always borrow from it,
+        // and so do the parts of the conditional ones.
+        let comment = match comment {
+            Cow::Borrowed(comment) => comment,
+            comment => return borrowed::Node::Comment(comment),
+        };
+        match split_conditional(comment) {
+            Some((condition, range)) => borrowed::Node::ConditionalComment {
+                condition: Cow::Borrowed(condition),
+                body: match body {
+                    Some(nodes) => borrowed::ConditionalBody::Nodes(nodes),
+                    None => borrowed::ConditionalBody::Raw(Cow::Borrowed(&comment[range])),
+                },
+            },
+            None => borrowed::Node::Comment(Cow::Borrowed(comment)),
+        }
+    }
+
+    fn new_element(&self, name: &str) -> Self::Node {
+        borrowed::Element::from_parts(Cow::Owned(name.to_string()), Vec::new(), None).into_node()
+    }
+
+    fn name(element: &Self::Element) -> &str {
+        &element.name
+    }
+
+    fn namespace(element: &Self::Element) -> &Namespace {
+        &element.namespace
+    }
+
+    fn append(element: &mut Self::Element, node: Self::Node) {
+        element.children.push(node);
+    }
+
+    fn close(mut element: Self::Element, end: usize, end_tag: Option<Span>) -> Self::Node {
+        if let Some(span) = element.span_mut() {
+            span.outer.end = end;
+            span.end_tag = end_tag;
+        }
+        if is_template(&element.name, &element.namespace) {
+            element.template_content = Some(std::mem::take(&mut element.children));
+        }
+        element.into_node()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DomBuilder<'a, D: Dom<'a> = OwnedDom> {
+    dom: D,
+    /// Whether to fix the illegal html like `try_parse()` instead of
+    /// returning errors.
+    fault_tolerant: bool,
+    /// The nodes outside of any element.
+    nodes: Vec<D::Node>,
+    /// The open elements with their children so far, where their start
+    /// tags are, and how their content is parsed.
+    open: Vec<(D::Element, Position, Content)>,
+    /// The names of the elements in `open`, for the implied end tags.
+    open_names: OpenNames,
+    /// The indexes in `open` of the open SVG and MathML elements by
+    /// their lowercase names, for the end tags which close them.
+    open_foreign: HashMap<String, Vec<usize>>,
+    /// The indexes in `open` of the open HTML elements.
+    open_html: Vec<usize>,
+    /// Byte offset right after the last token, which is the end of the
+    /// elements closed implicitly.
+    last_end: usize,
+    /// The illegal html fixed by the fault tolerant builder, if collected.
+    diagnostics: Option<Vec<ParseError>>,
+    /// Whether the input is XML, where no element is void.
+    xml: bool,
+    /// The options of the html, whose limits are checked by the builder
+    /// and which parse the bodies of the conditional comments.
+    options: ParseOptions,
+    /// The number of nodes so far, for `max_nodes`.
+    node_count: usize,
+    /// Whether the rest of the tokens are ignored after `max_nodes`.
+    truncated: bool,
+    /// The names of the elements dropped by `max_depth` with the number
+    /// of the open elements then, whose end tags are ignored.
+    dropped: Vec<(String, usize)>,
+}
+
+impl<'a, D: Dom<'a>> DomBuilder<'a, D> {
+    pub fn new(dom: D, fault_tolerant: bool) -> Self {
+        DomBuilder {
+            dom,
+            fault_tolerant,
+            nodes: Vec::new(),
+            open: Vec::new(),
+            open_names: OpenNames::default(),
+            open_foreign: HashMap::new(),
+            open_html: Vec::new(),
+            last_end: 0,
+            diagnostics: None,
+            xml: false,
+            options: ParseOptions::default(),
+            node_count: 0,
+            truncated: false,
+            dropped: Vec::new(),
+        }
+    }
+
+    /// Check the nesting depth and the node count against the limits of
+    /// the `options`, and parse the bodies of the conditional comments
+    /// with them.
+    pub fn with_options(mut self, options: &ParseOptions) -> Self {
+        self.options = options.clone();
+        self
+    }
+
+    /// The number of nodes so far, which is only counted with
+    /// `max_nodes`.
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    /// Build the DOM of XML, where no element is void and the names are
+    /// case-sensitive.
+    pub fn xml(mut self) -> Self {
+        self.xml = true;
+        self
+    }
+
+    /// Collect the illegal html fixed by the fault tolerant builder,
+    /// which is returned by [`DomBuilder::finish_with_diagnostics()`].
+    pub fn with_diagnostics(mut self) -> Self {
+        self.diagnostics = Some(Vec::new());
+        self
+    }
+
+    fn diagnose(&mut self, error: ParseError) {
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.push(error);
+        }
+    }
+
+    /// Return the error about the exceeded limit, or keep it as a
+    /// diagnostic if fault tolerant, in which case the token is dropped.
+    fn exceed(&mut self, limit: Limit, max: usize, position: Position) -> Result<(), ParseError> {
+        let error = ParseError::LimitExceeded {
+            limit,
+            max,
+            position,
+        };
+        if !self.fault_tolerant {
+            return Err(error);
+        }
+        self.diagnose(error);
+        Ok(())
+    }
+
+    /// Convert the token into a node, and parse the body of the
+    /// conditional comment with the limits left by the open elements and
+    /// the nodes so far.
+    fn node(&mut self, located: Located<'a>) -> Result<D::Node, ParseError> {
+        if !matches!(located.token, Token::Comment(_)) {
+            return Ok(self.dom.node(located));
+        }
+        let limits = self.options.limits;
+        let left = Limits {
+            max_depth: limits
+                .max_depth
+                .map(|max| max.saturating_sub(self.open.len())),
+            max_nodes: limits
+                .max_nodes
+                .map(|max| max.saturating_sub(self.node_count)),
+            ..limits
+        };
+        match parse_body(self.dom.clone(), &located, &self.options, left) {
+            Ok(Some((nodes, node_count))) => {
+                self.node_count += node_count;
+                Ok(self.dom.comment(located, Some(nodes)))
+            }
+            Ok(None) => Ok(self.dom.node(located)),
+            // The body exceeding the limits is kept raw if fault tolerant.
+            Err(ParseError::LimitExceeded {
+                limit,
+                max,
+                position,
+            }) => {
+                // The limit is of the whole html rather than the left.
+                let max = match limit {
+                    Limit::Depth => limits.max_depth,
+                    Limit::Nodes => limits.max_nodes,
+                    _ => None,
+                }
+                .unwrap_or(max);
+                self.exceed(limit, max, position)?;
+                Ok(self.dom.node(located))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Append the node to the innermost open element.
+    fn append(&mut self, node: D::Node) {
+        match self.open.last_mut() {
+            Some((parent, ..)) => D::append(parent, node),
+            None => self.nodes.push(node),
+        }
+    }
+
+    /// Close the innermost open element, with the span of its end tag if
+    /// it is closed explicitly.
+    fn close(&mut self, end_tag: Option<&Located>) {
+        if let Some((element, ..)) = self.open.pop() {
+            self.open_names.pop();
+            if *D::namespace(&element) == Namespace::Html {
+                self.open_html.pop();
+            } else {
+                let name = D::name(&element).to_ascii_lowercase();
+                if let Some(indexes) = self.open_foreign.get_mut(&name) {
+                    indexes.pop();
+                    if indexes.is_empty() {
+                        self.open_foreign.remove(&name);
+                    }
+                }
+            }
+            // The elements dropped in it are closed with it.
+            let depth = self.open.len();
+            self.dropped.retain(|(_, dropped)| *dropped <= depth);
+            let node = match end_tag {
+                Some(end_tag) => D::close(element, end_tag.end, Some(end_tag.span())),
+                None => D::close(element, self.last_end, None),
+            };
+            self.append(node);
+        }
+    }
+
+    /// Close the innermost open element without its end tag, and keep
+    /// the diagnostic made by `error` from the name and the position of
+    /// its start tag, unless its end tag can be omitted.
+    fn close_implied(&mut self, error: impl Fn(String, Position) -> ParseError) {
+        if let (Some(diagnostics), Some((element, start, _))) =
+            (&mut self.diagnostics, self.open.last())
+        {
+            let name = D::name(element);
+            if !OPTIONAL_END_TAGS.contains(&name) {
+                diagnostics.push(error(name.to_string(), *start));
+            }
+        }
+        self.close(None);
+    }
+
+    fn open(&mut self, located: Located<'a>) -> Result<(), ParseError> {
+        let start = located.start;
+        if let Some(max) = self
+            .options
+            .limits
+            .max_depth
+            .filter(|max| self.open.len() >= *max)
+        {
+            self.exceed(Limit::Depth, max, start)?;
+            if let Token::Start(name, ..) = &located.token {
+                self.dropped.push((name.to_string(), self.open.len()));
+            }
+            return Ok(());
+        }
+        let namespace = located.namespace.clone();
+        let content = match &located.token {
+            Token::Start(name, attrs, _) => {
+                let attrs = attrs.iter().map(|(k, v)| (&**k, &**v));
+                Content::of(&namespace, name, attrs)
+            }
+            _ => Content::Html,
+        };
+        let element = self.dom.element(located);
+        self.open_names
+            .push(implied::open_name(&namespace, D::name(&element)));
+        if namespace == Namespace::Html {
+            self.open_html.push(self.open.len());
+        } else {
+            self.open_foreign
+                .entry(D::name(&element).to_ascii_lowercase())
+                .or_default()
+                .push(self.open.len());
+        }
+        self.open.push((element, start, content));
+        Ok(())
+    }
+
+    /// Check if the start tag is void, which is only for the HTML ones.
+    fn is_void(&self, located: &Located) -> bool {
+        match &located.token {
+            Token::Start(tag, ..) if !self.xml => {
+                located.namespace == Namespace::Html && VOID_TAGS.contains(&&**tag)
+            }
+            _ => false,
+        }
+    }
+
+    /// Find the open SVG or MathML element which the end tag closes,
+    /// above the innermost HTML element.
+    fn foreign_end(&self, tag: &str) -> Option<usize> {
+        if self.open_foreign.is_empty() {
+            return None;
+        }
+        let index = *self.open_foreign.get(&tag.to_ascii_lowercase())?.last()?;
+        let above = self.open_html.last().map_or(0, |html| html + 1);
+        (index >= above).then_some(index)
+    }
+
+    pub fn push(&mut self, located: Located<'a>) -> Result<(), ParseError> {
+        if self.truncated {
+            return Ok(());
+        }
+        // The end tags make no nodes.
+        if let Some(max) = self
+            .options
+            .limits
+            .max_nodes
+            .filter(|_| !matches!(located.token, Token::End(_)))
+        {
+            if self.node_count >= max {
+                self.exceed(Limit::Nodes, max, located.start)?;
+                self.truncated = true;
+                return Ok(());
+            }
+            self.node_count += 1;
+        }
+        let end = located.end;
+        if self.fault_tolerant {
+            self.push_tolerant(located)?;
+        } else {
+            self.push_strict(located)?;
+        }
+        self.last_end = end;
+        Ok(())
+    }
+
+    fn push_strict(&mut self, located: Located<'a>) -> Result<(), ParseError> {
+        match &located.token {
+            Token::Start(..) if !self.is_void(&located) => self.open(located)?,
+            Token::End(tag) => {
+                let Some((start_tag, start, _)) = self.open.last() else {
+                    return Err(ParseError::StrayEndTag {
+                        name: tag.to_string(),
+                        end: located.start,
+                    });
+                };
+                // The names of SVG and MathML are compared
+                // case-insensitively except in XML, as `<svg>` and
+                // `</SVG>` are not lowercased when the case is preserved.
+                let matched = if self.xml || *D::namespace(start_tag) == Namespace::Html {
+                    tag == D::name(start_tag)
+                } else {
+                    tag.eq_ignore_ascii_case(D::name(start_tag))
+                };
+                if !matched {
+                    return Err(ParseError::MismatchedEndTag {
+                        start_name: D::name(start_tag).to_string(),
+                        end_name: tag.to_string(),
+                        start: *start,
+                        end: located.start,
+                    });
+                }
+                self.close(Some(&located));
+            }
+            _ => {
+                let node = self.node(located)?;
+                self.append(node);
+            }
+        }
+        Ok(())
+    }
+
+    fn push_tolerant(&mut self, located: Located<'a>) -> Result<(), ParseError> {
+        let unclosed = |name, start| ParseError::UnclosedTag { name, start };
+        // The HTML tags like `<p>` in the foreign content close the SVG
+        // and MathML elements.
+        if matches!(located.token, Token::Start(..) | Token::Closing(..))
+            && located.namespace == Namespace::Html
+        {
+            while self
+                .open
+                .last()
+                .is_some_and(|(.., content)| content.is_foreign())
+            {
+                self.close_implied(unclosed);
+            }
+        }
+        match &located.token {
+            Token::Start(..) if located.namespace != Namespace::Html => self.open(located)?,
+            Token::Start(tag, ..) => {
+                // Some elements are closed implicitly by the following
+                // start tag, like the first `<li>` in `<li>a<li>b`.
+                let len = implied::before_start_tag(&self.open_names, tag);
+                while self.open.len() > len {
+                    self.close_implied(unclosed);
+                }
+                if self.is_void(&located) {
+                    let node = self.node(located)?;
+                    self.append(node);
+                } else {
+                    self.open(located)?;
+                }
+            }
+            // The end tag of the element dropped by `max_depth` is
+            // dropped too, with the dropped elements in it.
+            Token::End(tag) if self.close_dropped(tag) => {}
+            Token::End(tag) => match self.foreign_end(tag) {
+                Some(index) => {
+                    while self.open.len() > index + 1 {
+                        self.close_implied(|start_name, start| ParseError::MismatchedEndTag {
+                            start_name,
+                            end_name: tag.to_string(),
+                            start,
+                            end: located.start,
+                        });
+                    }
+                    self.close(Some(&located));
+                }
+                None => self.end_html(tag, &located),
+            },
+            _ => {
+                let node = self.node(located)?;
+                self.append(node);
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the HTML element which the end tag closes with the elements
+    /// in it, or ignore the end tag if it closes none.
+    fn end_html(&mut self, tag: &str, located: &Located) {
+        match implied::before_end_tag(&self.open_names, tag) {
+            // The elements between are closed implicitly, like the `<li>`
+            // in `<ul><li>a</ul>`.
+            Some(index) => {
+                let mismatched = |start_name, start| ParseError::MismatchedEndTag {
+                    start_name,
+                    end_name: tag.to_string(),
+                    start,
+                    end: located.start,
+                };
+                while self.open.len() > index + 1 {
+                    self.close_implied(mismatched);
+                }
+                // The headings close each other, like `<h1></h2>`.
+                if let Some((element, start, _)) = self.open.last() {
+                    if D::name(element) != tag {
+                        let error = mismatched(D::name(element).to_string(), *start);
+                        self.diagnose(error);
+                    }
+                }
+                self.close(Some(located));
+            }
+            // The end tags which cannot close any element are ignored,
+            // except that `</p>` and `</br>` are treated as `<p></p>` and
+            // `<br>` like browsers do.
+            None => {
+                self.diagnose(ParseError::StrayEndTag {
+                    name: tag.to_string(),
+                    end: located.start,
+                });
+                if tag == "p" || tag == "br" {
+                    self.append(self.dom.new_element(tag));
+                }
+            }
+        }
+    }
+
+    /// Find the dropped element which the end tag closes, and forget it
+    /// with the dropped elements in it.
+    fn close_dropped(&mut self, tag: &str) -> bool {
+        match self
+            .dropped
+            .iter()
+            .rposition(|(name, _)| name.eq_ignore_ascii_case(tag))
+        {
+            Some(index) => {
+                self.dropped.truncate(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close the elements left open at the end of the html.
+    fn close_all(&mut self) {
+        while !self.open.is_empty() {
+            self.close_implied(|name, start| ParseError::UnclosedTag { name, start });
+        }
+    }
+
+    /// Get the nodes after all the tokens are pushed.
+    pub fn finish(mut self) -> Result<Vec<D::Node>, ParseError> {
+        if self.fault_tolerant {
+            self.close_all();
+        } else if let Some((element, start, _)) = self.open.pop() {
+            return Err(ParseError::UnclosedTag {
+                name: D::name(&element).to_string(),
+                start,
+            });
+        }
+        Ok(self.nodes)
+    }
+
+    /// Get the nodes and the diagnostics after all the tokens are pushed
+    /// to the fault tolerant builder.
+    pub fn finish_with_diagnostics(mut self) -> (Vec<D::Node>, Vec<ParseError>) {
+        self.close_all();
+        let diagnostics = self.diagnostics.take().unwrap_or_default();
+        (self.nodes, diagnostics)
+    }
+}
@@ -0,0 +1,175 @@
+use std::{error::Error, fmt};
+
+use super::Limit;
+
+/// A location in the source html.
+///
+/// `offset` is the byte offset from the beginning of the input, while
+/// `line` and `column` are both 1-based, and `column` is counted in
+/// chars rather than bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// The position of the first char of the input.
+    pub(crate) const fn start() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Move the position forward over `ch`.
+    #[inline]
+    pub(crate) fn advance(&mut self, ch: char) {
+        self.offset += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Error returned by [`parse()`](crate::parse).
+///
+/// Every variant records where the offending token starts, and the
+/// variants about unmatched tags also record where the related start
+/// tag is, so that it is possible to print diagnostics like:
+///
+/// ```log
+/// error: <div> does not match the </span>
+///  --> 3:9
+/// ```
+///
+/// Example:
+/// ```
+/// use html_editor::{parse, ParseError};
+///
+/// let error = parse("<div>\n    <span>\n</div>").unwrap_err();
+/// assert!(matches!(error, ParseError::MismatchedEndTag { .. }));
+/// assert_eq!(error.position().line, 3);
+/// assert_eq!(error.start_tag_position().unwrap().line, 2);
+/// assert_eq!(error.to_string(), "<span> does not match the </div> at 3:1");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A start tag like `<div>` is never closed.
+    UnclosedTag { name: String, start: Position },
+    /// An end tag does not match the start tag it should close, like
+    /// the `</span>` in `<div></span>`.
+    MismatchedEndTag {
+        start_name: String,
+        end_name: String,
+        start: Position,
+        end: Position,
+    },
+    /// An end tag like `</div>` has no start tag to close.
+    StrayEndTag { name: String, end: Position },
+    /// Text in angle brackets which cannot be recognized as a tag.
+    InvalidTag { tag: String, position: Position },
+    /// The attributes of a tag cannot be parsed, like `<a href=>`.
+    MalformedAttribute {
+        tag: String,
+        reason: String,
+        position: Position,
+    },
+    /// An attribute appears twice in a tag, like `<p id="a" id="b">`,
+    /// which is only an error with [`DuplicateAttrs::Error`](crate::DuplicateAttrs::Error).
+    DuplicateAttribute { name: String, position: Position },
+    /// The XML is not well-formed, like the unquoted attribute values,
+    /// the unbound namespace prefixes and the text outside of the root
+    /// element, which is only an error of [`parse_xml()`](crate::parse_xml).
+    NotWellFormed { reason: String, position: Position },
+    /// The input exceeds one of the [`Limits`](crate::Limits), where
+    /// `max` is the limit.
+    LimitExceeded {
+        limit: Limit,
+        max: usize,
+        position: Position,
+    },
+}
+
+impl ParseError {
+    /// Position of the token that causes the error.
+    ///
+    /// For [`ParseError::UnclosedTag`] it is the position of the
+    /// unclosed start tag.
+    pub fn position(&self) -> Position {
+        match self {
+            ParseError::UnclosedTag { start, .. } => *start,
+            ParseError::MismatchedEndTag { end, .. } => *end,
+            ParseError::StrayEndTag { end, .. } => *end,
+            ParseError::InvalidTag { position, .. } => *position,
+            ParseError::MalformedAttribute { position, .. } => *position,
+            ParseError::DuplicateAttribute { position, .. } => *position,
+            ParseError::NotWellFormed { position, .. } => *position,
+            ParseError::LimitExceeded { position, .. } => *position,
+        }
+    }
+
+    /// Position of the start tag related to the error, if any.
+    pub fn start_tag_position(&self) -> Option<Position> {
+        match self {
+            ParseError::UnclosedTag { start, .. } => Some(*start),
+            ParseError::MismatchedEndTag { start, .. } => Some(*start),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnclosedTag { name, start } => {
+                write!(f, "<{}> is not closed at {}", name, start)
+            }
+            ParseError::MismatchedEndTag {
+                start_name,
+                end_name,
+                end,
+                ..
+            } => write!(
+                f,
+                "<{}> does not match the </{}> at {}",
+                start_name, end_name, end
+            ),
+            ParseError::StrayEndTag { name, end } => {
+                write!(f, "No start tag matches </{}> at {}", name, end)
+            }
+            ParseError::InvalidTag { tag, position } => {
+                write!(f, "Invalid tag: {} at {}", tag, position)
+            }
+            ParseError::MalformedAttribute {
+                tag,
+                reason,
+                position,
+            } => write!(f, "{} in {} at {}", reason, tag, position),
+            ParseError::DuplicateAttribute { name, position } => {
+                write!(f, "Duplicate attribute `{}` at {}", name, position)
+            }
+            ParseError::NotWellFormed { reason, position } => {
+                write!(f, "{} at {}", reason, position)
+            }
+            ParseError::LimitExceeded {
+                limit,
+                max,
+                position,
+            } => write!(f, "{} exceeds the limit of {} at {}", limit, max, position),
+        }
+    }
+}
+
+impl Error for ParseError {}
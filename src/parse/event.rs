@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+
+use super::token::{Located, Token};
+use super::tokenizer::Tokens;
+use super::{ParseError, ParseOptions};
+use crate::{Doctype, Span};
+
+/// A piece of markup read by [`events()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Like `<div id="app">`, or `<br />` which is self-closing.
+    StartTag {
+        name: String,
+        attrs: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    /// Like `</div>`
+    EndTag { name: String },
+    /// Text between the tags, whose character references are decoded.
+    Text(String),
+    /// Like `<!-- comment -->`
+    Comment(String),
+    /// Like `<!DOCTYPE html>`
+    Doctype(Doctype),
+    /// Like `<![CDATA[ text ]]>`
+    CData(String),
+    /// Like `<?xml-stylesheet href="a.css"?>`
+    ProcessingInstruction { target: String, data: String },
+    /// Like `<![if !mso]>` or `<![endif]>`, with the text between `<!`
+    /// and `>`.
+    DownlevelRevealed(String),
+    /// Like `{{ value }}`, see [`ParseOptions::passthrough`].
+    Passthrough(String),
+}
+
+impl From<Token<'_>> for Event {
+    fn from(token: Token) -> Self {
+        let owned_attrs = |attrs: Vec<(Cow<str>, Cow<str>)>| {
+            attrs
+                .into_iter()
+                .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                .collect()
+        };
+        match token {
+            Token::Start(name, attrs, _) => Event::StartTag {
+                name: name.into_owned(),
+                attrs: owned_attrs(attrs),
+                self_closing: false,
+            },
+            Token::Closing(name, attrs, _) => Event::StartTag {
+                name: name.into_owned(),
+                attrs: owned_attrs(attrs),
+                self_closing: true,
+            },
+            Token::End(name) => Event::EndTag {
+                name: name.into_owned(),
+            },
+            Token::Text(text) => Event::Text(text.into_owned()),
+            Token::Comment(comment) => Event::Comment(comment.into_owned()),
+            Token::Doctype(doctype) => Event::Doctype(doctype),
+            Token::CData(text) => Event::CData(text.into_owned()),
+            Token::ProcessingInstruction(target, data) => Event::ProcessingInstruction {
+                target: target.into_owned(),
+                data: data.into_owned(),
+            },
+            Token::DownlevelRevealed(text) => Event::DownlevelRevealed(text.into_owned()),
+            Token::Passthrough(raw) => Event::Passthrough(raw.into_owned()),
+        }
+    }
+}
+
+/// Iterator of the [`Event`]s and their spans in the source, created by
+/// [`events()`] and [`events_with()`].
+pub struct Events<'a> {
+    tokens: Tokens<'a>,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<(Event, Span), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokens.next().map(|token| {
+            token.map(|located: Located| {
+                let span = located.span();
+                (located.token.into(), span)
+            })
+        })
+    }
+}
+
+/// Read the html lazily as the [`Event`]s without building the DOM,
+/// which is cheaper for a single pass over the markup.
+///
+/// Each event comes with its [`Span`] in the source. The iterator stops
+/// after the first error.
+///
+/// ```
+/// use html_editor::{events, Event};
+///
+/// let html = r#"<p><a href="a.html">A</a> and <a href="b.html">B</a></p>"#;
+/// let links: Vec<String> = events(html)
+///     .filter_map(Result::ok)
+///     .filter_map(|(event, _)| match event {
+///         Event::StartTag { name, attrs, .. } if name == "a" => attrs
+///             .into_iter()
+///             .find(|(key, _)| key == "href")
+///             .map(|(_, value)| value),
+///         _ => None,
+///     })
+///     .collect();
+/// assert_eq!(links, vec!["a.html", "b.html"]);
+/// ```
+pub fn events(html: &str) -> Events<'_> {
+    events_with(html, &ParseOptions::default())
+}
+
+/// Alternative for [`events()`] with the [`ParseOptions`].
+pub fn events_with<'a>(html: &'a str, options: &ParseOptions) -> Events<'a> {
+    Events {
+        tokens: Tokens::new(html, options, false),
+    }
+}
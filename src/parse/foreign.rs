@@ -0,0 +1,170 @@
+//! Rules of the foreign content, which is the content of `<svg>` and
+//! `<math>`, following the [HTML standard](https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign).
+
+use crate::Namespace;
+
+/// Start tags which break out of the foreign content, as they can only
+/// be HTML.
+const BREAKOUT_TAGS: [&str; 44] = [
+    "b",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "center",
+    "code",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "embed",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "hr",
+    "i",
+    "img",
+    "li",
+    "listing",
+    "menu",
+    "meta",
+    "nobr",
+    "ol",
+    "p",
+    "pre",
+    "ruby",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "strike",
+    "sub",
+    "sup",
+    "table",
+    "tt",
+    "u",
+    "ul",
+    "var",
+];
+
+const MATHML_TEXT_INTEGRATION_POINTS: [&str; 5] = ["mi", "mn", "mo", "ms", "mtext"];
+
+const SVG_HTML_INTEGRATION_POINTS: [&str; 3] = ["desc", "foreignobject", "title"];
+
+/// Special MathML elements, which are boundaries of the scopes except
+/// the table scope.
+const MATHML_SPECIAL: [&str; 6] = ["annotation-xml", "mi", "mn", "mo", "ms", "mtext"];
+
+/// How the content of an element is parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Content {
+    /// The content is HTML, like in the HTML elements and the HTML
+    /// integration points such as `<foreignObject>`.
+    Html,
+    /// The content is HTML except `<mglyph>` and `<malignmark>`, like in
+    /// the MathML text integration points such as `<mi>`.
+    MathMlText,
+    /// The content of `<annotation-xml>` which is not an HTML integration
+    /// point, where `<svg>` starts SVG.
+    Annotation,
+    /// The content is SVG.
+    Svg,
+    /// The content is MathML.
+    MathMl,
+}
+
+impl Content {
+    /// Get how the content of the element is parsed by its namespace,
+    /// name and attributes.
+    pub fn of<'a>(
+        namespace: &Namespace,
+        name: &str,
+        mut attrs: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> Content {
+        let name = name.to_ascii_lowercase();
+        match namespace {
+            Namespace::MathMl if MATHML_TEXT_INTEGRATION_POINTS.contains(&name.as_str()) => {
+                Content::MathMlText
+            }
+            Namespace::MathMl if name == "annotation-xml" => {
+                let html = attrs.any(|(key, value)| {
+                    key.eq_ignore_ascii_case("encoding")
+                        && (value.eq_ignore_ascii_case("text/html")
+                            || value.eq_ignore_ascii_case("application/xhtml+xml"))
+                });
+                if html {
+                    Content::Html
+                } else {
+                    Content::Annotation
+                }
+            }
+            Namespace::Svg if SVG_HTML_INTEGRATION_POINTS.contains(&name.as_str()) => Content::Html,
+            Namespace::Svg => Content::Svg,
+            Namespace::MathMl => Content::MathMl,
+            // The foreign content is only in HTML.
+            Namespace::Html | Namespace::Other(_) | Namespace::Null => Content::Html,
+        }
+    }
+
+    /// Check if the content is parsed by the rules of the foreign
+    /// content, which the tags breaking out of it close.
+    pub fn is_foreign(self) -> bool {
+        matches!(self, Content::Svg | Content::MathMl | Content::Annotation)
+    }
+
+    /// Get the namespace of the start tag `name` with `attrs` in the
+    /// content.
+    ///
+    /// Returns `None` if the start tag breaks out of the foreign content,
+    /// in which case the foreign elements should be closed first.
+    pub fn child<'a>(
+        self,
+        name: &str,
+        attrs: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> Option<Namespace> {
+        let name = name.to_ascii_lowercase();
+        match self {
+            Content::MathMlText if name == "mglyph" || name == "malignmark" => {
+                Some(Namespace::MathMl)
+            }
+            Content::Html | Content::MathMlText => Some(match name.as_str() {
+                "svg" => Namespace::Svg,
+                "math" => Namespace::MathMl,
+                _ => Namespace::Html,
+            }),
+            Content::Annotation if name == "svg" => Some(Namespace::Svg),
+            _ if breaks_out(&name, attrs) => None,
+            Content::Annotation | Content::MathMl => Some(Namespace::MathMl),
+            Content::Svg => Some(Namespace::Svg),
+        }
+    }
+}
+
+/// Check if the SVG or MathML element is in the special category, like
+/// `<foreignObject>` and `<mi>`.
+pub fn is_special(namespace: &Namespace, name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    match namespace {
+        Namespace::MathMl => MATHML_SPECIAL.contains(&name.as_str()),
+        // The HTML integration points of SVG are all special.
+        Namespace::Svg => SVG_HTML_INTEGRATION_POINTS.contains(&name.as_str()),
+        _ => false,
+    }
+}
+
+/// Check if the start tag `name`, which is lowercased, breaks out of the
+/// foreign content. `<font>` only does with `color`, `face` or `size`.
+pub fn breaks_out<'a>(name: &str, mut attrs: impl Iterator<Item = (&'a str, &'a str)>) -> bool {
+    BREAKOUT_TAGS.contains(&name)
+        || name == "font"
+            && attrs.any(|(key, _)| {
+                ["color", "face", "size"]
+                    .iter()
+                    .any(|attr| key.eq_ignore_ascii_case(attr))
+            })
+}
@@ -0,0 +1,304 @@
+//! Rules of the implied end tags, which follow the "in body" insertion
+//! mode of the [HTML standard](https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody).
+//!
+//! Each rule takes `open`, the names of the open elements from the
+//! outermost to the innermost, and returns the length which `open`
+//! should be truncated to. The SVG and MathML elements are named by
+//! [`open_name()`], so that the HTML rules never match them.
+
+use std::collections::HashMap;
+
+use super::foreign;
+use crate::data::SPECIAL_TAGS;
+use crate::Namespace;
+
+/// Name of the special SVG and MathML elements in [`OpenNames`], which
+/// are boundaries of the scopes like the special HTML elements.
+const FOREIGN_SPECIAL: &str = "#special";
+
+/// Get the name of the element in [`OpenNames`], which is empty for the
+/// SVG and MathML elements except the special ones.
+pub fn open_name<'a>(namespace: &Namespace, name: &'a str) -> &'a str {
+    match namespace {
+        Namespace::Html => name,
+        _ if foreign::is_special(namespace, name) => FOREIGN_SPECIAL,
+        _ => "",
+    }
+}
+
+/// The names of the open elements, with how many elements of each name
+/// are open.
+///
+/// The rules check the counts before looking for an element in `open`,
+/// so that they don't scan the whole stack for every token when the html
+/// is nested deeply.
+#[derive(Debug, Clone, Default)]
+pub struct OpenNames {
+    names: Vec<String>,
+    counts: HashMap<String, usize>,
+}
+
+impl OpenNames {
+    pub fn push(&mut self, name: &str) {
+        self.names.push(name.to_string());
+        self.count(name);
+    }
+
+    pub fn pop(&mut self) {
+        if let Some(name) = self.names.pop() {
+            if let Some(count) = self.counts.get_mut(&name) {
+                *count -= 1;
+            }
+        }
+    }
+
+    /// Insert the name of the element put into the stack at `index`.
+    pub fn insert(&mut self, index: usize, name: &str) {
+        self.names.insert(index, name.to_string());
+        self.count(name);
+    }
+
+    /// Count the element of the `name` put into the stack, which only
+    /// copies the name the first time.
+    fn count(&mut self, name: &str) {
+        match self.counts.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(name.to_string(), 1);
+            }
+        }
+    }
+
+    /// Remove the name of the element taken out of the stack at `index`.
+    pub fn remove(&mut self, index: usize) {
+        let name = self.names.remove(index);
+        if let Some(count) = self.counts.get_mut(&name) {
+            *count -= 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Check if any element of the `names` is open.
+    pub fn has_any(&self, names: &[&str]) -> bool {
+        names
+            .iter()
+            .any(|name| self.counts.get(*name).is_some_and(|count| *count > 0))
+    }
+
+    /// Iterate over the names from the innermost to the outermost in
+    /// `..len`, with their indexes.
+    fn rev(&self, len: usize) -> impl Iterator<Item = (usize, &str)> {
+        self.names[..len]
+            .iter()
+            .map(String::as_str)
+            .enumerate()
+            .rev()
+    }
+
+    fn get(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+}
+
+/// Start tags that close a `p` element in button scope.
+const P_CLOSERS: [&str; 36] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "search",
+    "section",
+    "summary",
+    "ul",
+];
+
+const HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Boundaries of the "has an element in scope" check.
+const SCOPE: [&str; 10] = [
+    "applet",
+    "caption",
+    "html",
+    "table",
+    "td",
+    "th",
+    "marquee",
+    "object",
+    "template",
+    FOREIGN_SPECIAL,
+];
+
+/// Boundaries of the "has an element in table scope" check.
+const TABLE_SCOPE: [&str; 3] = ["html", "table", "template"];
+
+/// End tags that are checked in table scope.
+const TABLE_TAGS: [&str; 8] = [
+    "caption", "colgroup", "table", "tbody", "td", "tfoot", "th", "thead",
+];
+
+/// Find the innermost element of the `targets` in `open[..len]` if it
+/// is in the scope, which ends at the `boundaries` and `extra`
+/// boundaries.
+fn in_scope(
+    open: &OpenNames,
+    len: usize,
+    targets: &[&str],
+    boundaries: &[&str],
+    extra: &[&str],
+) -> Option<usize> {
+    if !open.has_any(targets) {
+        return None;
+    }
+    for (i, name) in open.rev(len) {
+        if targets.contains(&name) {
+            return Some(i);
+        }
+        if boundaries.contains(&name) || extra.contains(&name) {
+            return None;
+        }
+    }
+    None
+}
+
+/// Find the outermost element of the `targets` above the innermost
+/// table boundary.
+fn in_table(open: &OpenNames, targets: &[&str]) -> Option<usize> {
+    if !open.has_any(targets) {
+        return None;
+    }
+    let mut found = None;
+    for (i, name) in open.rev(open.len()) {
+        if TABLE_SCOPE.contains(&name) {
+            break;
+        }
+        if targets.contains(&name) {
+            found = Some(i);
+        }
+    }
+    found
+}
+
+/// Get the length of `open` after the start tag `tag` closes the elements
+/// whose end tags are omitted, like the first `li` in `<li>a<li>b`.
+pub fn before_start_tag(open: &OpenNames, tag: &str) -> usize {
+    let mut len = open.len();
+    let last = |len: usize| {
+        if len > 0 {
+            Some(open.get(len - 1))
+        } else {
+            None
+        }
+    };
+
+    match tag {
+        "li" | "dd" | "dt" => {
+            let siblings: &[&str] = if tag == "li" { &["li"] } else { &["dd", "dt"] };
+            if open.has_any(siblings) {
+                for (i, name) in open.rev(len) {
+                    if siblings.contains(&name) {
+                        len = i;
+                        break;
+                    }
+                    if (SPECIAL_TAGS.contains(&name) || name == FOREIGN_SPECIAL)
+                        && !["address", "div", "p"].contains(&name)
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+        "option" if last(len) == Some("option") => len -= 1,
+        "optgroup" => {
+            if last(len) == Some("option") {
+                len -= 1;
+            }
+            if last(len) == Some("optgroup") {
+                len -= 1;
+            }
+        }
+        "td" | "th" => {
+            if let Some(i) = in_table(open, &["td", "th"]) {
+                len = i;
+            }
+        }
+        "tr" => {
+            if let Some(i) = in_table(open, &["tr", "td", "th"]) {
+                len = i;
+            }
+        }
+        "thead" | "tbody" | "tfoot" => {
+            if let Some(i) = in_table(open, &["thead", "tbody", "tfoot", "tr", "td", "th"]) {
+                len = i;
+            }
+        }
+        "rb" | "rtc" => {
+            while last(len).is_some_and(|name| ["rb", "rp", "rt", "rtc"].contains(&name)) {
+                len -= 1;
+            }
+        }
+        "rp" | "rt" => {
+            while last(len).is_some_and(|name| ["rb", "rp", "rt"].contains(&name)) {
+                len -= 1;
+            }
+        }
+        _ => {}
+    }
+
+    if P_CLOSERS.contains(&tag) || tag == "table" {
+        if let Some(i) = in_scope(open, len, &["p"], &SCOPE, &["button"]) {
+            len = i;
+        }
+    }
+
+    len
+}
+
+/// Get the length of `open` after the end tag `tag` closes its start tag
+/// and the elements whose end tags are omitted, like the `li` in
+/// `<ul><li>a</ul>`.
+///
+/// Returns `None` if the end tag should be ignored.
+pub fn before_end_tag(open: &OpenNames, tag: &str) -> Option<usize> {
+    let len = open.len();
+    match tag {
+        "li" => in_scope(open, len, &[tag], &SCOPE, &["ol", "ul"]),
+        "p" => in_scope(open, len, &[tag], &SCOPE, &["button"]),
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => in_scope(open, len, &HEADINGS, &SCOPE, &[]),
+        _ if TABLE_TAGS.contains(&tag) || tag == "tr" => {
+            in_scope(open, len, &[tag], &TABLE_SCOPE, &[])
+        }
+        _ if SPECIAL_TAGS.contains(&tag) => in_scope(open, len, &[tag], &SCOPE, &[]),
+        _ => in_scope(open, len, &[tag], &SPECIAL_TAGS, &[FOREIGN_SPECIAL]),
+    }
+}
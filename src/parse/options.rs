@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::data::{CAMEL_CASE_ATTRS, SVG_CAMEL_CASE_TAGS};
+use crate::Namespace;
+
+/// Options of [`parse_with()`](crate::parse_with) and
+/// [`try_parse_with()`](crate::try_parse_with).
+///
+/// ```
+/// use html_editor::{parse_with, NameCase, ParseOptions};
+/// use html_editor::operation::*;
+///
+/// let options = ParseOptions {
+///     name_case: NameCase::Html,
+///     ..Default::default()
+/// };
+/// let dom = parse_with("<DIV ID=app><SVG viewBox='0 0 1 1'></SVG></DIV>", &options).unwrap();
+/// assert_eq!(dom.html(), r#"<div id="app"><svg viewBox="0 0 1 1"></svg></div>"#);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// How the names of the tags and attributes are cased.
+    pub name_case: NameCase,
+    /// Which one is kept if an attribute is duplicated in a tag.
+    pub duplicate_attrs: DuplicateAttrs,
+    /// The limits of the input, which are unbounded by default.
+    pub limits: Limits,
+    /// The start and end delimiters of the template syntax like
+    /// `("{{", "}}")`, whose regions are kept as written.
+    ///
+    /// A region between the tags is a [`Node::Passthrough`](crate::Node::Passthrough).
+    /// An attribute value with a region is kept as written without
+    /// decoding, and written back as it is until it is changed. A region
+    /// between the attributes is an opaque fragment of them, which is
+    /// kept as the key of an attribute without value. The regions in the
+    /// comments and the text of `<script>` and the like are not
+    /// recognized. The pairs with an empty delimiter are ignored.
+    ///
+    /// ```
+    /// use html_editor::{parse_with, Node, ParseOptions};
+    /// use html_editor::operation::*;
+    ///
+    /// let options = ParseOptions {
+    ///     passthrough: vec![("{%".into(), "%}".into()), ("{{".into(), "}}".into())],
+    ///     ..Default::default()
+    /// };
+    /// let html = r#"<a href="/{{ user.id }}" {% if x > 0 %}class="on"{% endif %}>{{ name }}</a>"#;
+    /// let dom = parse_with(html, &options).unwrap();
+    /// assert_eq!(dom.html(), html);
+    ///
+    /// let a = dom[0].as_element().unwrap();
+    /// assert_eq!(a.attrs[0], ("href".into(), "/{{ user.id }}".into()));
+    /// assert_eq!(a.attrs[1].0, r#"{% if x > 0 %}class="on"{% endif %}"#);
+    /// assert!(matches!(&a.children[..], [Node::Passthrough(raw)] if raw == "{{ name }}"));
+    /// ```
+    pub passthrough: Vec<(String, String)>,
+}
+
+/// Limits of the input, which bound the memory and time spent on parsing
+/// the untrusted html. Each limit is unbounded if `None`.
+///
+/// Exceeding a limit is [`ParseError::LimitExceeded`](crate::ParseError::LimitExceeded)
+/// from [`parse_with()`](crate::parse_with), while the fault tolerant
+/// parsers truncate the input instead:
+///
+/// - The input after `max_input_len` bytes is ignored.
+/// - The elements nested deeper than `max_depth` are dropped, but their
+///   content is kept in the innermost element.
+/// - The rest of the input is ignored after `max_nodes` nodes.
+/// - The attributes after `max_attrs` in a tag are dropped.
+/// - The attribute values and the text longer than `max_attr_len` and
+///   `max_text_len` bytes are cut.
+///
+/// The depth and the node count are only checked when building the DOM,
+/// not by [`events_with()`](crate::events_with). They include the nodes
+/// parsed in the bodies of the conditional comments, which are kept raw
+/// by the fault tolerant parsers if they exceed the limits.
+///
+/// ```
+/// use html_editor::{parse_with, try_parse_with, Limits, ParseOptions};
+/// use html_editor::operation::*;
+///
+/// let options = ParseOptions {
+///     limits: Limits {
+///         max_depth: Some(2),
+///         max_text_len: Some(5),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// let html = "<div><p><b>Hello</b></p></div>";
+/// let error = parse_with(html, &options).unwrap_err();
+/// assert_eq!(error.to_string(), "Nesting depth exceeds the limit of 2 at 1:9");
+/// assert_eq!(try_parse_with(html, &options).html(), "<div><p>Hello</p></div>");
+///
+/// let html = "<p>Hello, world!</p>";
+/// assert!(parse_with(html, &options).is_err());
+/// assert_eq!(try_parse_with(html, &options).html(), "<p>Hello</p>");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum length of the input in bytes.
+    pub max_input_len: Option<usize>,
+    /// Maximum number of the elements open at the same time.
+    pub max_depth: Option<usize>,
+    /// Maximum number of the nodes, including the elements, the text and
+    /// the comments.
+    pub max_nodes: Option<usize>,
+    /// Maximum number of the attributes of an element.
+    pub max_attrs: Option<usize>,
+    /// Maximum length of an attribute value in bytes.
+    pub max_attr_len: Option<usize>,
+    /// Maximum length of a text, comment or CDATA section in bytes.
+    pub max_text_len: Option<usize>,
+}
+
+/// Which one of the [`Limits`] is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// [`Limits::max_input_len`]
+    InputLen,
+    /// [`Limits::max_depth`]
+    Depth,
+    /// [`Limits::max_nodes`]
+    Nodes,
+    /// [`Limits::max_attrs`]
+    Attrs,
+    /// [`Limits::max_attr_len`]
+    AttrLen,
+    /// [`Limits::max_text_len`]
+    TextLen,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Limit::InputLen => "Input length",
+            Limit::Depth => "Nesting depth",
+            Limit::Nodes => "Node count",
+            Limit::Attrs => "Attribute count",
+            Limit::AttrLen => "Attribute length",
+            Limit::TextLen => "Text length",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What to do with the duplicate attributes in a tag, like the second
+/// `class` in `<p class="a" class="b">`.
+///
+/// The names are compared after they are cased by [`NameCase`].
+///
+/// ```
+/// use html_editor::{parse_with, try_parse_with, DuplicateAttrs, ParseOptions};
+/// use html_editor::operation::*;
+///
+/// let html = r#"<p class="a" class="b"></p>"#;
+/// let options = ParseOptions {
+///     duplicate_attrs: DuplicateAttrs::Last,
+///     ..Default::default()
+/// };
+/// assert_eq!(parse_with(html, &options).unwrap().html(), r#"<p class="b"></p>"#);
+///
+/// let options = ParseOptions {
+///     duplicate_attrs: DuplicateAttrs::Error,
+///     ..Default::default()
+/// };
+/// assert!(parse_with(html, &options).is_err());
+/// assert_eq!(try_parse_with(html, &options).html(), r#"<p class="a"></p>"#);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateAttrs {
+    /// Keep the first one like browsers do.
+    #[default]
+    First,
+    /// Keep the last one.
+    Last,
+    /// Return [`ParseError::DuplicateAttribute`](crate::ParseError::DuplicateAttribute)
+    /// from [`parse()`](crate::parse). The fault tolerant parsers keep the
+    /// first one instead.
+    Error,
+}
+
+/// How the names of the tags and attributes are cased when parsing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameCase {
+    /// Keep the names as written, which is needed by XML.
+    #[default]
+    Preserve,
+    /// Lowercase the names like HTML parsers do, except the camelCase
+    /// names of SVG and MathML like `viewBox` and `foreignObject`.
+    ///
+    /// The camelCase names are only recognized in the SVG and MathML
+    /// elements, so `<div viewBox>` is lowercased.
+    Html,
+}
+
+impl NameCase {
+    /// Normalize the name of a tag in the `namespace`.
+    pub(crate) fn tag(self, name: &mut Cow<str>, namespace: &Namespace) {
+        match namespace {
+            Namespace::Svg => normalize(self, name, &SVG_CAMEL_CASE_TAGS),
+            _ => normalize(self, name, &[]),
+        }
+    }
+
+    /// Normalize the name of an attribute of an element in the
+    /// `namespace`.
+    pub(crate) fn attr(self, name: &mut Cow<str>, namespace: &Namespace) {
+        match namespace {
+            // `definitionURL` is the only camelCase one of MathML.
+            Namespace::MathMl => normalize(self, name, &["definitionURL"]),
+            Namespace::Svg if name.eq_ignore_ascii_case("definitionURL") => {
+                normalize(self, name, &[])
+            }
+            Namespace::Svg => normalize(self, name, &CAMEL_CASE_ATTRS),
+            _ => normalize(self, name, &[]),
+        }
+    }
+}
+
+/// Normalize the name, which is only copied if it is changed.
+fn normalize(case: NameCase, name: &mut Cow<str>, camel_case: &[&'static str]) {
+    if case == NameCase::Preserve {
+        return;
+    }
+    let found = camel_case.binary_search_by(|camel| {
+        camel
+            .bytes()
+            .map(|b| b.to_ascii_lowercase())
+            .cmp(name.bytes().map(|b| b.to_ascii_lowercase()))
+    });
+    match found {
+        Ok(index) if *name != camel_case[index] => *name = Cow::Borrowed(camel_case[index]),
+        Ok(_) => {}
+        Err(_) if name.bytes().any(|b| b.is_ascii_uppercase()) => {
+            name.to_mut().make_ascii_lowercase()
+        }
+        Err(_) => {}
+    }
+}
@@ -0,0 +1,170 @@
+use std::io::{self, Read};
+
+use super::builder::{DomBuilder, OwnedDom};
+use super::token::Located;
+use super::tokenizer::Tokenizer;
+use super::{ParseError, ParseOptions};
+use crate::Node;
+
+/// Incremental parser which takes the html by chunks, so that the whole
+/// input never needs to be in memory at once.
+///
+/// The tokens are added to the DOM as soon as they are read, and the
+/// result is the same as [`parse()`](crate::parse), or
+/// [`try_parse()`](crate::try_parse) if created by
+/// [`Parser::fault_tolerant()`].
+///
+/// ```
+/// use html_editor::{operation::Htmlifiable, Parser};
+///
+/// let mut parser = Parser::new();
+/// parser.feed("<div><p>Hello, ").unwrap();
+/// parser.feed_bytes("wörld!</p".as_bytes()).unwrap();
+/// parser.feed("></div>").unwrap();
+/// let dom = parser.finish().unwrap();
+/// assert_eq!(dom.html(), "<div><p>Hello, wörld!</p></div>");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Parser {
+    tokenizer: Tokenizer<'static>,
+    builder: DomBuilder<'static>,
+    /// The bytes at the end of the last chunk which are not a complete
+    /// UTF-8 char yet.
+    pending_bytes: Vec<u8>,
+    /// The first error, after which the rest of the input is ignored.
+    error: Option<ParseError>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    /// Create a parser which returns errors like [`parse()`](crate::parse).
+    pub fn new() -> Self {
+        Self::with_options(ParseOptions::default(), false)
+    }
+
+    /// Create a parser which fixes the illegal html like
+    /// [`try_parse()`](crate::try_parse).
+    pub fn fault_tolerant() -> Self {
+        Self::with_options(ParseOptions::default(), true)
+    }
+
+    /// Create a parser with the [`ParseOptions`].
+    pub fn with_options(options: ParseOptions, fault_tolerant: bool) -> Self {
+        Parser {
+            tokenizer: Tokenizer::new(&options, fault_tolerant),
+            builder: DomBuilder::new(OwnedDom, fault_tolerant).with_options(&options),
+            pending_bytes: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Parse the next chunk of html.
+    ///
+    /// A chunk can end anywhere, even in the middle of a tag.
+    ///
+    /// Returns the error as soon as it is found, and the same error for
+    /// the following chunks. The fault tolerant parsers never return
+    /// errors.
+    pub fn feed(&mut self, html: &str) -> Result<(), ParseError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let mut tokens = Vec::new();
+        let result = self
+            .tokenizer
+            .feed(html, &mut tokens)
+            .and_then(|_| self.build(tokens));
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                self.error = Some(error.clone());
+                Err(error)
+            }
+        }
+    }
+
+    /// Parse the next chunk of UTF-8 bytes.
+    ///
+    /// A chunk can end in the middle of a char, which will be completed by
+    /// the next chunk. The invalid bytes are replaced with `U+FFFD`, and
+    /// the spans are the offsets in the replaced text.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) -> Result<(), ParseError> {
+        self.pending_bytes.extend_from_slice(bytes);
+        let bytes = std::mem::take(&mut self.pending_bytes);
+        let mut rest = bytes.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(text) => return self.feed(text),
+                Err(error) => {
+                    let (valid, invalid) = rest.split_at(error.valid_up_to());
+                    // The valid part has been checked above.
+                    self.feed(std::str::from_utf8(valid).unwrap_or_default())?;
+                    match error.error_len() {
+                        Some(len) => {
+                            self.feed("\u{FFFD}")?;
+                            rest = &invalid[len..];
+                        }
+                        // It may be completed by the next chunk.
+                        None => {
+                            self.pending_bytes = invalid.to_vec();
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parse all the UTF-8 bytes from the reader.
+    ///
+    /// The parse errors are returned as [`io::ErrorKind::InvalidData`].
+    ///
+    /// ```
+    /// use html_editor::{operation::Htmlifiable, Parser};
+    ///
+    /// let file: &[u8] = b"<ul><li>1</li><li>2</li></ul>";
+    /// let mut parser = Parser::new();
+    /// parser.read_from(file).unwrap();
+    /// assert_eq!(parser.finish().unwrap().html(), "<ul><li>1</li><li>2</li></ul>");
+    /// ```
+    pub fn read_from(&mut self, mut reader: impl Read) -> io::Result<()> {
+        let mut buffer = [0; 8192];
+        loop {
+            let len = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(len) => len,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            self.feed_bytes(&buffer[..len])
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        }
+    }
+
+    /// Finish parsing and get the nodes.
+    pub fn finish(mut self) -> Result<Vec<Node>, ParseError> {
+        if !self.pending_bytes.is_empty() {
+            self.pending_bytes.clear();
+            self.feed("\u{FFFD}")?;
+        }
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut tokens = Vec::new();
+        self.tokenizer.finish(&mut tokens)?;
+        self.build(tokens)?;
+        self.builder.finish()
+    }
+
+    fn build(&mut self, tokens: Vec<Located<'static>>) -> Result<(), ParseError> {
+        for token in tokens {
+            self.builder.push(token)?;
+        }
+        Ok(())
+    }
+}
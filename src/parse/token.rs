@@ -1,139 +1,469 @@
-use crate::parse::attrs;
-use crate::{Doctype, Element, Node};
+use std::borrow::Cow;
+use std::ops::Range;
 
+use crate::parse::{attrs, DuplicateAttrs, NameCase, ParseError, Position};
+use crate::{ConditionalBody, Doctype, Element, ElementSpan, Namespace, Node, Span};
+
+/// A token whose names and text borrow from the source, unless they are
+/// changed like the decoded character references and the lowercased
+/// names.
 #[derive(Debug, Clone)]
-pub enum Token {
+pub enum Token<'a> {
     /// Like `<div>`, including `<img>`, `<input>`, etc.
-    Start(String, Vec<(String, String)>),
+    ///
+    /// The spans are of the attributes.
+    Start(Cow<'a, str>, Vec<(Cow<'a, str>, Cow<'a, str>)>, Vec<Span>),
     /// Like `</div>`
-    End(String),
+    End(Cow<'a, str>),
     /// Like `<div />`
-    Closing(String, Vec<(String, String)>),
+    ///
+    /// The spans are of the attributes.
+    Closing(Cow<'a, str>, Vec<(Cow<'a, str>, Cow<'a, str>)>, Vec<Span>),
     /// Like `<!doctype html>`
     Doctype(Doctype),
     /// Like `<!-- comment -->`
-    Comment(String),
+    Comment(Cow<'a, str>),
+    /// Like `<![CDATA[ text ]]>`
+    CData(Cow<'a, str>),
+    /// Like `<?xml-stylesheet href="a.css"?>`, with the target and data
+    ProcessingInstruction(Cow<'a, str>, Cow<'a, str>),
+    /// Like `<![if !mso]>`, with the text between `<!` and `>`
+    DownlevelRevealed(Cow<'a, str>),
     /// Any text
-    Text(String),
+    Text(Cow<'a, str>),
+    /// Like `{{ value }}`, with the delimiters
+    Passthrough(Cow<'a, str>),
 }
 
-impl Token {
-    pub fn from(tag: String) -> Result<Self, String> {
-        if tag.ends_with("/>") {
-            let tag_name_start = tag[1..tag.len()]
-                .chars()
-                .position(|x| x != ' ')
-                .expect("tag name cannot be all spaces after \"<\"")
-                + 1;
-            let tag_name_end_option = tag[tag_name_start..tag.len()]
-                .chars()
-                .position(|x| x == ' ');
-            let tag_name_end = match tag_name_end_option {
-                Some(end) => end + tag_name_start,
-                None => tag.len() - 2,
-            };
-            let tag_name = tag[tag_name_start..tag_name_end].to_string();
-            let attr_str = tag[tag_name_end..tag.len() - 2].trim().to_string();
-            Ok(Self::Closing(tag_name, attrs::parse(attr_str)))
-        } else if tag.starts_with("</") {
-            Ok(Self::End(tag[2..tag.len() - 1].trim().to_string()))
+impl<'a> Token<'a> {
+    /// Parse the `tag` text, which starts at `position` of the source.
+    ///
+    /// The malformed attributes are fixed like browsers do, and the error
+    /// about them is returned along with the token, see [`attrs::parse()`].
+    pub fn from(
+        tag: &'a str,
+        position: Position,
+        passthrough: &[(String, String)],
+    ) -> Result<(Self, Option<ParseError>), ParseError> {
+        let mut malformed = None;
+        // `attr_str` must be a substring of `tag`, so that we can know
+        // where the attributes are.
+        let mut parse_attrs = |attr_str: &'a str| {
+            let offset = (attr_str.as_ptr() as usize)
+                .checked_sub(tag.as_ptr() as usize)
+                .filter(|offset| *offset <= tag.len())
+                .unwrap_or(0)
+                + position.offset;
+            let (attrs, reason) = attrs::parse(attr_str, offset, passthrough);
+            if let Some(reason) = reason {
+                malformed = Some(ParseError::MalformedAttribute {
+                    tag: tag.to_string(),
+                    reason,
+                    position,
+                });
+            }
+            attrs
+        };
+        let Some(content) = tag.strip_prefix('<') else {
+            return Err(ParseError::InvalidTag {
+                tag: tag.to_string(),
+                position,
+            });
+        };
+        let token = if let Some(content) = content.strip_prefix('/') {
+            // Like browsers, only the name of an end tag matters, and the
+            // rest like the `/` in `</div/>` is ignored.
+            let content = content.strip_suffix('>').unwrap_or(content);
+            Self::End(Cow::Borrowed(split_tag_name(content).0))
+        } else if let Some(content) = content.strip_suffix("/>") {
+            let (tag_name, attr_str) = split_tag_name(content);
+            let (attrs, spans) = parse_attrs(attr_str.trim());
+            Self::Closing(Cow::Borrowed(tag_name), attrs, spans)
         } else if tag.starts_with("<!--") {
-            Ok(Self::from_comment(tag))
-        } else if tag.starts_with("<!") {
-            Ok(Self::Doctype(Doctype::Html))
-        } else if tag.starts_with("<?") {
-            let attr = tag[2..tag.len() - 2].to_string();
-            let attr = attrs::parse(attr);
-            let version = attr
-                .iter()
-                .find(|(name, _)| name == "version")
-                .expect("cannot find version attribute in xml declaration")
-                .1
-                .to_string();
-            let encoding = attr
-                .iter()
-                .find(|(name, _)| name == "encoding")
-                .expect("cannot find encoding attribute in xml declaration")
-                .1
-                .to_string();
-            Ok(Self::Doctype(Doctype::Xml { version, encoding }))
-        } else if tag.starts_with('<') {
-            let tag_name_start = tag[1..tag.len()]
-                .chars()
-                .position(|x| !x.is_ascii_whitespace())
-                .expect("tag name cannot be all spaces after \"<\"")
-                + 1;
-            let tag_name_end_option = tag[tag_name_start..tag.len()]
-                .chars()
-                .position(|x| x.is_ascii_whitespace());
-            let tag_name_end = match tag_name_end_option {
-                Some(end) => end + tag_name_start,
-                None => tag.len() - 1,
+            Self::from_comment(tag)
+        } else if let Some(text) = content.strip_prefix("![CDATA[") {
+            Self::CData(Cow::Borrowed(text.strip_suffix("]]>").unwrap_or(text)))
+        } else if let Some(content) = content.strip_prefix('!') {
+            let content = content.strip_suffix('>').unwrap_or(content);
+            let is_doctype = content
+                .get(..7)
+                .is_some_and(|keyword| keyword.eq_ignore_ascii_case("doctype"));
+            if is_doctype {
+                Self::Doctype(parse_doctype(content))
+            } else if is_downlevel_revealed(content) {
+                Self::DownlevelRevealed(Cow::Borrowed(content))
+            } else {
+                // The other markup declarations like `<!ELEMENT br EMPTY>`
+                // are bogus comments.
+                Self::Comment(Cow::Borrowed(content))
+            }
+        } else if let Some(content) = content.strip_prefix('?') {
+            let content = content.strip_suffix("?>").unwrap_or(content);
+            // The data is kept with the whitespace after the target, so
+            // that it is written back as is.
+            let (target, data) = match content.find(|ch: char| ch.is_ascii_whitespace()) {
+                Some(index) => content.split_at(index),
+                None => content.split_at(content.len()),
+            };
+            let declaration = match target {
+                "xml" => xml_declaration(&parse_attrs(data.trim_start()).0, tag),
+                _ => None,
             };
-            let tag_name = tag[tag_name_start..tag_name_end].to_string();
-            let attr_str = tag[tag_name_end..tag.len() - 1].trim().to_string();
-            Ok(Self::Start(tag_name, attrs::parse(attr_str)))
+            // Without the version, it is not a valid XML declaration
+            // and is kept as a processing instruction.
+            match declaration {
+                Some(doctype) => Self::Doctype(doctype),
+                None => Self::ProcessingInstruction(Cow::Borrowed(target), Cow::Borrowed(data)),
+            }
         } else {
-            Err(format!("Invalid tag: {}", tag))
+            let content = content.strip_suffix('>').unwrap_or(content);
+            let (tag_name, attr_str) = split_tag_name(content);
+            let (attrs, spans) = parse_attrs(attr_str.trim());
+            Self::Start(Cow::Borrowed(tag_name), attrs, spans)
+        };
+        Ok((token, malformed))
+    }
+
+    /// Normalize the names of the tag and its attributes, which are in
+    /// the `namespace`, except the opaque fragments.
+    pub fn normalize_case(
+        &mut self,
+        case: NameCase,
+        namespace: &Namespace,
+        passthrough: &[(String, String)],
+    ) {
+        match self {
+            Self::Start(name, attrs, _) | Self::Closing(name, attrs, _) => {
+                case.tag(name, namespace);
+                for (key, _) in attrs {
+                    if !attrs::is_opaque(key, passthrough) {
+                        case.attr(key, namespace);
+                    }
+                }
+            }
+            Self::End(name) => case.tag(name, namespace),
+            _ => {}
         }
     }
 
-    #[inline]
-    pub fn from_comment(comment: String) -> Self {
-        Self::Comment(comment[4..comment.len() - 3].to_string())
+    /// Remove the duplicate attributes by the `policy`, and return the
+    /// name of the first duplicate one if any.
+    pub fn dedup_attrs(
+        &mut self,
+        policy: DuplicateAttrs,
+        passthrough: &[(String, String)],
+    ) -> Option<String> {
+        match self {
+            Self::Start(_, attrs, spans) | Self::Closing(_, attrs, spans) => {
+                attrs::dedup(attrs, spans, policy, passthrough)
+            }
+            _ => None,
+        }
     }
 
-    pub fn node(&self) -> Node {
-        self.clone().into_node()
+    #[inline]
+    pub fn from_comment(comment: &'a str) -> Self {
+        let comment = comment.strip_prefix("<!--").unwrap_or(comment);
+        // The empty comments may be closed abruptly, like `<!-->`.
+        let comment = match comment {
+            ">" | "->" => "",
+            _ => comment.strip_suffix("-->").unwrap_or(comment),
+        };
+        Self::Comment(Cow::Borrowed(comment))
     }
 
-    pub fn into_node(self) -> Node {
+    /// Copy the names and text borrowed from the source.
+    pub fn into_owned(self) -> Token<'static> {
+        let owned = |text: Cow<str>| Cow::Owned(text.into_owned());
+        let owned_attrs = |attrs: Vec<(Cow<str>, Cow<str>)>| {
+            attrs
+                .into_iter()
+                .map(|(key, value)| (owned(key), owned(value)))
+                .collect()
+        };
         match self {
-            Self::Start(name, attrs) => Element {
-                name,
-                attrs,
-                children: Vec::new(),
+            Token::Start(name, attrs, spans) => {
+                Token::Start(owned(name), owned_attrs(attrs), spans)
             }
-            .into_node(),
-
-            Self::End(name) => Element {
-                name,
-                attrs: Vec::new(),
-                children: Vec::new(),
+            Token::End(name) => Token::End(owned(name)),
+            Token::Closing(name, attrs, spans) => {
+                Token::Closing(owned(name), owned_attrs(attrs), spans)
             }
-            .into_node(),
-
-            Self::Closing(name, attrs) => Element {
-                name,
-                attrs,
-                children: Vec::new(),
+            Token::Doctype(doctype) => Token::Doctype(doctype),
+            Token::Comment(text) => Token::Comment(owned(text)),
+            Token::CData(text) => Token::CData(owned(text)),
+            Token::ProcessingInstruction(target, data) => {
+                Token::ProcessingInstruction(owned(target), owned(data))
             }
-            .into_node(),
+            Token::DownlevelRevealed(text) => Token::DownlevelRevealed(owned(text)),
+            Token::Text(text) => Token::Text(owned(text)),
+            Token::Passthrough(raw) => Token::Passthrough(owned(raw)),
+        }
+    }
+}
+
+/// Split the content of a tag like `div id="app"` into the name and the
+/// attributes, which are separated by whitespace or `/`.
+fn split_tag_name(content: &str) -> (&str, &str) {
+    let content = content.trim_start();
+    match content.find(|ch: char| ch.is_ascii_whitespace() || ch == '/') {
+        Some(index) => content.split_at(index),
+        None => content.split_at(content.len()),
+    }
+}
 
-            Self::Doctype(doctype) => Node::Doctype(doctype),
-            Self::Comment(comment) => Node::Comment(comment),
-            Self::Text(text) => Node::Text(text),
+/// Parse the content of `<!DOCTYPE ...>` after the `<!`.
+fn parse_doctype(content: &str) -> Doctype {
+    let rest = content.get(7..).unwrap_or("").trim_start();
+    let name_end = rest
+        .find(|ch: char| ch.is_ascii_whitespace())
+        .unwrap_or(rest.len());
+    let name = &rest[..name_end];
+    let rest = rest[name_end..].trim_start();
+
+    let keyword = rest.get(..6).unwrap_or("");
+    let (public_id, system_id) = if keyword.eq_ignore_ascii_case("public") {
+        match quoted(rest[6..].trim_start()) {
+            Some((public_id, rest)) => (Some(public_id), quoted(rest).map(|(id, _)| id)),
+            None => (None, None),
+        }
+    } else if keyword.eq_ignore_ascii_case("system") {
+        (None, quoted(rest[6..].trim_start()).map(|(id, _)| id))
+    } else {
+        (None, None)
+    };
+
+    if name.eq_ignore_ascii_case("html") && public_id.is_none() && system_id.is_none() {
+        Doctype::Html
+    } else {
+        Doctype::Dtd {
+            name: name.to_string(),
+            public_id,
+            system_id,
         }
     }
+}
 
-    pub fn into_element(self) -> Element {
-        match self {
-            Self::Start(name, attrs) => Element {
-                name,
-                attrs,
-                children: Vec::new(),
-            },
-            Self::End(name) => Element {
-                name,
-                attrs: Vec::new(),
-                children: Vec::new(),
+/// Get the XML declaration from the `attrs` of `<?xml ...?>`, which is
+/// written as `raw`, or `None` if it does not have the version.
+fn xml_declaration(attrs: &[(Cow<str>, Cow<str>)], raw: &str) -> Option<Doctype> {
+    let get = |key: &str| {
+        attrs
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.to_string())
+    };
+    Some(Doctype::Xml {
+        version: get("version")?,
+        encoding: get("encoding"),
+        standalone: get("standalone"),
+        raw: Some(raw.to_string()),
+    })
+}
+
+/// Parse the XML declaration written as `raw`, like
+/// `<?xml version='1.0'?>`.
+pub fn parse_xml_declaration(raw: &str) -> Option<Doctype> {
+    let data = raw.strip_prefix("<?xml")?.strip_suffix("?>")?;
+    if !data.starts_with(|ch: char| ch.is_ascii_whitespace()) {
+        return None;
+    }
+    let ((attrs, _), _) = attrs::parse(data.trim_start(), 0, &[]);
+    xml_declaration(&attrs, raw)
+}
+
+/// Read a quoted identifier and the rest after it.
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|ch| *ch == '"' || *ch == '\'')?;
+    let text = &text[1..];
+    let end = text.find(quote).unwrap_or(text.len());
+    let rest = text.get(end + 1..).unwrap_or("").trim_start();
+    Some((text[..end].to_string(), rest))
+}
+
+/// A token and where it is in the source html.
+#[derive(Debug, Clone)]
+pub struct Located<'a> {
+    pub token: Token<'a>,
+    /// Position of the first char of the token.
+    pub start: Position,
+    /// Byte offset right after the last char of the token.
+    pub end: usize,
+    /// Namespace of the tag, which is HTML for the other tokens.
+    pub namespace: Namespace,
+    /// Indexes of the attributes of the tag whose values are kept as
+    /// written, see [`attrs::parse()`].
+    pub raw_attrs: Vec<usize>,
+}
+
+impl<'a> Located<'a> {
+    pub fn new(token: Token<'a>, start: Position, end: usize) -> Self {
+        Located {
+            token,
+            start,
+            end,
+            namespace: Namespace::Html,
+            raw_attrs: Vec::new(),
+        }
+    }
+
+    /// Set the namespace of the tag.
+    pub fn in_namespace(mut self, namespace: Namespace) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Find the attributes of the tag whose values have regions between
+    /// the `passthrough` delimiters.
+    pub fn with_raw_attrs(mut self, passthrough: &[(String, String)]) -> Self {
+        if let Token::Start(_, attrs, _) | Token::Closing(_, attrs, _) = &self.token {
+            self.raw_attrs = (0..attrs.len())
+                .filter(|i| attrs::is_raw(&attrs[*i].1, passthrough))
+                .collect();
+        }
+        self
+    }
+
+    /// Copy the names and text borrowed from the source, like
+    /// [`Token::into_owned()`].
+    pub fn into_owned(self) -> Located<'static> {
+        Located {
+            token: self.token.into_owned(),
+            start: self.start,
+            end: self.end,
+            namespace: self.namespace,
+            raw_attrs: self.raw_attrs,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.start.offset, self.end)
+    }
+
+    /// Convert the token into a node, which has the span of the token
+    /// if it is an element.
+    pub fn into_node(self) -> Node {
+        match self.token {
+            Token::Start(..) | Token::End(..) | Token::Closing(..) => {
+                self.into_element().into_node()
+            }
+            Token::Doctype(doctype) => Node::Doctype(doctype),
+            Token::Comment(_) => self.into_comment(None),
+            Token::CData(text) => Node::CData(text.into_owned()),
+            Token::ProcessingInstruction(target, data) => Node::ProcessingInstruction {
+                target: target.into_owned(),
+                data: data.into_owned(),
             },
-            Self::Closing(name, attrs) => Element {
-                name,
-                attrs,
-                children: Vec::new(),
+            Token::Text(text) => Node::Text(text.into_owned()),
+            Token::DownlevelRevealed(text) => Node::DownlevelRevealed(text.into_owned()),
+            Token::Passthrough(raw) => Node::Passthrough(raw.into_owned()),
+        }
+    }
+
+    /// Convert the comment into a node, which is a conditional comment
+    /// with the parsed `body` if any, or with the raw body otherwise.
+    pub fn into_comment(self, body: Option<Vec<Node>>) -> Node {
+        let Token::Comment(comment) = self.token else {
+            return self.into_node();
+        };
+        match split_conditional(&comment) {
+            Some((condition, range)) => Node::ConditionalComment {
+                condition: condition.to_string(),
+                body: match body {
+                    Some(nodes) => ConditionalBody::Nodes(nodes),
+                    None => ConditionalBody::Raw(comment[range].to_string()),
+                },
             },
-            _ => panic!("Cannot convert token to element"),
+            None => Node::Comment(comment.into_owned()),
         }
     }
+
+    /// Convert the start tag into an element without children.
+    ///
+    /// The span of the element only covers the start tag until it is
+    /// closed by [`close_element()`](close_element).
+    pub fn into_element(self) -> Element {
+        let span = self.span();
+        let namespace = self.namespace;
+        let (name, attrs, attr_spans) = match self.token {
+            Token::Start(name, attrs, spans) => (name, attrs, spans),
+            Token::End(name) => (name, Vec::new(), Vec::new()),
+            Token::Closing(name, attrs, spans) => (name, attrs, spans),
+            // Only the tags are converted into elements.
+            _ => (Cow::Borrowed(""), Vec::new(), Vec::new()),
+        };
+        let attrs: Vec<_> = attrs
+            .into_iter()
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        let raw_attrs = raw_attrs(&self.raw_attrs, &attrs);
+        let mut element = Element::from_parts(
+            name.into_owned(),
+            attrs,
+            Some(ElementSpan {
+                outer: span,
+                start_tag: span,
+                end_tag: None,
+                attrs: attr_spans,
+            }),
+        );
+        element.namespace = namespace;
+        element.raw_attrs = raw_attrs;
+        element
+    }
+}
+
+/// Split the text of a conditional comment like `[if mso]><br><![endif]`
+/// into the condition and the range of the body.
+pub fn split_conditional(comment: &str) -> Option<(&str, Range<usize>)> {
+    let rest = comment
+        .strip_prefix("[if")?
+        .strip_prefix(|ch: char| ch.is_ascii_whitespace())?;
+    let condition_len = rest.find("]>")?;
+    let start = comment.len() - rest.len() + condition_len + 2;
+    let end = comment.strip_suffix("<![endif]")?.len();
+    (start <= end).then(|| (rest[..condition_len].trim(), start..end))
+}
+
+/// Check if the text of a markup declaration, like `[if !mso]` of
+/// `<![if !mso]>`, is of a downlevel-revealed conditional comment.
+fn is_downlevel_revealed(content: &str) -> bool {
+    let is_if = content
+        .strip_prefix("[if")
+        .is_some_and(|rest| rest.starts_with(|ch: char| ch.is_ascii_whitespace()));
+    is_if && content.ends_with(']') || content == "[endif]"
+}
+
+/// Get the position of the body of the conditional comment starting at
+/// `start`, which is at `offset` of the comment text.
+pub fn body_start(start: Position, comment: &str, offset: usize) -> Position {
+    let mut position = start;
+    for ch in "<!--".chars().chain(comment[..offset].chars()) {
+        position.advance(ch);
+    }
+    position
+}
+
+/// Check if the element is a `<template>`, whose children are moved to
+/// its template content when it is built.
+pub fn is_template(name: &str, namespace: &Namespace) -> bool {
+    name == "template" && *namespace == Namespace::Html
+}
+
+/// Get the attributes at the `indexes` out of the `attrs` of a tag, like
+/// the ones whose values are kept as written.
+pub fn raw_attrs<T: Clone>(indexes: &[usize], attrs: &[T]) -> Vec<T> {
+    indexes
+        .iter()
+        .filter_map(|index| attrs.get(*index).cloned())
+        .collect()
+}
+
+/// Extend the span of `element` to `end`, which is the byte offset right
+/// after the element, with the span of its end tag if any.
+pub fn close_element(element: &mut Element, end: usize, end_tag: Option<Span>) {
+    if let Some(span) = &mut element.span {
+        span.outer.end = end;
+        span.end_tag = end_tag;
+    }
 }
@@ -0,0 +1,989 @@
+//! The state machine turning the chars of html into tokens, which can be
+//! fed by chunks.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::attrs;
+use super::foreign::Content;
+use super::token::{Located, Token};
+use super::{DuplicateAttrs, Limit, Limits, NameCase, ParseError, ParseOptions, Position};
+use crate::{
+    data::{self, TextContent},
+    escape::{decode, decode_xml},
+    Element, Namespace,
+};
+
+/// The namespace prefixes declared by an XML element with their URLs,
+/// and the namespace of the element.
+type XmlScope = (Vec<(String, String)>, Namespace);
+
+#[derive(Debug, Clone)]
+pub struct Tokenizer<'a> {
+    // The whole html if known, which the tokens borrow from instead of
+    // copying the chars
+    source: Option<&'a str>,
+    // Byte offset of `source` in the html
+    source_offset: usize,
+    name_case: NameCase,
+    duplicate_attrs: DuplicateAttrs,
+    // Whether to recover from the malformed tags instead of returning
+    // errors, which makes the tokenizer never fail
+    fault_tolerant: bool,
+    chars_stack: Vec<char>,
+    // Position of the char being handled
+    position: Position,
+    // Position of the first char in `chars_stack`
+    token_start: Position,
+    in_quotes: Option<char>,
+    // More precisely: is in angle brackets
+    in_brackets: bool,
+    // The length of the start and the end of the comment, CDATA section
+    // or processing instruction being read, like `<!--` and `-->`
+    section: Option<(usize, &'static [char])>,
+    // Name of the element whose content is being read as text
+    text_content: Option<(String, TextContent)>,
+    // Position of the last `<` in the text content, which may start the
+    // end tag, and its index in `chars_stack`
+    last_lt: Position,
+    last_lt_index: usize,
+    // The errors fixed by the fault tolerant tokenizer, if collected
+    diagnostics: Option<Vec<ParseError>>,
+    // The open SVG and MathML elements, which decide the namespaces of
+    // the tags, and whose content is never read as text
+    foreign: Vec<(String, Namespace, Content)>,
+    // How many elements of each name are in `foreign`, so that the end
+    // tags which close none of them don't scan it
+    foreign_counts: HashMap<String, usize>,
+    // The namespace prefixes declared by the open elements with their
+    // namespaces, if the input is XML
+    xmlns: Option<Vec<XmlScope>>,
+    limits: Limits,
+    // Whether the input after `max_input_len` is being ignored
+    truncated: bool,
+    // Position of the `<` at the end of `chars_stack`, which starts a tag
+    // if the next char is a letter, `/`, `!` or `?`
+    pending_lt: Option<Position>,
+    // The delimiters of the template syntax, which are not empty
+    passthrough: Vec<(String, String)>,
+    // The region of the template syntax being read
+    opaque: Option<Opaque>,
+}
+
+/// A region between the delimiters of [`ParseOptions::passthrough`].
+#[derive(Debug, Clone)]
+struct Opaque {
+    // Index of the start delimiter in `chars_stack`
+    index: usize,
+    // Length of the start delimiter in chars
+    start_len: usize,
+    end: Vec<char>,
+    // Whether the region is in a tag, where it is a part of the tag text
+    // instead of a token
+    in_tag: bool,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(options: &ParseOptions, fault_tolerant: bool) -> Self {
+        Tokenizer {
+            source: None,
+            source_offset: 0,
+            name_case: options.name_case,
+            duplicate_attrs: options.duplicate_attrs,
+            fault_tolerant,
+            chars_stack: Vec::new(),
+            position: Position::start(),
+            token_start: Position::start(),
+            in_quotes: None,
+            in_brackets: false,
+            section: None,
+            text_content: None,
+            last_lt: Position::start(),
+            last_lt_index: 0,
+            diagnostics: None,
+            foreign: Vec::new(),
+            foreign_counts: HashMap::new(),
+            xmlns: None,
+            limits: options.limits,
+            truncated: false,
+            pending_lt: None,
+            passthrough: options
+                .passthrough
+                .iter()
+                .filter(|(start, end)| !start.is_empty() && !end.is_empty())
+                .cloned()
+                .collect(),
+            opaque: None,
+        }
+    }
+
+    /// Read the input as XML, where every tag is generic, `<x/>` closes
+    /// any element and the namespaces are declared by `xmlns`.
+    pub fn xml(&mut self) {
+        self.xmlns = Some(Vec::new());
+    }
+
+    /// Read the html as the content of the `context` element, which is
+    /// text to the end if the content of `context` is text, like of
+    /// `<textarea>`.
+    pub fn in_context(&mut self, context: &Element) {
+        let namespace = context.namespace.clone();
+        if namespace == Namespace::Html {
+            // No end tag ends the text, as no start tag is read.
+            self.text_content = data::text_content(&context.name).map(|c| (String::new(), c));
+        } else {
+            let attrs = context.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+            let content = Content::of(&namespace, &context.name, attrs);
+            self.push_foreign(context.name.to_ascii_lowercase(), namespace, content);
+        }
+    }
+
+    /// Read the html as if it starts at `position` of the source, like
+    /// the body of a conditional comment.
+    pub fn starting_at(&mut self, position: Position) {
+        self.source_offset = position.offset;
+        self.position = position;
+        self.token_start = position;
+        self.last_lt = position;
+    }
+
+    /// Collect the errors fixed by the fault tolerant tokenizer, which
+    /// are taken by [`Tokenizer::take_diagnostics()`].
+    pub fn collect_diagnostics(&mut self) {
+        self.diagnostics.get_or_insert_with(Vec::new);
+    }
+
+    /// Take the diagnostics collected so far.
+    pub fn take_diagnostics(&mut self) -> Vec<ParseError> {
+        self.diagnostics
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Return the error, or keep it as a diagnostic if fault tolerant, in
+    /// which case the token has been fixed.
+    fn recover(&mut self, error: ParseError) -> Result<(), ParseError> {
+        if !self.fault_tolerant {
+            return Err(error);
+        }
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.push(error);
+        }
+        Ok(())
+    }
+
+    /// Get the text of the `chars` starting at the byte offset `start`,
+    /// which borrows from the source if it is known.
+    fn text(&self, chars: &[char], start: usize) -> Cow<'a, str> {
+        let len: usize = chars.iter().map(|ch| ch.len_utf8()).sum();
+        let text = self.source.and_then(|source| {
+            let start = start.checked_sub(self.source_offset)?;
+            source.get(start..start + len)
+        });
+        match text {
+            Some(text) => Cow::Borrowed(text),
+            None => Cow::Owned(String::from_iter(chars)),
+        }
+    }
+
+    /// Parse the text of a tag, comment or the like, which starts at
+    /// `start`, and get the namespace of the tag, or `None` if the tag is
+    /// dropped.
+    fn tag(
+        &mut self,
+        tag_text: Cow<'a, str>,
+        start: Position,
+    ) -> Result<Option<(Token<'a>, Namespace)>, ParseError> {
+        match tag_text {
+            Cow::Borrowed(tag_text) => self.read_tag(tag_text, start),
+            Cow::Owned(tag_text) => Ok(self
+                .read_tag(&tag_text, start)?
+                .map(|(tag, namespace)| (tag.into_owned(), namespace))),
+        }
+    }
+
+    /// Parse the `tag_text` like [`Tokenizer::tag()`], into the token
+    /// borrowing from it.
+    fn read_tag<'b>(
+        &mut self,
+        tag_text: &'b str,
+        start: Position,
+    ) -> Result<Option<(Token<'b>, Namespace)>, ParseError> {
+        // Like the HTML tokenizer, `</` without a letter after it is not
+        // an end tag: `</>` is dropped, and the others like `</ p>` are
+        // bogus comments.
+        let bogus = tag_text
+            .strip_prefix("</")
+            .filter(|rest| !rest.starts_with(|ch: char| ch.is_ascii_alphabetic()));
+        // Nothing but whitespace may follow the name of an end tag in XML.
+        let invalid_end_tag = tag_text.strip_prefix("</").is_some_and(|rest| {
+            let rest = rest.strip_suffix('>').unwrap_or(rest);
+            let name_len = rest
+                .find(|ch: char| ch.is_ascii_whitespace() || ch == '/')
+                .unwrap_or(rest.len());
+            bogus.is_some() || !rest[name_len..].trim().is_empty()
+        });
+        if invalid_end_tag && self.xmlns.is_some() {
+            return Err(ParseError::NotWellFormed {
+                reason: format!("Invalid end tag `{}`", tag_text),
+                position: start,
+            });
+        }
+        if let Some(rest) = bogus {
+            let comment = Cow::Borrowed(rest.strip_suffix('>').unwrap_or(rest));
+            self.recover(ParseError::InvalidTag {
+                tag: tag_text.to_string(),
+                position: start,
+            })?;
+            return Ok((!comment.is_empty()).then_some((Token::Comment(comment), Namespace::Html)));
+        }
+        let source = self.xmlns.is_some().then_some(tag_text);
+        let (mut tag, malformed) = Token::from(tag_text, start, &self.passthrough)?;
+        if let Some(error) = malformed {
+            self.recover(error)?;
+        }
+        let namespace = match source {
+            Some(source) => {
+                xml_attrs(&mut tag, source, start, &self.passthrough)?;
+                self.xml_namespace(&tag, start)?
+            }
+            None => self.namespace(&tag),
+        };
+        tag.normalize_case(self.name_case, &namespace, &self.passthrough);
+        if let Some(name) = tag.dedup_attrs(self.duplicate_attrs, &self.passthrough) {
+            // The duplicates are only errors by the policy, but always
+            // diagnostics as they are dropped.
+            if self.fault_tolerant || self.duplicate_attrs == DuplicateAttrs::Error {
+                self.recover(ParseError::DuplicateAttribute {
+                    name,
+                    position: start,
+                })?;
+            }
+        }
+        // Handle the HTML tags whose content is text
+        if let (Token::Start(tag_name, ..), Namespace::Html, None) = (&tag, &namespace, &self.xmlns)
+        {
+            self.text_content = data::text_content(tag_name).map(|c| (tag_name.to_string(), c));
+        }
+        Ok(Some((tag, namespace)))
+    }
+
+    /// Get the namespace of the XML tag, whose text is `source`, and keep
+    /// track of the namespace prefixes declared by the open elements.
+    ///
+    /// Returns an error if the tag uses a prefix which is not declared.
+    fn xml_namespace(&mut self, tag: &Token, start: Position) -> Result<Namespace, ParseError> {
+        let not_well_formed = |reason| ParseError::NotWellFormed {
+            reason,
+            position: start,
+        };
+        let passthrough = &self.passthrough;
+        let scopes = self.xmlns.get_or_insert_with(Vec::new);
+        let (name, attrs) = match tag {
+            Token::Start(name, attrs, _) | Token::Closing(name, attrs, _) => (name, attrs),
+            Token::End(_) => return Ok(scopes.pop().map_or(Namespace::Null, |(_, ns)| ns)),
+            _ => return Ok(Namespace::Html),
+        };
+
+        let mut prefixes = Vec::new();
+        for (key, value) in attrs {
+            if attrs::is_opaque(key, passthrough) {
+                continue;
+            }
+            if key == "xmlns" {
+                prefixes.push((String::new(), value.to_string()));
+            } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+                prefixes.push((prefix.to_string(), value.to_string()));
+            }
+        }
+        scopes.push((prefixes, Namespace::Null));
+
+        let resolve = |prefix: &str| match prefix {
+            "xml" => Some("http://www.w3.org/XML/1998/namespace"),
+            "xmlns" => Some("http://www.w3.org/2000/xmlns/"),
+            _ => scopes
+                .iter()
+                .rev()
+                .flat_map(|(prefixes, _)| prefixes.iter().rev())
+                .find(|(declared, _)| declared == prefix)
+                .map(|(_, url)| url.as_str()),
+        };
+        let undeclared = |prefix: &str| {
+            not_well_formed(format!("Namespace prefix `{}` is not declared", prefix))
+        };
+        // The attributes without prefixes are in no namespace, while the
+        // elements are in the default namespace.
+        for (key, _) in attrs {
+            if attrs::is_opaque(key, passthrough) {
+                continue;
+            }
+            if let Some((prefix, _)) = key.split_once(':') {
+                resolve(prefix).ok_or_else(|| undeclared(prefix))?;
+            }
+        }
+        let url = match name.split_once(':') {
+            Some((prefix, _)) => resolve(prefix).ok_or_else(|| undeclared(prefix))?,
+            None => resolve("").unwrap_or_default(),
+        };
+        let namespace = Namespace::from_url(url);
+
+        if matches!(tag, Token::Closing(..)) {
+            scopes.pop();
+        } else if let Some((_, open)) = scopes.last_mut() {
+            *open = namespace.clone();
+        }
+        Ok(namespace)
+    }
+
+    /// Get the namespace of the tag, and keep track of the open foreign
+    /// elements.
+    fn namespace(&mut self, tag: &Token) -> Namespace {
+        match tag {
+            Token::Start(name, attrs, _) | Token::Closing(name, attrs, _) => {
+                let attrs = || attrs.iter().map(|(k, v)| (&**k, &**v));
+                let content = |foreign: &[(String, Namespace, Content)]| {
+                    foreign
+                        .last()
+                        .map_or(Content::Html, |(_, _, content)| *content)
+                };
+                let namespace = match content(&self.foreign).child(name, attrs()) {
+                    Some(namespace) => namespace,
+                    // The tags like `<p>` close the foreign elements.
+                    None => {
+                        while self
+                            .foreign
+                            .last()
+                            .is_some_and(|(_, _, content)| content.is_foreign())
+                        {
+                            self.pop_foreign();
+                        }
+                        content(&self.foreign)
+                            .child(name, attrs())
+                            .unwrap_or_default()
+                    }
+                };
+                if namespace != Namespace::Html && matches!(tag, Token::Start(..)) {
+                    let content = Content::of(&namespace, name, attrs());
+                    self.push_foreign(name.to_ascii_lowercase(), namespace.clone(), content);
+                }
+                namespace
+            }
+            Token::End(_) if self.foreign.is_empty() => Namespace::Html,
+            Token::End(name) => {
+                let name = name.to_ascii_lowercase();
+                if self
+                    .foreign_counts
+                    .get(&name)
+                    .is_none_or(|count| *count == 0)
+                {
+                    return Namespace::Html;
+                }
+                // The end tag closes the elements in the one of its name.
+                loop {
+                    match self.pop_foreign() {
+                        Some((open, namespace)) if open == name => return namespace,
+                        Some(_) => {}
+                        None => return Namespace::Html,
+                    }
+                }
+            }
+            _ => Namespace::Html,
+        }
+    }
+
+    fn push_foreign(&mut self, name: String, namespace: Namespace, content: Content) {
+        *self.foreign_counts.entry(name.clone()).or_default() += 1;
+        self.foreign.push((name, namespace, content));
+    }
+
+    /// Pop the innermost open foreign element with its namespace.
+    fn pop_foreign(&mut self) -> Option<(String, Namespace)> {
+        let (name, namespace, _) = self.foreign.pop()?;
+        if let Some(count) = self.foreign_counts.get_mut(&name) {
+            *count -= 1;
+        }
+        Some((name, namespace))
+    }
+
+    /// Start the tag at the `<` at `lt`, and push the text before it to
+    /// `tokens`.
+    fn start_tag(&mut self, lt: Position, tokens: &mut Vec<Located<'a>>) -> Result<(), ParseError> {
+        self.in_brackets = true;
+        // In case of pushing empty text tokens to the stack
+        if !self.chars_stack.is_empty() {
+            let text = self.text(&self.chars_stack, self.token_start.offset);
+            self.chars_stack.clear();
+            tokens.push(Located::new(
+                Token::Text(self.decode_text(text, self.token_start)?),
+                self.token_start,
+                lt.offset,
+            ));
+        }
+        self.token_start = lt;
+        self.chars_stack.push('<');
+        Ok(())
+    }
+
+    /// Decode the character references in the text starting at `start`,
+    /// which must be well-formed in XML.
+    ///
+    /// The text is only copied if it has any reference.
+    fn decode_text(&self, text: Cow<'a, str>, start: Position) -> Result<Cow<'a, str>, ParseError> {
+        let decoded = if self.xmlns.is_none() {
+            decode(&text, false)
+        } else {
+            decode_xml(&text, false).map_err(|(reason, offset)| {
+                let mut position = start;
+                for ch in text[..offset].chars() {
+                    position.advance(ch);
+                }
+                ParseError::NotWellFormed { reason, position }
+            })?
+        };
+        Ok(match decoded {
+            Cow::Owned(decoded) => Cow::Owned(decoded),
+            Cow::Borrowed(_) => text,
+        })
+    }
+
+    /// Read the next chunk of html, and push the tokens completed by it
+    /// to `tokens`.
+    pub fn feed(&mut self, html: &str, tokens: &mut Vec<Located<'a>>) -> Result<(), ParseError> {
+        for ch in html.chars() {
+            self.feed_char(ch, tokens)?;
+        }
+        Ok(())
+    }
+
+    /// Read the next char of html, and push the tokens completed by it
+    /// to `tokens`.
+    pub fn feed_char(&mut self, ch: char, tokens: &mut Vec<Located<'a>>) -> Result<(), ParseError> {
+        if self.truncated {
+            return Ok(());
+        }
+        if let Some(max) = self.limits.max_input_len {
+            if self.position.offset + ch.len_utf8() > max {
+                self.recover(ParseError::LimitExceeded {
+                    limit: Limit::InputLen,
+                    max,
+                    position: self.position,
+                })?;
+                self.truncated = true;
+                return Ok(());
+            }
+        }
+        let len = tokens.len();
+        self.read_char(ch, tokens)?;
+        self.position.advance(ch);
+        for located in &mut tokens[len..] {
+            self.limit(located)?;
+        }
+        Ok(())
+    }
+
+    /// Check the attributes and the text of the token against the
+    /// limits, which are cut if fault tolerant.
+    fn limit(&mut self, located: &mut Located<'a>) -> Result<(), ParseError> {
+        let limits = self.limits;
+        let position = located.start;
+        let exceeded = |limit, max| ParseError::LimitExceeded {
+            limit,
+            max,
+            position,
+        };
+        match &mut located.token {
+            Token::Start(_, attrs, spans) | Token::Closing(_, attrs, spans) => {
+                if let Some(max) = limits.max_attrs.filter(|max| attrs.len() > *max) {
+                    self.recover(exceeded(Limit::Attrs, max))?;
+                    attrs.truncate(max);
+                    spans.truncate(max);
+                }
+                if let Some(max) = limits.max_attr_len {
+                    for (_, value) in attrs.iter_mut().filter(|(_, value)| value.len() > max) {
+                        self.recover(exceeded(Limit::AttrLen, max))?;
+                        truncate(value, max);
+                    }
+                }
+            }
+            Token::Text(text)
+            | Token::Comment(text)
+            | Token::CData(text)
+            | Token::ProcessingInstruction(_, text)
+            | Token::DownlevelRevealed(text)
+            | Token::Passthrough(text) => {
+                if let Some(max) = limits.max_text_len.filter(|max| text.len() > *max) {
+                    self.recover(exceeded(Limit::TextLen, max))?;
+                    truncate(text, max);
+                }
+            }
+            Token::End(_) | Token::Doctype(_) => {}
+        }
+        Ok(())
+    }
+
+    fn read_char(&mut self, ch: char, tokens: &mut Vec<Located<'a>>) -> Result<(), ParseError> {
+        let chars_stack = &mut self.chars_stack;
+        let position = self.position;
+        if chars_stack.is_empty() {
+            self.token_start = position;
+        }
+        let token_start = self.token_start;
+
+        if let Some(opaque) = &self.opaque {
+            chars_stack.push(ch);
+            // The delimiters cannot overlap, like `{{}}`.
+            if chars_stack.len() >= opaque.index + opaque.start_len + opaque.end.len()
+                && ends_with(chars_stack, &opaque.end)
+            {
+                if !opaque.in_tag {
+                    let raw = self.text(&self.chars_stack, token_start.offset);
+                    self.chars_stack.clear();
+                    let end = position.offset + ch.len_utf8();
+                    tokens.push(Located::new(Token::Passthrough(raw), token_start, end));
+                }
+                self.opaque = None;
+            }
+            return Ok(());
+        }
+        if self.start_opaque(ch, tokens) {
+            return Ok(());
+        }
+        let chars_stack = &mut self.chars_stack;
+
+        if let Some(quote) = self.in_quotes {
+            if ch == quote {
+                self.in_quotes = None;
+            }
+            chars_stack.push(ch);
+        } else if let Some((start_len, end)) = self.section {
+            chars_stack.push(ch);
+
+            // The start and the end cannot overlap, except the empty
+            // comments `<!-->` and `<!--->`.
+            let empty_comment = start_len == 4
+                && (chars_stack == &['<', '!', '-', '-', '>']
+                    || chars_stack == &['<', '!', '-', '-', '-', '>']);
+            if empty_comment
+                || chars_stack.len() >= start_len + end.len() && ends_with(chars_stack, end)
+            {
+                let tag_text = self.text(&self.chars_stack, token_start.offset);
+                self.chars_stack.clear();
+                if let Some((tag, namespace)) = self.tag(tag_text, token_start)? {
+                    let located = Located::new(tag, token_start, position.offset + 1)
+                        .with_raw_attrs(&self.passthrough);
+                    tokens.push(located.in_namespace(namespace));
+                }
+                self.section = None;
+                self.in_brackets = false;
+            }
+        } else if let Some((tag_name, content)) = &self.text_content {
+            if ch == '<' {
+                self.last_lt = position;
+                self.last_lt_index = chars_stack.len();
+            }
+            chars_stack.push(ch);
+
+            let end_tag_len = match content {
+                TextContent::Plaintext => None,
+                // No end tag ends the text content of the fragment context.
+                _ if tag_name.is_empty() => None,
+                // Only the chars from the last `<` may be the end tag, so
+                // the text before it is never read again.
+                _ if ch == '>' => chars_stack
+                    .get(self.last_lt_index..)
+                    .and_then(|tag| end_tag_len(tag, tag_name)),
+                _ => None,
+            };
+            if let Some(end_tag_len) = end_tag_len {
+                let len = chars_stack.len();
+                let mut text =
+                    self.text(&self.chars_stack[..len - end_tag_len], token_start.offset);
+                if *content == TextContent::Escapable {
+                    text = self.decode_text(text, token_start)?;
+                }
+                self.chars_stack.clear();
+                let end_tag_start = self.last_lt;
+                if !text.is_empty() {
+                    tokens.push(Located::new(
+                        Token::Text(text),
+                        token_start,
+                        end_tag_start.offset,
+                    ));
+                }
+                // The end tag has the name of the start tag.
+                let tag_name = self.text_content.take().map(|(name, _)| name);
+                tokens.push(Located::new(
+                    Token::End(Cow::Owned(tag_name.unwrap_or_default())),
+                    end_tag_start,
+                    position.offset + 1,
+                ));
+            }
+        } else {
+            // Like the HTML tokenizer, a `<` only starts a tag if it is
+            // followed by a letter, `/`, `!` or `?`, and is text
+            // otherwise, like `a < b`.
+            if let Some(lt) = self.pending_lt.take() {
+                if ch.is_ascii_alphabetic() || matches!(ch, '/' | '!' | '?') {
+                    self.chars_stack.pop();
+                    self.start_tag(lt, tokens)?;
+                }
+            }
+            // In XML, a tag is always closed before the next `<`, and its
+            // name follows the `<` right away.
+            if self.xmlns.is_some() && self.in_brackets {
+                let reason = if ch == '<' {
+                    Some(format!(
+                        "Tag `{}` is not closed",
+                        String::from_iter(&self.chars_stack)
+                    ))
+                } else if self.chars_stack == ['<']
+                    && !(ch.is_alphabetic() || matches!(ch, '_' | ':' | '/' | '!' | '?'))
+                {
+                    Some("`<` is not escaped".to_string())
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    return Err(ParseError::NotWellFormed {
+                        reason,
+                        position: token_start,
+                    });
+                }
+            }
+            let chars_stack = &mut self.chars_stack;
+            match ch {
+                // Every `<` starts a tag in XML.
+                '<' if self.xmlns.is_some() => self.start_tag(position, tokens)?,
+                // A `<` in a tag is a part of it, like `<img alt=1<2>`.
+                '<' if self.in_brackets => chars_stack.push(ch),
+                '<' => {
+                    chars_stack.push(ch);
+                    self.pending_lt = Some(position);
+                }
+                // A `>` outside of the tags is text, like `a > b`.
+                '>' if !self.in_brackets => chars_stack.push(ch),
+                '>' => {
+                    self.in_brackets = false;
+                    chars_stack.push(ch);
+                    // Get the text of the chars in `chars_stack` and
+                    // clean the chars stack.
+                    let tag_text = self.text(&self.chars_stack, token_start.offset);
+                    self.chars_stack.clear();
+                    // Push the tag with the text we just got to the token stack.
+                    if let Some((tag, namespace)) = self.tag(tag_text, token_start)? {
+                        let located = Located::new(tag, token_start, position.offset + 1)
+                            .with_raw_attrs(&self.passthrough);
+                        tokens.push(located.in_namespace(namespace));
+                    }
+                }
+                '-' => {
+                    chars_stack.push(ch);
+                    if chars_stack == &['<', '!', '-', '-'] {
+                        self.section = Some((4, &['-', '-', '>']));
+                    }
+                }
+                _ => {
+                    // The quotes only matter at the start of the attribute
+                    // values, like `<a title="a > b">` but not `<a b=c'd>`.
+                    let starts_value = || {
+                        chars_stack
+                            .iter()
+                            .rev()
+                            .find(|ch| !ch.is_ascii_whitespace())
+                            .is_some_and(|ch| *ch == '=')
+                    };
+                    if self.in_brackets && (ch == '\'' || ch == '"') && starts_value() {
+                        self.in_quotes = Some(ch);
+                    }
+                    chars_stack.push(ch);
+                    if chars_stack == &['<', '?'] {
+                        self.section = Some((2, &['?', '>']));
+                    } else if chars_stack == &['<', '!', '[', 'C', 'D', 'A', 'T', 'A', '['] {
+                        self.section = Some((9, &[']', ']', '>']));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start a region of the template syntax if `ch` completes its start
+    /// delimiter, and push the text before it to `tokens`.
+    ///
+    /// The regions are not read in the text content like of `<script>`,
+    /// nor in the comments, CDATA sections and processing instructions
+    /// unless they start with the delimiter, like `<?php`.
+    fn start_opaque(&mut self, ch: char, tokens: &mut Vec<Located<'a>>) -> bool {
+        if self.text_content.is_some() {
+            return false;
+        }
+        let chars_stack = &self.chars_stack;
+        let Some((start, end)) = self.passthrough.iter().find(|(start, _)| {
+            ends_with_delimiter(chars_stack, ch, start)
+                && (self.section.is_none() || chars_stack.len() + 1 == start.chars().count())
+        }) else {
+            return false;
+        };
+        let start_len = start.chars().count();
+        let index = chars_stack.len() + 1 - start_len;
+        let in_tag = self.in_brackets && index > 0;
+        let mut opaque = Opaque {
+            index,
+            start_len,
+            end: end.chars().collect(),
+            in_tag,
+        };
+        self.pending_lt = None;
+        if !in_tag {
+            self.in_brackets = false;
+            self.in_quotes = None;
+            self.section = None;
+            if index > 0 {
+                let text = self.text(&self.chars_stack[..index], self.token_start.offset);
+                self.chars_stack.drain(..index);
+                let mut start = self.token_start;
+                for ch in text.chars() {
+                    start.advance(ch);
+                }
+                let text = match decode(&text, false) {
+                    Cow::Owned(decoded) => Cow::Owned(decoded),
+                    Cow::Borrowed(_) => text,
+                };
+                tokens.push(Located::new(
+                    Token::Text(text),
+                    self.token_start,
+                    start.offset,
+                ));
+                self.token_start = start;
+                opaque.index = 0;
+            }
+        }
+        self.chars_stack.push(ch);
+        self.opaque = Some(opaque);
+        true
+    }
+
+    /// Push the text left at the end of the html to `tokens`.
+    pub fn finish(&mut self, tokens: &mut Vec<Located<'a>>) -> Result<(), ParseError> {
+        // The region not closed takes the rest of the html.
+        if let Some(Opaque { in_tag: false, .. }) = self.opaque.take() {
+            let raw = self.text(&self.chars_stack, self.token_start.offset);
+            self.chars_stack.clear();
+            let mut located = Located::new(
+                Token::Passthrough(raw),
+                self.token_start,
+                self.position.offset,
+            );
+            self.limit(&mut located)?;
+            tokens.push(located);
+        }
+        // Like browsers, the tag whose quoted attribute value is not
+        // terminated is dropped, instead of taking the rest of the html.
+        if let Some(quote) = self.in_quotes.take() {
+            let tag = String::from_iter(std::mem::take(&mut self.chars_stack));
+            let value = tag.rfind(quote).map_or("", |index| &tag[index + 1..]);
+            let reason = format!("Unterminated attribute value `{}`", value);
+            self.recover(ParseError::MalformedAttribute {
+                tag,
+                reason,
+                position: self.token_start,
+            })?;
+        }
+        if !self.chars_stack.is_empty() {
+            let mut text = self.text(&self.chars_stack, self.token_start.offset);
+            self.chars_stack.clear();
+            if matches!(self.text_content, None | Some((_, TextContent::Escapable))) {
+                text = self.decode_text(text, self.token_start)?;
+            }
+            let mut located =
+                Located::new(Token::Text(text), self.token_start, self.position.offset);
+            self.limit(&mut located)?;
+            tokens.push(located);
+        }
+        Ok(())
+    }
+}
+
+/// Check the attributes of the XML tag, whose text is `source`, which
+/// must be quoted, and decode their values with the references of XML
+/// only.
+fn xml_attrs<'a>(
+    tag: &mut Token<'a>,
+    source: &'a str,
+    start: Position,
+    passthrough: &[(String, String)],
+) -> Result<(), ParseError> {
+    let not_well_formed = |reason| ParseError::NotWellFormed {
+        reason,
+        position: start,
+    };
+    let (Token::Start(_, attrs, spans) | Token::Closing(_, attrs, spans)) = tag else {
+        return Ok(());
+    };
+    for ((key, value), span) in attrs.iter_mut().zip(spans.iter()) {
+        if attrs::is_opaque(key, passthrough) || attrs::is_raw(value, passthrough) {
+            continue;
+        }
+        let text = span
+            .start
+            .checked_sub(start.offset)
+            .and_then(|offset| source.get(offset..span.end - start.offset))
+            .unwrap_or_default();
+        let raw = text.split_once('=').and_then(|(_, raw)| {
+            let raw = raw.trim_start();
+            let quote = raw.chars().next().filter(|ch| *ch == '"' || *ch == '\'')?;
+            raw.strip_prefix(quote)?.strip_suffix(quote)
+        });
+        let Some(raw) = raw else {
+            return Err(not_well_formed(format!(
+                "Value of attribute `{}` is not quoted",
+                key
+            )));
+        };
+        *value = decode_xml(raw, true).map_err(|(reason, _)| {
+            not_well_formed(format!("{} in the value of attribute `{}`", reason, key))
+        })?;
+    }
+    Ok(())
+}
+
+/// Cut `text` to `max` bytes at most, without splitting a char.
+fn truncate(text: &mut Cow<str>, max: usize) {
+    let mut len = max;
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    match text {
+        Cow::Borrowed(text) => *text = &text[..len],
+        Cow::Owned(text) => text.truncate(len),
+    }
+}
+
+/// Get the length of `tag` if it is the end tag of `tag_name`, which
+/// is matched case-insensitively and may have whitespace before the `>`,
+/// like `</SCRIPT >`.
+fn end_tag_len(tag: &[char], tag_name: &str) -> Option<usize> {
+    if !tag.starts_with(&['<', '/']) {
+        return None;
+    }
+    let name_len = tag_name.chars().count();
+    let name = tag.get(2..2 + name_len)?;
+    let matched = name
+        .iter()
+        .zip(tag_name.chars())
+        .all(|(a, b)| a.eq_ignore_ascii_case(&b));
+    let rest = tag.get(2 + name_len..tag.len() - 1)?;
+    // Checked from the end, so that it stops right away at the `>` of
+    // the text like in `</style a>>>`.
+    if matched && rest.iter().rev().all(|ch| ch.is_ascii_whitespace()) {
+        Some(tag.len())
+    } else {
+        None
+    }
+}
+
+// Use `&[char]` instead of `&str` to improve performance.
+fn ends_with(chars: &[char], end: &[char]) -> bool {
+    let chars_len = chars.len();
+    let end_len = end.len();
+    for i in 1..(end_len + 1) {
+        if chars[chars_len - i] != end[end.len() - i] {
+            return false;
+        }
+    }
+    true
+}
+
+/// Check if `chars` followed by `ch` ends with the `delimiter`.
+fn ends_with_delimiter(chars: &[char], ch: char, delimiter: &str) -> bool {
+    let mut delimiter = delimiter.chars().rev();
+    delimiter.next() == Some(ch)
+        && delimiter.clone().count() <= chars.len()
+        && delimiter.zip(chars.iter().rev()).all(|(a, b)| a == *b)
+}
+
+/// Iterator of the tokens of a html string, which are read lazily.
+pub struct Tokens<'a> {
+    chars: std::str::Chars<'a>,
+    tokenizer: Tokenizer<'a>,
+    /// The tokens read but not yielded yet.
+    tokens: Vec<Located<'a>>,
+    finished: bool,
+}
+
+impl<'a> Tokens<'a> {
+    /// Read the `html`, which the tokens borrow from.
+    pub fn new(html: &'a str, options: &ParseOptions, fault_tolerant: bool) -> Self {
+        let mut tokenizer = Tokenizer::new(options, fault_tolerant);
+        tokenizer.source = Some(html);
+        Tokens {
+            chars: html.chars(),
+            tokenizer,
+            tokens: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Collect the errors fixed by the fault tolerant tokenizer.
+    pub fn with_diagnostics(mut self) -> Self {
+        self.tokenizer.collect_diagnostics();
+        self
+    }
+
+    /// Take the diagnostics collected so far.
+    pub fn take_diagnostics(&mut self) -> Vec<ParseError> {
+        self.tokenizer.take_diagnostics()
+    }
+
+    /// Read the html as if it starts at `position`, like
+    /// [`Tokenizer::starting_at()`].
+    pub fn starting_at(mut self, position: Position) -> Self {
+        self.tokenizer.starting_at(position);
+        self
+    }
+
+    /// Read the input as XML, like [`Tokenizer::xml()`].
+    pub fn xml(mut self) -> Self {
+        self.tokenizer.xml();
+        self
+    }
+
+    /// Read the html as the content of the `context` element, like
+    /// [`Tokenizer::in_context()`].
+    pub fn in_context(mut self, context: &Element) -> Self {
+        self.tokenizer.in_context(context);
+        self
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<Located<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A char completes two tokens at most, like the text and the end
+        // tag of `<script>`, so the buffer is always short.
+        while self.tokens.is_empty() && !self.finished {
+            match self.chars.next() {
+                // The rest of the input is ignored once truncated.
+                Some(ch) if !self.tokenizer.truncated => {
+                    if let Err(error) = self.tokenizer.feed_char(ch, &mut self.tokens) {
+                        self.finished = true;
+                        return Some(Err(error));
+                    }
+                }
+                _ => {
+                    self.finished = true;
+                    if let Err(error) = self.tokenizer.finish(&mut self.tokens) {
+                        return Some(Err(error));
+                    }
+                }
+            }
+        }
+        if self.tokens.is_empty() {
+            None
+        } else {
+            Some(Ok(self.tokens.remove(0)))
+        }
+    }
+}
@@ -0,0 +1,2184 @@
+//! Tree construction following the [HTML standard](https://html.spec.whatwg.org/multipage/parsing.html#tree-construction),
+//! including the insertion modes, the implicit `html`, `head` and `body`
+//! elements, foster parenting and the adoption agency algorithm.
+//!
+//! The nodes are kept in an arena while building, as the algorithm needs
+//! to move them around, and are converted to [`Node`]s at the end.
+
+use super::builder::OwnedDom;
+use super::foreign::{self, Content};
+use super::implied::{self, OpenNames};
+use super::token::{close_element, is_template, Located, Token};
+use super::{parse_body, ParseOptions};
+use crate::{data::SPECIAL_TAGS, Doctype, Element, Namespace, Node, QuirksMode, Span};
+
+type Id = usize;
+
+const DOCUMENT: Id = 0;
+
+enum Data {
+    Document,
+    Element(Element),
+    Text(String),
+    /// A comment, CDATA section or processing instruction.
+    Comment(Node),
+    Doctype(Doctype),
+}
+
+struct ArenaNode {
+    data: Data,
+    parent: Option<Id>,
+    children: Vec<Id>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    InTemplate,
+    AfterBody,
+    InFrameset,
+    AfterFrameset,
+    AfterAfterBody,
+    AfterAfterFrameset,
+}
+
+/// Entry of the list of active formatting elements.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Formatting {
+    Marker,
+    Element(Id),
+}
+
+enum Tok {
+    Start(Element, bool),
+    End(String, Option<Span>),
+    Text(String),
+    /// A comment, CDATA section or processing instruction, which are all
+    /// inserted like comments.
+    Comment(Node),
+    Doctype(Doctype),
+    Eof,
+}
+
+const FORMATTING_TAGS: [&str; 14] = [
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+
+const HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Start tags that close a `p` element in button scope, except the
+/// headings and the others handled separately.
+const BLOCK_TAGS: [&str; 26] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "header",
+    "hgroup",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "search",
+    "section",
+    "summary",
+    "ul",
+    "listing",
+];
+
+/// End tags that close the block elements.
+const BLOCK_END_TAGS: [&str; 29] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "button",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "header",
+    "hgroup",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "pre",
+    "search",
+    "section",
+    "select",
+    "summary",
+    "ul",
+    "xmp",
+];
+
+const IMPLIED_END_TAGS: [&str; 10] = [
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+const THOROUGHLY_IMPLIED_END_TAGS: [&str; 18] = [
+    "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+    "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+const SCOPE: [&str; 9] = [
+    "applet", "caption", "html", "marquee", "object", "table", "td", "template", "th",
+];
+
+const LIST_ITEM_SCOPE: [&str; 11] = [
+    "applet", "caption", "html", "marquee", "object", "table", "td", "template", "th", "ol", "ul",
+];
+
+const BUTTON_SCOPE: [&str; 10] = [
+    "applet", "caption", "html", "marquee", "object", "table", "td", "template", "th", "button",
+];
+
+const TABLE_SCOPE: [&str; 3] = ["html", "table", "template"];
+
+const TABLE_SECTIONS: [&str; 3] = ["tbody", "tfoot", "thead"];
+
+const TABLE_CELLS: [&str; 2] = ["td", "th"];
+
+/// Tags which decide the insertion mode when it is reset, besides `body`
+/// and `html`.
+const MODE_TAGS: [&str; 13] = [
+    "caption", "colgroup", "frameset", "head", "select", "table", "tbody", "td", "template",
+    "tfoot", "th", "thead", "tr",
+];
+
+/// Tags handled by the "in head" insertion mode wherever they are.
+const HEAD_TAGS: [&str; 10] = [
+    "base", "basefont", "bgsound", "link", "meta", "noframes", "script", "style", "template",
+    "title",
+];
+
+fn is_whitespace(ch: char) -> bool {
+    matches!(ch, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+/// Split the leading whitespace from the text.
+fn split_whitespace(text: &str) -> (&str, &str) {
+    let index = text.find(|ch| !is_whitespace(ch)).unwrap_or(text.len());
+    text.split_at(index)
+}
+
+pub struct TreeBuilder {
+    nodes: Vec<ArenaNode>,
+    mode: Mode,
+    original_mode: Mode,
+    /// The element whose content is handled by the "text" insertion mode.
+    raw_text_element: Option<Id>,
+    template_modes: Vec<Mode>,
+    open: Vec<Id>,
+    /// The names of the elements in `open`, so that the scope checks
+    /// don't scan it when no element of the names is open.
+    open_names: OpenNames,
+    formatting: Vec<Formatting>,
+    head: Option<Id>,
+    form: Option<Id>,
+    foster_parenting: bool,
+    quirks_mode: QuirksMode,
+    /// Whether to ignore the next `\n` right after `<pre>`, `<listing>`
+    /// and `<textarea>`.
+    skip_newline: bool,
+    /// Byte offset right after the last token, which is the end of the
+    /// elements closed implicitly.
+    last_end: usize,
+    /// The context element of the fragment, which is not in the tree.
+    context: Option<Id>,
+    /// The node whose children are built, which is the `html` element of
+    /// the fragment.
+    root: Id,
+    /// The options of the html, which parse the bodies of the
+    /// conditional comments.
+    options: ParseOptions,
+}
+
+impl TreeBuilder {
+    pub fn new(options: &ParseOptions) -> Self {
+        TreeBuilder {
+            nodes: vec![ArenaNode {
+                data: Data::Document,
+                parent: None,
+                children: Vec::new(),
+            }],
+            mode: Mode::Initial,
+            original_mode: Mode::Initial,
+            raw_text_element: None,
+            template_modes: Vec::new(),
+            open: Vec::new(),
+            open_names: OpenNames::default(),
+            formatting: Vec::new(),
+            head: None,
+            form: None,
+            foster_parenting: false,
+            quirks_mode: QuirksMode::NoQuirks,
+            skip_newline: false,
+            last_end: 0,
+            context: None,
+            root: DOCUMENT,
+            options: options.clone(),
+        }
+    }
+
+    /// Create a builder for the fragment in the `context` element,
+    /// following the [fragment parsing algorithm](https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments).
+    pub fn fragment(context: &Element, options: &ParseOptions) -> Self {
+        let mut builder = TreeBuilder::new(options);
+        let name = context.name.to_ascii_lowercase();
+        let root = builder.create(Data::Element(Element::new("html", vec![], vec![])));
+        builder.append(DOCUMENT, root);
+        builder.push_open(root);
+        builder.root = root;
+        if name == "template" {
+            builder.template_modes.push(Mode::InTemplate);
+        }
+        let is_form = name == "form";
+        let mut element = Element::from_parts(name, context.attrs.clone(), None);
+        element.namespace = context.namespace.clone();
+        let context = builder.create(Data::Element(element));
+        if is_form {
+            builder.form = Some(context);
+        }
+        builder.context = Some(context);
+        builder.reset_insertion_mode();
+        builder
+    }
+
+    /// Process a token from the tokenizer.
+    pub fn push(&mut self, located: Located) {
+        let end = located.end;
+        // The namespaces of the elements are decided by the tree.
+        let element = |located: Located| {
+            let mut element = located.into_element();
+            element.namespace = Namespace::Html;
+            element
+        };
+        let tok = match located.token {
+            Token::Start(..) => Tok::Start(element(located), false),
+            Token::Closing(..) => Tok::Start(element(located), true),
+            Token::End(name) => Tok::End(
+                name.into_owned(),
+                Some(Span::new(located.start.offset, end)),
+            ),
+            Token::Text(text) => {
+                let mut text = text.into_owned();
+                if self.skip_newline && text.starts_with('\n') {
+                    text.remove(0);
+                }
+                if text.is_empty() {
+                    self.skip_newline = false;
+                    self.last_end = end;
+                    return;
+                }
+                Tok::Text(text)
+            }
+            Token::Comment(..) => Tok::Comment(self.comment(located)),
+            Token::CData(..)
+            | Token::ProcessingInstruction(..)
+            | Token::DownlevelRevealed(..)
+            | Token::Passthrough(..) => Tok::Comment(located.into_node()),
+            Token::Doctype(doctype) => Tok::Doctype(doctype),
+        };
+        self.skip_newline = false;
+        if self.in_html_content(&tok) {
+            self.process(tok);
+        } else {
+            self.in_foreign_content(tok);
+        }
+        self.last_end = end;
+    }
+
+    /// Convert the comment into a node, whose body is parsed if it is a
+    /// conditional comment.
+    fn comment(&self, located: Located) -> Node {
+        let limits = self.options.limits;
+        match parse_body(OwnedDom, &located, &self.options, limits) {
+            Ok(Some((nodes, _))) => located.into_comment(Some(nodes)),
+            _ => located.into_node(),
+        }
+    }
+
+    /// Finish building and get the nodes of the document, or of the
+    /// fragment.
+    pub fn finish(mut self) -> Vec<Node> {
+        self.process(Tok::Eof);
+        while !self.open.is_empty() {
+            self.pop();
+        }
+        // Build the nodes in the reverse preorder, in which the children
+        // are built before their parents, without recursion which would
+        // overflow the stack for the deeply nested html.
+        let mut order = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(&self.nodes[id].children);
+        }
+        let mut built: Vec<Option<Node>> = self.nodes.iter().map(|_| None).collect();
+        for id in order.into_iter().rev() {
+            let children = std::mem::take(&mut self.nodes[id].children)
+                .into_iter()
+                .filter_map(|child| built[child].take())
+                .collect();
+            if id == self.root {
+                return children;
+            }
+            built[id] = match std::mem::replace(&mut self.nodes[id].data, Data::Document) {
+                Data::Element(mut element) => {
+                    if is_template(&element.name, &element.namespace) {
+                        element.template_content = Some(children);
+                    } else {
+                        element.children = children;
+                    }
+                    Some(element.into_node())
+                }
+                Data::Text(text) => Some(Node::Text(text)),
+                Data::Comment(node) => Some(node),
+                Data::Doctype(doctype) => Some(Node::Doctype(doctype)),
+                Data::Document => None,
+            };
+        }
+        Vec::new()
+    }
+
+    // ---------------------------------------------------------------------
+    // Arena
+    // ---------------------------------------------------------------------
+
+    fn create(&mut self, data: Data) -> Id {
+        self.nodes.push(ArenaNode {
+            data,
+            parent: None,
+            children: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    fn element(&self, id: Id) -> Option<&Element> {
+        match &self.nodes[id].data {
+            Data::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the HTML element, which is empty for the other
+    /// nodes including the SVG and MathML elements, so that they never
+    /// match the names of the HTML rules.
+    fn name(&self, id: Id) -> &str {
+        match self.element(id) {
+            Some(element) if element.namespace == Namespace::Html => &element.name,
+            _ => "",
+        }
+    }
+
+    /// Check if the element is in the special category, which includes
+    /// some SVG and MathML elements.
+    fn is_special(&self, id: Id) -> bool {
+        match self.element(id) {
+            Some(element) if element.namespace == Namespace::Html => {
+                SPECIAL_TAGS.contains(&element.name.as_str())
+            }
+            Some(element) => foreign::is_special(&element.namespace, &element.name),
+            None => false,
+        }
+    }
+
+    fn detach(&mut self, id: Id) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|&child| child != id);
+        }
+    }
+
+    /// Insert the node at `location`, which is the parent and the sibling
+    /// to insert before.
+    fn insert_at(&mut self, (parent, before): (Id, Option<Id>), id: Id) {
+        self.detach(id);
+        let children = &mut self.nodes[parent].children;
+        match before.and_then(|before| children.iter().position(|&child| child == before)) {
+            Some(index) => children.insert(index, id),
+            None => children.push(id),
+        }
+        self.nodes[id].parent = Some(parent);
+    }
+
+    fn append(&mut self, parent: Id, id: Id) {
+        self.insert_at((parent, None), id);
+    }
+
+    // ---------------------------------------------------------------------
+    // Stack of open elements
+    // ---------------------------------------------------------------------
+
+    fn current(&self) -> Option<Id> {
+        self.open.last().copied()
+    }
+
+    fn current_name(&self) -> &str {
+        self.current().map_or("", |id| self.name(id))
+    }
+
+    fn current_is(&self, names: &[&str]) -> bool {
+        names.contains(&self.current_name())
+    }
+
+    fn push_open(&mut self, id: Id) {
+        self.insert_open(self.open.len(), id);
+    }
+
+    fn insert_open(&mut self, index: usize, id: Id) {
+        let name = match &self.nodes[id].data {
+            Data::Element(element) => implied::open_name(&element.namespace, &element.name),
+            _ => "",
+        };
+        self.open_names.insert(index, name);
+        self.open.insert(index, id);
+    }
+
+    /// Check if the element is open, looking from the current node as the
+    /// element is mostly near it.
+    fn is_open(&self, id: Id) -> bool {
+        self.open.iter().rev().any(|&open| open == id)
+    }
+
+    /// Take the element out of the stack wherever it is.
+    fn remove_open(&mut self, id: Id) -> Option<Id> {
+        let index = self.open.iter().rposition(|&open| open == id)?;
+        self.open_names.remove(index);
+        Some(self.open.remove(index))
+    }
+
+    /// Pop the current node, whose span ends at the last token.
+    fn pop(&mut self) -> Option<Id> {
+        let id = self.remove_open(self.current()?)?;
+        let end = self.last_end;
+        if let Data::Element(element) = &mut self.nodes[id].data {
+            if element.span().is_some_and(|span| span.end_tag.is_none()) {
+                close_element(element, end, None);
+            }
+        }
+        Some(id)
+    }
+
+    /// Pop the elements until an element in `names` has been popped, and
+    /// set the span of its end tag.
+    fn pop_until(&mut self, names: &[&str], end_tag: Option<Span>) {
+        while let Some(id) = self.pop() {
+            if names.contains(&self.name(id)) {
+                if let (Some(end_tag), Data::Element(element)) = (end_tag, &mut self.nodes[id].data)
+                {
+                    close_element(element, end_tag.end, Some(end_tag));
+                }
+                break;
+            }
+        }
+    }
+
+    /// Check if an element of `names` is in the scope, which ends at the
+    /// `boundaries`, and at the special SVG and MathML elements if
+    /// `foreign_boundaries`.
+    fn in_specific_scope(
+        &self,
+        names: &[&str],
+        boundaries: &[&str],
+        foreign_boundaries: bool,
+    ) -> bool {
+        if !self.open_names.has_any(names) {
+            return false;
+        }
+        for &id in self.open.iter().rev() {
+            let name = self.name(id);
+            if names.contains(&name) {
+                return true;
+            }
+            if boundaries.contains(&name) {
+                return false;
+            }
+            let foreign = self
+                .element(id)
+                .is_some_and(|element| element.namespace != Namespace::Html);
+            if foreign_boundaries && foreign && self.is_special(id) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn in_scope(&self, names: &[&str]) -> bool {
+        self.in_specific_scope(names, &SCOPE, true)
+    }
+
+    fn in_list_item_scope(&self, names: &[&str]) -> bool {
+        self.in_specific_scope(names, &LIST_ITEM_SCOPE, true)
+    }
+
+    fn in_button_scope(&self, names: &[&str]) -> bool {
+        self.in_specific_scope(names, &BUTTON_SCOPE, true)
+    }
+
+    fn in_table_scope(&self, names: &[&str]) -> bool {
+        self.in_specific_scope(names, &TABLE_SCOPE, false)
+    }
+
+    fn in_select_scope(&self, names: &[&str]) -> bool {
+        if !self.open_names.has_any(names) {
+            return false;
+        }
+        for &id in self.open.iter().rev() {
+            let name = self.name(id);
+            if names.contains(&name) {
+                return true;
+            }
+            if name != "optgroup" && name != "option" {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while self.current_is(&IMPLIED_END_TAGS) && Some(self.current_name()) != except {
+            self.pop();
+        }
+    }
+
+    fn generate_all_implied_end_tags(&mut self) {
+        while self.current_is(&THOROUGHLY_IMPLIED_END_TAGS) {
+            self.pop();
+        }
+    }
+
+    fn close_p(&mut self) {
+        if self.in_button_scope(&["p"]) {
+            self.generate_implied_end_tags(Some("p"));
+            self.pop_until(&["p"], None);
+        }
+    }
+
+    fn clear_to_context(&mut self, names: &[&str]) {
+        while !self.current_is(names) && !self.current_is(&["html", "template"]) {
+            self.pop();
+        }
+    }
+
+    // ---------------------------------------------------------------------
+    // Insertion
+    // ---------------------------------------------------------------------
+
+    /// Get the appropriate place for inserting a node.
+    fn insertion_location(&self, override_target: Option<Id>) -> (Id, Option<Id>) {
+        let target = override_target.or(self.current()).unwrap_or(DOCUMENT);
+        if self.foster_parenting
+            && ["table", "tbody", "tfoot", "thead", "tr"].contains(&self.name(target))
+        {
+            let last_template = self
+                .open
+                .iter()
+                .rposition(|&id| self.name(id) == "template");
+            let last_table = self.open.iter().rposition(|&id| self.name(id) == "table");
+            match (last_template, last_table) {
+                (Some(template), Some(table)) if template > table => {
+                    return (self.open[template], None)
+                }
+                (Some(template), None) => return (self.open[template], None),
+                (_, None) => return (self.open[0], None),
+                (_, Some(table)) => {
+                    let table_id = self.open[table];
+                    return match self.nodes[table_id].parent {
+                        Some(parent) => (parent, Some(table_id)),
+                        None => (self.open[table - 1], None),
+                    };
+                }
+            }
+        }
+        (target, None)
+    }
+
+    fn insert_element(&mut self, element: Element) -> Id {
+        let location = self.insertion_location(None);
+        let id = self.create(Data::Element(element));
+        self.insert_at(location, id);
+        self.push_open(id);
+        id
+    }
+
+    /// Insert a void element, which is popped at once.
+    fn insert_void(&mut self, element: Element) {
+        let id = self.insert_element(element);
+        self.remove_open(id);
+    }
+
+    fn insert_text(&mut self, text: &str) {
+        let (parent, before) = self.insertion_location(None);
+        if parent == DOCUMENT {
+            return;
+        }
+        let children = &self.nodes[parent].children;
+        let previous = match before.and_then(|before| children.iter().position(|&c| c == before)) {
+            Some(0) => None,
+            Some(index) => Some(children[index - 1]),
+            None => children.last().copied(),
+        };
+        if let Some(previous) = previous {
+            if let Data::Text(previous) = &mut self.nodes[previous].data {
+                previous.push_str(text);
+                return;
+            }
+        }
+        let id = self.create(Data::Text(text.to_string()));
+        self.insert_at((parent, before), id);
+    }
+
+    fn insert_comment(&mut self, comment: Node, parent: Option<Id>) {
+        let location = match parent {
+            Some(parent) => (parent, None),
+            None => self.insertion_location(None),
+        };
+        let id = self.create(Data::Comment(comment));
+        self.insert_at(location, id);
+    }
+
+    /// Create an element with the same name and attributes as `id`, which
+    /// does not come from the source directly.
+    fn clone_element(&mut self, id: Id) -> Id {
+        let (name, attrs, namespace) = self
+            .element(id)
+            .map(|element| {
+                let name = element.name.clone();
+                (name, element.attrs.clone(), element.namespace.clone())
+            })
+            .unwrap_or_default();
+        let mut element = Element::from_parts(name, attrs, None);
+        element.namespace = namespace;
+        self.create(Data::Element(element))
+    }
+
+    // ---------------------------------------------------------------------
+    // Active formatting elements
+    // ---------------------------------------------------------------------
+
+    fn push_formatting(&mut self, id: Id) {
+        // The "Noah's Ark" clause: at most three same elements after the
+        // last marker.
+        let mut same = Vec::new();
+        for (i, entry) in self.formatting.iter().enumerate().rev() {
+            match entry {
+                Formatting::Marker => break,
+                Formatting::Element(other) => {
+                    let (a, b) = (self.element(id), self.element(*other));
+                    if let (Some(a), Some(b)) = (a, b) {
+                        let mut a_attrs = a.attrs.clone();
+                        let mut b_attrs = b.attrs.clone();
+                        a_attrs.sort();
+                        b_attrs.sort();
+                        if a.name == b.name && a_attrs == b_attrs {
+                            same.push(i);
+                        }
+                    }
+                }
+            }
+        }
+        if same.len() >= 3 {
+            self.formatting.remove(same[same.len() - 1]);
+        }
+        self.formatting.push(Formatting::Element(id));
+    }
+
+    fn clear_formatting_to_marker(&mut self) {
+        while let Some(entry) = self.formatting.pop() {
+            if entry == Formatting::Marker {
+                break;
+            }
+        }
+    }
+
+    fn reconstruct_formatting(&mut self) {
+        let is_open = |builder: &Self, entry: &Formatting| match entry {
+            Formatting::Marker => true,
+            Formatting::Element(id) => builder.is_open(*id),
+        };
+        let Some(last) = self.formatting.last() else {
+            return;
+        };
+        if is_open(self, last) {
+            return;
+        }
+        let mut index = self.formatting.len() - 1;
+        while index > 0 && !is_open(self, &self.formatting[index - 1]) {
+            index -= 1;
+        }
+        for i in index..self.formatting.len() {
+            if let Formatting::Element(id) = self.formatting[i] {
+                let new_id = self.clone_element(id);
+                let location = self.insertion_location(None);
+                self.insert_at(location, new_id);
+                self.push_open(new_id);
+                self.formatting[i] = Formatting::Element(new_id);
+            }
+        }
+    }
+
+    /// The adoption agency algorithm for misnested formatting elements,
+    /// like `<b><i></b></i>`.
+    ///
+    /// Returns `false` if the end tag should be handled as "any other end
+    /// tag".
+    fn adoption_agency(&mut self, subject: &str, end_tag: Option<Span>) -> bool {
+        if let Some(current) = self.current() {
+            if self.name(current) == subject
+                && !self.formatting.contains(&Formatting::Element(current))
+            {
+                self.pop_until(&[subject], end_tag);
+                return true;
+            }
+        }
+
+        for _ in 0..8 {
+            let formatting_index = self.formatting.iter().rposition(|entry| match entry {
+                Formatting::Marker => true,
+                Formatting::Element(id) => self.name(*id) == subject,
+            });
+            let (formatting_index, formatting_element) = match formatting_index {
+                Some(index) => match self.formatting[index] {
+                    Formatting::Element(id) => (index, id),
+                    Formatting::Marker => return false,
+                },
+                None => return false,
+            };
+            let Some(stack_index) = self.open.iter().rposition(|&id| id == formatting_element)
+            else {
+                self.formatting.remove(formatting_index);
+                return true;
+            };
+            if !self.in_scope(&[subject]) {
+                return true;
+            }
+
+            let furthest_block = self.open[stack_index + 1..]
+                .iter()
+                .position(|&id| self.is_special(id))
+                .map(|i| i + stack_index + 1);
+            let Some(furthest_block_index) = furthest_block else {
+                while let Some(id) = self.pop() {
+                    if id == formatting_element {
+                        if let (Some(end_tag), Data::Element(element)) =
+                            (end_tag, &mut self.nodes[id].data)
+                        {
+                            close_element(element, end_tag.end, Some(end_tag));
+                        }
+                        break;
+                    }
+                }
+                self.formatting.remove(formatting_index);
+                return true;
+            };
+            let furthest_block = self.open[furthest_block_index];
+            let common_ancestor = self.open[stack_index - 1];
+            let mut bookmark = formatting_index;
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block;
+
+            let mut inner = 0;
+            loop {
+                inner += 1;
+                node_index -= 1;
+                let node = self.open[node_index];
+                if node == formatting_element {
+                    break;
+                }
+                let mut node_formatting_index = self
+                    .formatting
+                    .iter()
+                    .position(|entry| entry == &Formatting::Element(node));
+                if inner > 3 {
+                    if let Some(index) = node_formatting_index.take() {
+                        self.formatting.remove(index);
+                        if index < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+                let Some(node_formatting_index) = node_formatting_index else {
+                    self.remove_open(node);
+                    continue;
+                };
+                let new_node = self.clone_element(node);
+                self.formatting[node_formatting_index] = Formatting::Element(new_node);
+                self.open[node_index] = new_node;
+                if last_node == furthest_block {
+                    bookmark = node_formatting_index + 1;
+                }
+                self.append(new_node, last_node);
+                last_node = new_node;
+            }
+
+            let location = self.insertion_location(Some(common_ancestor));
+            self.insert_at(location, last_node);
+
+            let new_element = self.clone_element(formatting_element);
+            let children = std::mem::take(&mut self.nodes[furthest_block].children);
+            for child in children {
+                self.nodes[child].parent = None;
+                self.append(new_element, child);
+            }
+            self.append(furthest_block, new_element);
+
+            if let Some(formatting_index) = self
+                .formatting
+                .iter()
+                .position(|entry| entry == &Formatting::Element(formatting_element))
+            {
+                self.formatting.remove(formatting_index);
+                if formatting_index < bookmark {
+                    bookmark -= 1;
+                }
+            }
+            let bookmark = bookmark.min(self.formatting.len());
+            self.formatting
+                .insert(bookmark, Formatting::Element(new_element));
+
+            self.remove_open(formatting_element);
+            let index = self
+                .open
+                .iter()
+                .position(|&id| id == furthest_block)
+                .map_or(self.open.len(), |index| index + 1);
+            self.insert_open(index, new_element);
+        }
+        true
+    }
+
+    // ---------------------------------------------------------------------
+    // Foreign content
+    // ---------------------------------------------------------------------
+
+    /// Get the current node, which is the context element instead of the
+    /// root of the fragment.
+    fn adjusted_current(&self) -> Option<Id> {
+        match self.context {
+            Some(context) if self.open.len() == 1 => Some(context),
+            _ => self.current(),
+        }
+    }
+
+    /// Check if the token is processed by the insertion mode, rather
+    /// than the rules of the foreign content, following the [tree
+    /// construction dispatcher](https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher).
+    fn in_html_content(&self, tok: &Tok) -> bool {
+        let Some(element) = self.adjusted_current().and_then(|id| self.element(id)) else {
+            return true;
+        };
+        if element.namespace == Namespace::Html {
+            return true;
+        }
+        let attrs = element.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        let content = Content::of(&element.namespace, &element.name, attrs);
+        match (content, tok) {
+            (_, Tok::Eof) => true,
+            (Content::MathMlText, Tok::Start(start, _)) => {
+                !["mglyph", "malignmark"].contains(&start.name.as_str())
+            }
+            // The end tags are handled by the foreign content, which
+            // closes the integration points like `<foreignObject>`.
+            (Content::Html | Content::MathMlText, Tok::Start(..) | Tok::Text(_)) => true,
+            (Content::Annotation, Tok::Start(start, _)) => start.name == "svg",
+            _ => false,
+        }
+    }
+
+    /// Pop the foreign elements until the current node is an HTML element
+    /// or an integration point, and process the token by the insertion
+    /// mode.
+    fn break_out(&mut self, tok: Tok) {
+        while let Some(element) = self.current().and_then(|id| self.element(id)) {
+            let attrs = element.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+            if !Content::of(&element.namespace, &element.name, attrs).is_foreign() {
+                break;
+            }
+            self.pop();
+        }
+        self.process(tok);
+    }
+
+    fn in_foreign_content(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text) => self.insert_text(&text),
+            Tok::Comment(comment) => self.insert_comment(comment, None),
+            Tok::Doctype(_) => {}
+            Tok::Start(mut element, self_closing) => {
+                let name = element.name.to_ascii_lowercase();
+                let attrs = element.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+                if foreign::breaks_out(&name, attrs) {
+                    self.break_out(Tok::Start(element, self_closing));
+                    return;
+                }
+                element.namespace = self
+                    .adjusted_current()
+                    .and_then(|id| self.element(id))
+                    .map_or(Namespace::Html, |current| current.namespace.clone());
+                if self_closing {
+                    self.insert_void(element);
+                } else {
+                    self.insert_element(element);
+                }
+            }
+            Tok::End(name, span) if name == "br" || name == "p" => {
+                self.break_out(Tok::End(name, span));
+            }
+            Tok::End(name, span) => {
+                for index in (0..self.open.len()).rev() {
+                    let id = self.open[index];
+                    let Some(element) = self.element(id) else {
+                        continue;
+                    };
+                    if element.namespace == Namespace::Html {
+                        self.process(Tok::End(name, span));
+                        return;
+                    }
+                    if element.name.eq_ignore_ascii_case(&name) {
+                        while self.open.len() > index + 1 {
+                            self.pop();
+                        }
+                        self.pop();
+                        if let (Some(end_tag), Data::Element(element)) =
+                            (span, &mut self.nodes[id].data)
+                        {
+                            close_element(element, end_tag.end, Some(end_tag));
+                        }
+                        return;
+                    }
+                }
+            }
+            Tok::Eof => self.process(Tok::Eof),
+        }
+    }
+
+    // ---------------------------------------------------------------------
+    // Insertion modes
+    // ---------------------------------------------------------------------
+
+    fn reset_insertion_mode(&mut self) {
+        // The mode is decided by the `body` or the `html` element when no
+        // other element deciding it is open, as in `<div><select></select>`.
+        if self.context.is_none() && !self.open_names.has_any(&MODE_TAGS) {
+            self.mode = if self.open_names.has_any(&["body"]) || self.open.is_empty() {
+                Mode::InBody
+            } else if self.head.is_none() {
+                Mode::BeforeHead
+            } else {
+                Mode::AfterHead
+            };
+            return;
+        }
+        for (i, &id) in self.open.iter().enumerate().rev() {
+            let last = i == 0;
+            // The context element stands for the root in the fragment.
+            let id = match self.context {
+                Some(context) if last => context,
+                _ => id,
+            };
+            self.mode = match self.name(id) {
+                "select" => {
+                    let in_table = self.open_names.has_any(&["table"])
+                        && self.open[..i]
+                            .iter()
+                            .rev()
+                            .take_while(|&&id| self.name(id) != "template")
+                            .any(|&id| self.name(id) == "table");
+                    if in_table {
+                        Mode::InSelectInTable
+                    } else {
+                        Mode::InSelect
+                    }
+                }
+                "td" | "th" if !last => Mode::InCell,
+                "tr" => Mode::InRow,
+                "tbody" | "thead" | "tfoot" => Mode::InTableBody,
+                "caption" => Mode::InCaption,
+                "colgroup" => Mode::InColumnGroup,
+                "table" => Mode::InTable,
+                "template" => *self.template_modes.last().unwrap_or(&Mode::InTemplate),
+                "head" if !last => Mode::InHead,
+                "body" => Mode::InBody,
+                "frameset" => Mode::InFrameset,
+                "html" => {
+                    if self.head.is_none() {
+                        Mode::BeforeHead
+                    } else {
+                        Mode::AfterHead
+                    }
+                }
+                _ if last => Mode::InBody,
+                _ => continue,
+            };
+            return;
+        }
+        self.mode = Mode::InBody;
+    }
+
+    fn process(&mut self, tok: Tok) {
+        match self.mode {
+            Mode::Initial => self.initial(tok),
+            Mode::BeforeHtml => self.before_html(tok),
+            Mode::BeforeHead => self.before_head(tok),
+            Mode::InHead => self.in_head(tok),
+            Mode::AfterHead => self.after_head(tok),
+            Mode::InBody => self.in_body(tok),
+            Mode::Text => self.text(tok),
+            Mode::InTable => self.in_table(tok),
+            Mode::InCaption => self.in_caption(tok),
+            Mode::InColumnGroup => self.in_column_group(tok),
+            Mode::InTableBody => self.in_table_body(tok),
+            Mode::InRow => self.in_row(tok),
+            Mode::InCell => self.in_cell(tok),
+            Mode::InSelect => self.in_select(tok),
+            Mode::InSelectInTable => self.in_select_in_table(tok),
+            Mode::InTemplate => self.in_template(tok),
+            Mode::AfterBody => self.after_body(tok),
+            Mode::InFrameset => self.in_frameset(tok),
+            Mode::AfterFrameset => self.after_frameset(tok),
+            Mode::AfterAfterBody => self.after_after_body(tok),
+            Mode::AfterAfterFrameset => self.after_after_frameset(tok),
+        }
+    }
+
+    /// Process the leading whitespace of the text by `whitespace`, and the
+    /// rest by `rest`.
+    fn split_text(
+        &mut self,
+        tok: Tok,
+        whitespace: impl FnOnce(&mut Self, &str),
+        rest: impl FnOnce(&mut Self, Tok),
+    ) {
+        match tok {
+            Tok::Text(text) => {
+                let (leading, remaining) = split_whitespace(&text);
+                whitespace(self, leading);
+                if !remaining.is_empty() {
+                    rest(self, Tok::Text(remaining.to_string()));
+                }
+            }
+            tok => rest(self, tok),
+        }
+    }
+
+    fn initial(&mut self, tok: Tok) {
+        self.split_text(
+            tok,
+            |_, _| {},
+            |builder, tok| match tok {
+                Tok::Comment(comment) => builder.insert_comment(comment, Some(DOCUMENT)),
+                Tok::Doctype(doctype) => {
+                    builder.quirks_mode = doctype.quirks_mode();
+                    let id = builder.create(Data::Doctype(doctype));
+                    builder.append(DOCUMENT, id);
+                    builder.mode = Mode::BeforeHtml;
+                }
+                tok => {
+                    builder.quirks_mode = QuirksMode::Quirks;
+                    builder.mode = Mode::BeforeHtml;
+                    builder.process(tok);
+                }
+            },
+        );
+    }
+
+    fn insert_html(&mut self, element: Element) {
+        let id = self.create(Data::Element(element));
+        self.append(DOCUMENT, id);
+        self.push_open(id);
+        self.mode = Mode::BeforeHead;
+    }
+
+    fn before_html(&mut self, tok: Tok) {
+        self.split_text(
+            tok,
+            |_, _| {},
+            |builder, tok| match tok {
+                Tok::Doctype(_) => {}
+                Tok::Comment(comment) => builder.insert_comment(comment, Some(DOCUMENT)),
+                Tok::Start(element, _) if element.name == "html" => builder.insert_html(element),
+                Tok::End(name, _) if !["head", "body", "html", "br"].contains(&name.as_str()) => {}
+                tok => {
+                    builder.insert_html(Element::new("html", vec![], vec![]));
+                    builder.process(tok);
+                }
+            },
+        );
+    }
+
+    fn before_head(&mut self, tok: Tok) {
+        self.split_text(
+            tok,
+            |_, _| {},
+            |builder, tok| match tok {
+                Tok::Comment(comment) => builder.insert_comment(comment, None),
+                Tok::Doctype(_) => {}
+                Tok::Start(element, _) if element.name == "html" => {
+                    builder.in_body(Tok::Start(element, false))
+                }
+                Tok::Start(element, _) if element.name == "head" => {
+                    builder.head = Some(builder.insert_element(element));
+                    builder.mode = Mode::InHead;
+                }
+                Tok::End(name, _) if !["head", "body", "html", "br"].contains(&name.as_str()) => {}
+                tok => {
+                    builder.head =
+                        Some(builder.insert_element(Element::new("head", vec![], vec![])));
+                    builder.mode = Mode::InHead;
+                    builder.process(tok);
+                }
+            },
+        );
+    }
+
+    /// Insert an element whose content is raw text, like `<script>`.
+    fn insert_raw_text(&mut self, element: Element) {
+        self.raw_text_element = Some(self.insert_element(element));
+        // The raw text elements may be nested when the tokenizer reads
+        // the content of the outer one as html, like `<textarea>` which
+        // it takes as a MathML element.
+        if self.mode != Mode::Text {
+            self.original_mode = self.mode;
+        }
+        self.mode = Mode::Text;
+    }
+
+    fn in_head(&mut self, tok: Tok) {
+        self.split_text(
+            tok,
+            |builder, whitespace| {
+                if !whitespace.is_empty() {
+                    builder.insert_text(whitespace)
+                }
+            },
+            |builder, tok| match tok {
+                Tok::Comment(comment) => builder.insert_comment(comment, None),
+                Tok::Doctype(_) => {}
+                Tok::Start(element, self_closing) => match element.name.as_str() {
+                    "html" => builder.in_body(Tok::Start(element, self_closing)),
+                    "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                        builder.insert_void(element)
+                    }
+                    "title" | "noscript" | "noframes" | "style" | "script" => {
+                        builder.insert_raw_text(element)
+                    }
+                    "template" => {
+                        builder.insert_element(element);
+                        builder.formatting.push(Formatting::Marker);
+                        builder.mode = Mode::InTemplate;
+                        builder.template_modes.push(Mode::InTemplate);
+                    }
+                    "head" => {}
+                    _ => {
+                        builder.pop();
+                        builder.mode = Mode::AfterHead;
+                        builder.process(Tok::Start(element, self_closing));
+                    }
+                },
+                Tok::End(name, span) => match name.as_str() {
+                    "head" => {
+                        builder.pop_until(&["head"], span);
+                        builder.mode = Mode::AfterHead;
+                    }
+                    "template" => {
+                        if !builder
+                            .open
+                            .iter()
+                            .any(|&id| builder.name(id) == "template")
+                        {
+                            return;
+                        }
+                        builder.generate_all_implied_end_tags();
+                        builder.pop_until(&["template"], span);
+                        builder.clear_formatting_to_marker();
+                        builder.template_modes.pop();
+                        builder.reset_insertion_mode();
+                    }
+                    "body" | "html" | "br" => {
+                        builder.pop();
+                        builder.mode = Mode::AfterHead;
+                        builder.process(Tok::End(name, span));
+                    }
+                    _ => {}
+                },
+                tok => {
+                    builder.pop();
+                    builder.mode = Mode::AfterHead;
+                    builder.process(tok);
+                }
+            },
+        );
+    }
+
+    fn after_head(&mut self, tok: Tok) {
+        self.split_text(
+            tok,
+            |builder, whitespace| {
+                if !whitespace.is_empty() {
+                    builder.insert_text(whitespace)
+                }
+            },
+            |builder, tok| match tok {
+                Tok::Comment(comment) => builder.insert_comment(comment, None),
+                Tok::Doctype(_) => {}
+                Tok::Start(element, self_closing) => match element.name.as_str() {
+                    "html" => builder.in_body(Tok::Start(element, self_closing)),
+                    "body" => {
+                        builder.insert_element(element);
+                        builder.mode = Mode::InBody;
+                    }
+                    "frameset" => {
+                        builder.insert_element(element);
+                        builder.mode = Mode::InFrameset;
+                    }
+                    name if HEAD_TAGS.contains(&name) => {
+                        let Some(head) = builder.head else {
+                            return;
+                        };
+                        builder.push_open(head);
+                        builder.in_head(Tok::Start(element, self_closing));
+                        builder.remove_open(head);
+                    }
+                    "head" => {}
+                    _ => {
+                        builder.insert_element(Element::new("body", vec![], vec![]));
+                        builder.mode = Mode::InBody;
+                        builder.process(Tok::Start(element, self_closing));
+                    }
+                },
+                Tok::End(name, span) => match name.as_str() {
+                    "template" => builder.in_head(Tok::End(name, span)),
+                    "body" | "html" | "br" => {
+                        builder.insert_element(Element::new("body", vec![], vec![]));
+                        builder.mode = Mode::InBody;
+                        builder.process(Tok::End(name, span));
+                    }
+                    _ => {}
+                },
+                tok => {
+                    builder.insert_element(Element::new("body", vec![], vec![]));
+                    builder.mode = Mode::InBody;
+                    builder.process(tok);
+                }
+            },
+        );
+    }
+
+    fn in_body(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text) => {
+                self.reconstruct_formatting();
+                self.insert_text(&text);
+            }
+            Tok::Comment(comment) => self.insert_comment(comment, None),
+            Tok::Doctype(_) => {}
+            Tok::Start(element, self_closing) => self.in_body_start(element, self_closing),
+            Tok::End(name, span) => self.in_body_end(name, span),
+            Tok::Eof => {
+                if !self.template_modes.is_empty() {
+                    self.in_template(Tok::Eof);
+                }
+            }
+        }
+    }
+
+    fn in_body_start(&mut self, mut element: Element, self_closing: bool) {
+        let name = element.name.clone();
+        match name.as_str() {
+            "html" => {
+                if self.open_names.has_any(&["template"]) {
+                    return;
+                }
+                self.merge_attrs(0, element);
+            }
+            name if HEAD_TAGS.contains(&name) => self.in_head(Tok::Start(element, self_closing)),
+            "body" => {
+                if self.open.len() < 2 || self.name(self.open[1]) != "body" {
+                    return;
+                }
+                self.merge_attrs(1, element);
+            }
+            "frameset" => {}
+            name if BLOCK_TAGS.contains(&name) => {
+                self.close_p();
+                self.insert_element(element);
+                if name == "listing" {
+                    self.skip_newline = true;
+                }
+            }
+            name if HEADINGS.contains(&name) => {
+                self.close_p();
+                if self.current_is(&HEADINGS) {
+                    self.pop();
+                }
+                self.insert_element(element);
+            }
+            "pre" => {
+                self.close_p();
+                self.insert_element(element);
+                self.skip_newline = true;
+            }
+            "form" => {
+                let in_template = self.open_names.has_any(&["template"]);
+                if self.form.is_some() && !in_template {
+                    return;
+                }
+                self.close_p();
+                let id = self.insert_element(element);
+                if !in_template {
+                    self.form = Some(id);
+                }
+            }
+            "li" | "dd" | "dt" => {
+                let siblings: &[&str] = if name == "li" { &["li"] } else { &["dd", "dt"] };
+                for &id in self.open.iter().rev() {
+                    let node_name = self.name(id);
+                    if siblings.contains(&node_name) {
+                        let node_name = node_name.to_string();
+                        self.generate_implied_end_tags(Some(&node_name));
+                        self.pop_until(&[&node_name], None);
+                        break;
+                    }
+                    if self.is_special(id) && !["address", "div", "p"].contains(&node_name) {
+                        break;
+                    }
+                }
+                self.close_p();
+                self.insert_element(element);
+            }
+            "plaintext" => {
+                self.close_p();
+                self.insert_element(element);
+            }
+            "button" => {
+                if self.in_scope(&["button"]) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["button"], None);
+                }
+                self.reconstruct_formatting();
+                self.insert_element(element);
+            }
+            "a" => {
+                let open_a = self
+                    .formatting
+                    .iter()
+                    .rev()
+                    .take_while(|entry| **entry != Formatting::Marker)
+                    .find_map(|entry| match entry {
+                        Formatting::Element(id) if self.name(*id) == "a" => Some(*id),
+                        _ => None,
+                    });
+                if let Some(a) = open_a {
+                    self.adoption_agency("a", None);
+                    self.formatting
+                        .retain(|entry| entry != &Formatting::Element(a));
+                    self.remove_open(a);
+                }
+                self.reconstruct_formatting();
+                let id = self.insert_element(element);
+                self.push_formatting(id);
+            }
+            "nobr" => {
+                self.reconstruct_formatting();
+                if self.in_scope(&["nobr"]) {
+                    self.adoption_agency("nobr", None);
+                    self.reconstruct_formatting();
+                }
+                let id = self.insert_element(element);
+                self.push_formatting(id);
+            }
+            name if FORMATTING_TAGS.contains(&name) => {
+                self.reconstruct_formatting();
+                let id = self.insert_element(element);
+                self.push_formatting(id);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_formatting();
+                self.insert_element(element);
+                self.formatting.push(Formatting::Marker);
+            }
+            "table" => {
+                if self.quirks_mode != QuirksMode::Quirks {
+                    self.close_p();
+                }
+                self.insert_element(element);
+                self.mode = Mode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" | "input" => {
+                self.reconstruct_formatting();
+                self.insert_void(element);
+            }
+            "param" | "source" | "track" => self.insert_void(element),
+            "hr" => {
+                self.close_p();
+                self.insert_void(element);
+            }
+            "image" => {
+                element.name = "img".to_string();
+                self.in_body_start(element, self_closing);
+            }
+            "textarea" => {
+                self.insert_raw_text(element);
+                self.skip_newline = true;
+            }
+            "xmp" => {
+                self.close_p();
+                self.reconstruct_formatting();
+                self.insert_raw_text(element);
+            }
+            "iframe" | "noembed" | "noscript" => self.insert_raw_text(element),
+            "select" => {
+                self.reconstruct_formatting();
+                self.insert_element(element);
+                self.mode = match self.mode {
+                    Mode::InTable
+                    | Mode::InCaption
+                    | Mode::InTableBody
+                    | Mode::InRow
+                    | Mode::InCell => Mode::InSelectInTable,
+                    _ => Mode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.current_is(&["option"]) {
+                    self.pop();
+                }
+                self.reconstruct_formatting();
+                self.insert_element(element);
+            }
+            "rb" | "rtc" => {
+                if self.in_scope(&["ruby"]) {
+                    self.generate_implied_end_tags(None);
+                }
+                self.insert_element(element);
+            }
+            "rp" | "rt" => {
+                if self.in_scope(&["ruby"]) {
+                    self.generate_implied_end_tags(Some("rtc"));
+                }
+                self.insert_element(element);
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => {}
+            "svg" | "math" => {
+                element.namespace = if name == "svg" {
+                    Namespace::Svg
+                } else {
+                    Namespace::MathMl
+                };
+                self.reconstruct_formatting();
+                if self_closing {
+                    self.insert_void(element);
+                } else {
+                    self.insert_element(element);
+                }
+            }
+            _ => {
+                self.reconstruct_formatting();
+                self.insert_element(element);
+            }
+        }
+    }
+
+    /// Add the attributes of `element` to the open element at `index` if
+    /// they are missing, which is for the duplicated `<html>` and `<body>`.
+    fn merge_attrs(&mut self, index: usize, mut element: Element) {
+        let id = self.open[index];
+        if let Data::Element(target) = &mut self.nodes[id].data {
+            for (key, value) in std::mem::take(&mut element.attrs) {
+                if !target.attrs.iter().any(|(k, _)| k == &key) {
+                    target.attrs.push((key, value));
+                }
+            }
+        }
+    }
+
+    fn in_body_end(&mut self, name: String, span: Option<Span>) {
+        match name.as_str() {
+            "template" => self.in_head(Tok::End(name, span)),
+            "body" | "html" => {
+                if !self.in_scope(&["body"]) {
+                    return;
+                }
+                self.mode = Mode::AfterBody;
+                if name == "html" {
+                    self.process(Tok::End(name, span));
+                }
+            }
+            name if BLOCK_END_TAGS.contains(&name) => {
+                if !self.in_scope(&[name]) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&[name], span);
+            }
+            "form" => {
+                if self.open_names.has_any(&["template"]) {
+                    if !self.in_scope(&["form"]) {
+                        return;
+                    }
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["form"], span);
+                    return;
+                }
+                let Some(form) = self.form.take() else {
+                    return;
+                };
+                if !self.in_scope(&["form"]) || !self.is_open(form) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if let (Some(span), Data::Element(element)) = (span, &mut self.nodes[form].data) {
+                    close_element(element, span.end, Some(span));
+                }
+                self.remove_open(form);
+            }
+            "p" => {
+                if !self.in_button_scope(&["p"]) {
+                    self.insert_element(Element::new("p", vec![], vec![]));
+                }
+                self.generate_implied_end_tags(Some("p"));
+                self.pop_until(&["p"], span);
+            }
+            "li" => {
+                if !self.in_list_item_scope(&["li"]) {
+                    return;
+                }
+                self.generate_implied_end_tags(Some("li"));
+                self.pop_until(&["li"], span);
+            }
+            "dd" | "dt" => {
+                if !self.in_scope(&[name.as_str()]) {
+                    return;
+                }
+                self.generate_implied_end_tags(Some(name.as_str()));
+                self.pop_until(&[name.as_str()], span);
+            }
+            name if HEADINGS.contains(&name) => {
+                if !self.in_scope(&HEADINGS) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&HEADINGS, span);
+            }
+            name if FORMATTING_TAGS.contains(&name) => {
+                if !self.adoption_agency(name, span) {
+                    self.any_other_end_tag(name, span);
+                }
+            }
+            "applet" | "marquee" | "object" => {
+                if !self.in_scope(&[name.as_str()]) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&[name.as_str()], span);
+                self.clear_formatting_to_marker();
+            }
+            "br" => {
+                self.reconstruct_formatting();
+                self.insert_void(Element::new("br", vec![], vec![]));
+            }
+            name => self.any_other_end_tag(name, span),
+        }
+    }
+
+    fn any_other_end_tag(&mut self, name: &str, span: Option<Span>) {
+        for index in (0..self.open.len()).rev() {
+            let id = self.open[index];
+            let node_name = self.name(id);
+            if node_name == name {
+                self.generate_implied_end_tags(Some(name));
+                while self.open.len() > index + 1 {
+                    self.pop();
+                }
+                self.pop_until(&[name], span);
+                return;
+            }
+            if self.is_special(id) {
+                return;
+            }
+        }
+    }
+
+    fn text(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text) => self.insert_text(&text),
+            Tok::End(name, span)
+                if self.raw_text_element.map(|id| self.name(id)) == Some(&name) =>
+            {
+                self.pop_until(&[&name], span);
+                self.raw_text_element = None;
+                self.mode = self.original_mode;
+            }
+            Tok::Eof => {
+                if let Some(id) = self.raw_text_element.take() {
+                    while self.is_open(id) {
+                        self.pop();
+                    }
+                }
+                self.mode = self.original_mode;
+                self.process(Tok::Eof);
+            }
+            // The tokenizer only reads the content of `<script>` and
+            // `<style>` as raw text, so the tags in the others like
+            // `<title>` are kept as elements.
+            tok => self.in_body(tok),
+        }
+    }
+
+    fn in_table(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text)
+                if self.current_is(&["table", "tbody", "template", "tfoot", "thead", "tr"]) =>
+            {
+                if text.chars().all(is_whitespace) {
+                    self.insert_text(&text);
+                } else {
+                    self.foster_parent(Tok::Text(text));
+                }
+            }
+            Tok::Comment(comment) => self.insert_comment(comment, None),
+            Tok::Doctype(_) => {}
+            Tok::Start(element, self_closing) => match element.name.as_str() {
+                "caption" => {
+                    self.clear_to_context(&["table"]);
+                    self.formatting.push(Formatting::Marker);
+                    self.insert_element(element);
+                    self.mode = Mode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_to_context(&["table"]);
+                    self.insert_element(element);
+                    self.mode = Mode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_to_context(&["table"]);
+                    self.insert_element(Element::new("colgroup", vec![], vec![]));
+                    self.mode = Mode::InColumnGroup;
+                    self.process(Tok::Start(element, self_closing));
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_to_context(&["table"]);
+                    self.insert_element(element);
+                    self.mode = Mode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_to_context(&["table"]);
+                    self.insert_element(Element::new("tbody", vec![], vec![]));
+                    self.mode = Mode::InTableBody;
+                    self.process(Tok::Start(element, self_closing));
+                }
+                "table" => {
+                    if !self.in_table_scope(&["table"]) {
+                        return;
+                    }
+                    self.pop_until(&["table"], None);
+                    self.reset_insertion_mode();
+                    self.process(Tok::Start(element, self_closing));
+                }
+                "style" | "script" | "template" => self.in_head(Tok::Start(element, self_closing)),
+                "input"
+                    if element.attrs.iter().any(|(key, value)| {
+                        key == "type" && value.eq_ignore_ascii_case("hidden")
+                    }) =>
+                {
+                    self.insert_void(element)
+                }
+                "form" => {
+                    let in_template = self.open_names.has_any(&["template"]);
+                    if in_template || self.form.is_some() {
+                        return;
+                    }
+                    let id = self.insert_element(element);
+                    self.form = Some(id);
+                    self.remove_open(id);
+                }
+                _ => self.foster_parent(Tok::Start(element, self_closing)),
+            },
+            Tok::End(name, span) => match name.as_str() {
+                "table" => {
+                    if !self.in_table_scope(&["table"]) {
+                        return;
+                    }
+                    self.pop_until(&["table"], span);
+                    self.reset_insertion_mode();
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => {}
+                "template" => self.in_head(Tok::End(name, span)),
+                _ => self.foster_parent(Tok::End(name, span)),
+            },
+            tok => self.in_body(tok),
+        }
+    }
+
+    /// Process the token by "in body" with foster parenting enabled.
+    fn foster_parent(&mut self, tok: Tok) {
+        self.foster_parenting = true;
+        self.in_body(tok);
+        self.foster_parenting = false;
+    }
+
+    fn close_caption(&mut self, span: Option<Span>) -> bool {
+        if !self.in_table_scope(&["caption"]) {
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.pop_until(&["caption"], span);
+        self.clear_formatting_to_marker();
+        self.mode = Mode::InTable;
+        true
+    }
+
+    fn in_caption(&mut self, tok: Tok) {
+        match tok {
+            Tok::End(name, span) if name == "caption" => {
+                self.close_caption(span);
+            }
+            Tok::Start(element, self_closing)
+                if [
+                    "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+                ]
+                .contains(&element.name.as_str()) =>
+            {
+                if self.close_caption(None) {
+                    self.process(Tok::Start(element, self_closing));
+                }
+            }
+            Tok::End(name, span) if name == "table" => {
+                if self.close_caption(None) {
+                    self.process(Tok::End(name, span));
+                }
+            }
+            Tok::End(name, _)
+                if [
+                    "body", "col", "colgroup", "html", "tbody", "td", "tfoot", "th", "thead", "tr",
+                ]
+                .contains(&name.as_str()) => {}
+            tok => self.in_body(tok),
+        }
+    }
+
+    fn in_column_group(&mut self, tok: Tok) {
+        self.split_text(
+            tok,
+            |builder, whitespace| {
+                if !whitespace.is_empty() {
+                    builder.insert_text(whitespace)
+                }
+            },
+            |builder, tok| match tok {
+                Tok::Comment(comment) => builder.insert_comment(comment, None),
+                Tok::Doctype(_) => {}
+                Tok::Start(element, self_closing) if element.name == "html" => {
+                    builder.in_body(Tok::Start(element, self_closing))
+                }
+                Tok::Start(element, _) if element.name == "col" => builder.insert_void(element),
+                Tok::Start(element, self_closing) if element.name == "template" => {
+                    builder.in_head(Tok::Start(element, self_closing))
+                }
+                Tok::End(name, span) if name == "colgroup" => {
+                    if builder.current_is(&["colgroup"]) {
+                        builder.pop_until(&["colgroup"], span);
+                        builder.mode = Mode::InTable;
+                    }
+                }
+                Tok::End(name, _) if name == "col" => {}
+                Tok::End(name, span) if name == "template" => builder.in_head(Tok::End(name, span)),
+                Tok::Eof => builder.in_body(Tok::Eof),
+                tok => {
+                    if builder.current_is(&["colgroup"]) {
+                        builder.pop();
+                        builder.mode = Mode::InTable;
+                        builder.process(tok);
+                    }
+                }
+            },
+        );
+    }
+
+    fn in_table_body(&mut self, tok: Tok) {
+        match tok {
+            Tok::Start(element, self_closing) => match element.name.as_str() {
+                "tr" => {
+                    self.clear_to_context(&TABLE_SECTIONS);
+                    self.insert_element(element);
+                    self.mode = Mode::InRow;
+                }
+                "th" | "td" => {
+                    self.clear_to_context(&TABLE_SECTIONS);
+                    self.insert_element(Element::new("tr", vec![], vec![]));
+                    self.mode = Mode::InRow;
+                    self.process(Tok::Start(element, self_closing));
+                }
+                "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" => {
+                    if !self.in_table_scope(&TABLE_SECTIONS) {
+                        return;
+                    }
+                    self.clear_to_context(&TABLE_SECTIONS);
+                    self.pop();
+                    self.mode = Mode::InTable;
+                    self.process(Tok::Start(element, self_closing));
+                }
+                _ => self.in_table(Tok::Start(element, self_closing)),
+            },
+            Tok::End(name, span) => match name.as_str() {
+                "tbody" | "tfoot" | "thead" => {
+                    if !self.in_table_scope(&[&name]) {
+                        return;
+                    }
+                    self.clear_to_context(&TABLE_SECTIONS);
+                    self.pop_until(&[&name], span);
+                    self.mode = Mode::InTable;
+                }
+                "table" => {
+                    if !self.in_table_scope(&TABLE_SECTIONS) {
+                        return;
+                    }
+                    self.clear_to_context(&TABLE_SECTIONS);
+                    self.pop();
+                    self.mode = Mode::InTable;
+                    self.process(Tok::End(name, span));
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr" => {}
+                _ => self.in_table(Tok::End(name, span)),
+            },
+            tok => self.in_table(tok),
+        }
+    }
+
+    fn close_row(&mut self, span: Option<Span>) -> bool {
+        if !self.in_table_scope(&["tr"]) {
+            return false;
+        }
+        self.clear_to_context(&["tr"]);
+        self.pop_until(&["tr"], span);
+        self.mode = Mode::InTableBody;
+        true
+    }
+
+    fn in_row(&mut self, tok: Tok) {
+        match tok {
+            Tok::Start(element, self_closing) => match element.name.as_str() {
+                "th" | "td" => {
+                    self.clear_to_context(&["tr"]);
+                    self.insert_element(element);
+                    self.mode = Mode::InCell;
+                    self.formatting.push(Formatting::Marker);
+                }
+                "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr" => {
+                    if self.close_row(None) {
+                        self.process(Tok::Start(element, self_closing));
+                    }
+                }
+                _ => self.in_table(Tok::Start(element, self_closing)),
+            },
+            Tok::End(name, span) => match name.as_str() {
+                "tr" => {
+                    self.close_row(span);
+                }
+                "table" => {
+                    if self.close_row(None) {
+                        self.process(Tok::End(name, span));
+                    }
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    if self.in_table_scope(&[&name]) && self.close_row(None) {
+                        self.process(Tok::End(name, span));
+                    }
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" => {}
+                _ => self.in_table(Tok::End(name, span)),
+            },
+            tok => self.in_table(tok),
+        }
+    }
+
+    fn close_cell(&mut self, span: Option<Span>) {
+        self.generate_implied_end_tags(None);
+        self.pop_until(&TABLE_CELLS, span);
+        self.clear_formatting_to_marker();
+        self.mode = Mode::InRow;
+    }
+
+    fn in_cell(&mut self, tok: Tok) {
+        match tok {
+            Tok::End(name, span) if TABLE_CELLS.contains(&name.as_str()) => {
+                if self.in_table_scope(&[&name]) {
+                    self.close_cell(span);
+                }
+            }
+            Tok::Start(element, self_closing)
+                if [
+                    "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+                ]
+                .contains(&element.name.as_str()) =>
+            {
+                if self.in_table_scope(&TABLE_CELLS) {
+                    self.close_cell(None);
+                    self.process(Tok::Start(element, self_closing));
+                }
+            }
+            Tok::End(name, _)
+                if ["body", "caption", "col", "colgroup", "html"].contains(&name.as_str()) => {}
+            Tok::End(name, span)
+                if ["table", "tbody", "tfoot", "thead", "tr"].contains(&name.as_str()) =>
+            {
+                if self.in_table_scope(&[&name]) {
+                    self.close_cell(None);
+                    self.process(Tok::End(name, span));
+                }
+            }
+            tok => self.in_body(tok),
+        }
+    }
+
+    fn in_select(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text) => self.insert_text(&text),
+            Tok::Comment(comment) => self.insert_comment(comment, None),
+            Tok::Doctype(_) => {}
+            Tok::Start(element, self_closing) => match element.name.as_str() {
+                "html" => self.in_body(Tok::Start(element, self_closing)),
+                "option" => {
+                    if self.current_is(&["option"]) {
+                        self.pop();
+                    }
+                    self.insert_element(element);
+                }
+                "optgroup" | "hr" => {
+                    if self.current_is(&["option"]) {
+                        self.pop();
+                    }
+                    if self.current_is(&["optgroup"]) {
+                        self.pop();
+                    }
+                    if element.name == "hr" {
+                        self.insert_void(element);
+                    } else {
+                        self.insert_element(element);
+                    }
+                }
+                "select" if self.in_select_scope(&["select"]) => {
+                    self.pop_until(&["select"], None);
+                    self.reset_insertion_mode();
+                }
+                "input" | "keygen" | "textarea" if self.in_select_scope(&["select"]) => {
+                    self.pop_until(&["select"], None);
+                    self.reset_insertion_mode();
+                    self.process(Tok::Start(element, self_closing));
+                }
+                "script" | "template" => self.in_head(Tok::Start(element, self_closing)),
+                _ => {}
+            },
+            Tok::End(name, span) => match name.as_str() {
+                "optgroup" => {
+                    let len = self.open.len();
+                    if self.current_is(&["option"])
+                        && len > 1
+                        && self.name(self.open[len - 2]) == "optgroup"
+                    {
+                        self.pop();
+                    }
+                    if self.current_is(&["optgroup"]) {
+                        self.pop_until(&["optgroup"], span);
+                    }
+                }
+                "option" if self.current_is(&["option"]) => {
+                    self.pop_until(&["option"], span);
+                }
+                "select" if self.in_select_scope(&["select"]) => {
+                    self.pop_until(&["select"], span);
+                    self.reset_insertion_mode();
+                }
+                "template" => self.in_head(Tok::End(name, span)),
+                _ => {}
+            },
+            Tok::Eof => self.in_body(Tok::Eof),
+        }
+    }
+
+    fn in_select_in_table(&mut self, tok: Tok) {
+        const TABLE_TAGS: [&str; 8] = [
+            "caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th",
+        ];
+        match tok {
+            Tok::Start(element, self_closing) if TABLE_TAGS.contains(&element.name.as_str()) => {
+                self.pop_until(&["select"], None);
+                self.reset_insertion_mode();
+                self.process(Tok::Start(element, self_closing));
+            }
+            Tok::End(name, span) if TABLE_TAGS.contains(&name.as_str()) => {
+                if self.in_table_scope(&[&name]) {
+                    self.pop_until(&["select"], None);
+                    self.reset_insertion_mode();
+                    self.process(Tok::End(name, span));
+                }
+            }
+            tok => self.in_select(tok),
+        }
+    }
+
+    fn in_template(&mut self, tok: Tok) {
+        match tok {
+            Tok::Start(element, self_closing) => {
+                let mode = match element.name.as_str() {
+                    name if HEAD_TAGS.contains(&name) => {
+                        return self.in_head(Tok::Start(element, self_closing))
+                    }
+                    "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => Mode::InTable,
+                    "col" => Mode::InColumnGroup,
+                    "tr" => Mode::InTableBody,
+                    "td" | "th" => Mode::InRow,
+                    _ => Mode::InBody,
+                };
+                self.template_modes.pop();
+                self.template_modes.push(mode);
+                self.mode = mode;
+                self.process(Tok::Start(element, self_closing));
+            }
+            Tok::End(name, span) if name == "template" => self.in_head(Tok::End(name, span)),
+            Tok::End(..) => {}
+            Tok::Eof => {
+                if !self.open_names.has_any(&["template"]) {
+                    return;
+                }
+                self.pop_until(&["template"], None);
+                self.clear_formatting_to_marker();
+                self.template_modes.pop();
+                self.reset_insertion_mode();
+                self.process(Tok::Eof);
+            }
+            tok => self.in_body(tok),
+        }
+    }
+
+    fn after_body(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text) if text.chars().all(is_whitespace) => self.in_body(Tok::Text(text)),
+            Tok::Comment(comment) => {
+                let html = self.open.first().copied();
+                self.insert_comment(comment, html);
+            }
+            Tok::Doctype(_) => {}
+            Tok::Start(element, self_closing) if element.name == "html" => {
+                self.in_body(Tok::Start(element, self_closing))
+            }
+            Tok::End(name, _) if name == "html" => self.mode = Mode::AfterAfterBody,
+            Tok::Eof => {}
+            tok => {
+                self.mode = Mode::InBody;
+                self.process(tok);
+            }
+        }
+    }
+
+    fn in_frameset(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text) => {
+                let whitespace: String = text.chars().filter(|&ch| is_whitespace(ch)).collect();
+                if !whitespace.is_empty() {
+                    self.insert_text(&whitespace);
+                }
+            }
+            Tok::Comment(comment) => self.insert_comment(comment, None),
+            Tok::Start(element, self_closing) => match element.name.as_str() {
+                "html" => self.in_body(Tok::Start(element, self_closing)),
+                "frameset" => {
+                    self.insert_element(element);
+                }
+                "frame" => self.insert_void(element),
+                "noframes" => self.in_head(Tok::Start(element, self_closing)),
+                _ => {}
+            },
+            Tok::End(name, span) if name == "frameset" && self.open.len() > 1 => {
+                self.pop_until(&["frameset"], span);
+                if !self.current_is(&["frameset"]) {
+                    self.mode = Mode::AfterFrameset;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn after_frameset(&mut self, tok: Tok) {
+        match tok {
+            Tok::Text(text) => {
+                let whitespace: String = text.chars().filter(|&ch| is_whitespace(ch)).collect();
+                if !whitespace.is_empty() {
+                    self.insert_text(&whitespace);
+                }
+            }
+            Tok::Comment(comment) => self.insert_comment(comment, None),
+            Tok::Start(element, self_closing) => match element.name.as_str() {
+                "html" => self.in_body(Tok::Start(element, self_closing)),
+                "noframes" => self.in_head(Tok::Start(element, self_closing)),
+                _ => {}
+            },
+            Tok::End(name, _) if name == "html" => self.mode = Mode::AfterAfterFrameset,
+            _ => {}
+        }
+    }
+
+    fn after_after_body(&mut self, tok: Tok) {
+        match tok {
+            Tok::Comment(comment) => self.insert_comment(comment, Some(DOCUMENT)),
+            Tok::Doctype(_) | Tok::Eof => {}
+            Tok::Text(text) if text.chars().all(is_whitespace) => self.in_body(Tok::Text(text)),
+            Tok::Start(element, self_closing) if element.name == "html" => {
+                self.in_body(Tok::Start(element, self_closing))
+            }
+            tok => {
+                self.mode = Mode::InBody;
+                self.process(tok);
+            }
+        }
+    }
+
+    fn after_after_frameset(&mut self, tok: Tok) {
+        match tok {
+            Tok::Comment(comment) => self.insert_comment(comment, Some(DOCUMENT)),
+            Tok::Start(element, self_closing) if element.name == "html" => {
+                self.in_body(Tok::Start(element, self_closing))
+            }
+            Tok::Start(element, self_closing) if element.name == "noframes" => {
+                self.in_head(Tok::Start(element, self_closing))
+            }
+            Tok::Text(text) if text.chars().all(is_whitespace) => self.in_body(Tok::Text(text)),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::ops::Range;
+
+/// A range of bytes in the source html, from `start` (inclusive) to
+/// `end` (exclusive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a new span.
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Get the span as a `Range`, which can be used to slice the source.
+    ///
+    /// ```
+    /// use html_editor::{parse, operation::*};
+    ///
+    /// let html = r#"<div><a href="/">Home</a></div>"#;
+    /// let dom = parse(html).unwrap();
+    /// let a = dom.query(&Selector::from("a")).unwrap();
+    /// let span = a.span().unwrap();
+    ///
+    /// assert_eq!(&html[span.outer.range()], r#"<a href="/">Home</a>"#);
+    /// assert_eq!(&html[span.start_tag.range()], r#"<a href="/">"#);
+    /// assert_eq!(&html[span.end_tag.unwrap().range()], "</a>");
+    /// assert_eq!(&html[span.attrs[0].range()], r#"href="/""#);
+    /// ```
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Length of the span in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Check if the span is empty.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.range()
+    }
+}
+
+/// Where an [`Element`](crate::Element) is in the source html.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ElementSpan {
+    /// The whole element, from the start tag to the end tag.
+    pub outer: Span,
+    /// The start tag, like `<a href="/">`.
+    pub start_tag: Span,
+    /// The end tag, like `</a>`.
+    ///
+    /// It is `None` for void elements, self-closing elements and the
+    /// elements closed automatically by [`try_parse()`](crate::try_parse).
+    pub end_tag: Option<Span>,
+    /// Each attribute, like `href="/"`, in the same order as
+    /// [`Element::attrs`](crate::Element::attrs).
+    pub attrs: Vec<Span>,
+}
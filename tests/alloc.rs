@@ -0,0 +1,65 @@
+//! Count the allocations of parsing, which are made by this thread only
+//! as the tests run in parallel.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use html_editor::{parse, parse_borrowed};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// The number of allocations made by `f`, not counting its result.
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    let count = ALLOCATIONS.with(Cell::get) - before;
+    drop(result);
+    count
+}
+
+const BENCH: &str = include_str!("../benches/bench.html");
+
+#[test]
+fn borrowed_allocates_less() {
+    let owned = allocations(|| parse(BENCH).unwrap());
+    let borrowed = allocations(|| parse_borrowed(BENCH).unwrap());
+    // The owned DOM copies every name, attribute and text.
+    assert!(borrowed * 3 < owned * 2, "{} and {}", borrowed, owned);
+}
+
+#[test]
+fn copy_only_the_decoded_text() {
+    let count = |html: &str| allocations(|| parse_borrowed(html).unwrap());
+    // The owned DOM copies the name, the attribute and the text.
+    let html = r#"<p class="a">text</p>"#;
+    assert_eq!(allocations(|| parse(html).unwrap()), count(html) + 4);
+    // Only the text and the value with references are copied.
+    assert_eq!(count("<p>a &lt; b</p>"), count("<p>a &lx; b</p>") + 1);
+    assert_eq!(
+        count(r#"<a title="&lt;"></a>"#),
+        count(r#"<a title="&lx;"></a>"#) + 1
+    );
+}
@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+
+use html_editor::operation::*;
+use html_editor::{borrowed, parse, parse_borrowed, try_parse, try_parse_borrowed, Node};
+
+const HTML: &str = r#"<!DOCTYPE html>
+<html>
+    <head>
+        <title>Café &amp; Bar</title>
+        <script>if (a < b && c > d) { "</div>" }</script>
+    </head>
+    <body>
+        <!-- comment -->
+        <div id="app" class='a b'>
+            <p>Hello, <b>wörld</b>! 🎉</p>
+            <img src="a.png" alt="a &gt; b">
+            <br/>
+        </div>
+    </body>
+</html>"#;
+
+/// Debug format of the nodes, since `Node` is not `PartialEq`.
+fn debug(dom: &[Node]) -> String {
+    format!("{:?}", dom)
+}
+
+fn to_owned(dom: &[borrowed::Node]) -> Vec<Node> {
+    dom.iter().map(borrowed::Node::to_owned).collect()
+}
+
+#[test]
+fn same_as_parse() {
+    let dom = parse_borrowed(HTML).unwrap();
+    assert_eq!(debug(&to_owned(&dom)), debug(&parse(HTML).unwrap()));
+    assert_eq!(dom.html(), parse(HTML).unwrap().html());
+
+    let html = "<div><p>a<p>b</div><span>c";
+    let dom = try_parse_borrowed(html);
+    assert_eq!(debug(&to_owned(&dom)), debug(&try_parse(html)));
+}
+
+#[test]
+fn borrow_from_source() {
+    let dom = parse_borrowed(HTML).unwrap();
+    let app = dom.query(&Selector::from("#app")).unwrap();
+    assert!(matches!(app.name, Cow::Borrowed("div")));
+    assert!(app
+        .attrs
+        .iter()
+        .all(|(key, value)| matches!((key, value), (Cow::Borrowed(_), Cow::Borrowed(_)))));
+
+    let img = dom.query(&Selector::from("img")).unwrap();
+    let (_, alt) = img.attrs.iter().find(|(key, _)| key == "alt").unwrap();
+    assert_eq!(alt, "a > b");
+    assert!(matches!(alt, Cow::Owned(_)));
+
+    let title = dom.query(&Selector::from("title")).unwrap();
+    assert!(
+        matches!(&title.children[0], borrowed::Node::Text(Cow::Owned(text)) if text == "Café & Bar")
+    );
+    let script = dom.query(&Selector::from("script")).unwrap();
+    assert!(matches!(
+        &script.children[0],
+        borrowed::Node::Text(Cow::Borrowed(_))
+    ));
+}
+
+#[test]
+fn query() {
+    let dom = parse_borrowed(HTML).unwrap();
+    assert_eq!(dom.query_all(&Selector::from("p, b")).len(), 2);
+    assert!(dom.query(&Selector::from(".b")).is_some());
+    assert!(dom.query(&Selector::from(".c")).is_none());
+
+    let mut dom = dom;
+    let b = dom.query_mut(&Selector::from("b")).unwrap();
+    b.children = vec![borrowed::Node::Text(Cow::Borrowed("world"))];
+    assert!(dom.html().contains("<b>world</b>"));
+}
+
+#[test]
+fn spans() {
+    let dom = parse_borrowed(HTML).unwrap();
+    let p = dom.query(&Selector::from("p")).unwrap();
+    let span = p.span().unwrap();
+    assert_eq!(&HTML[span.outer.range()], "<p>Hello, <b>wörld</b>! 🎉</p>");
+    let owned = p.to_owned();
+    assert_eq!(owned.span(), Some(span));
+}
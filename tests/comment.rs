@@ -0,0 +1,214 @@
+use html_editor::operation::*;
+use html_editor::{
+    borrowed, parse, parse_borrowed, parse_document, parse_with, try_parse,
+    try_parse_with_diagnostics, ConditionalBody, Doctype, DuplicateAttrs, NameCase, Node,
+    ParseError, ParseOptions,
+};
+
+const EMAIL: &str = r#"<table><tr><td><!--[if mso]><table role="presentation"><tr><td width="600"><![endif]--><div class="col">A</div><!--[if mso]></td></tr></table><![endif]--><!--[if !mso]><!--><p class="col">B</p><!--<![endif]--><!--[if gte mso 9]><v:rect fill="true"><v:fill color="red"></v:fill></v:rect><![endif]--></td></tr></table>"#;
+
+fn comments(dom: &[Node]) -> Vec<String> {
+    dom.iter()
+        .filter_map(|node| match node {
+            Node::Comment(comment) => Some(comment.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn bogus_comments() {
+    let html = "<!DOCTYPE html><!ELEMENT br EMPTY><![endif]--><!><![if]>";
+    let dom = parse(html).unwrap();
+    assert!(matches!(dom[0], Node::Doctype(Doctype::Html)));
+    assert_eq!(
+        comments(&dom),
+        ["ELEMENT br EMPTY", "[endif]--", "", "[if]"]
+    );
+    assert_eq!(
+        dom.html(),
+        "<!DOCTYPE html><!--ELEMENT br EMPTY--><!--[endif]----><!----><!--[if]-->"
+    );
+
+    // The empty comments may be closed abruptly.
+    let dom = parse("<!-->a<!--->b<!---->").unwrap();
+    assert_eq!(comments(&dom), ["", "", ""]);
+    assert_eq!(dom.html(), "<!---->a<!---->b<!---->");
+}
+
+#[test]
+fn bogus_end_tags() {
+    let html = "a</ b>c</>d</3>";
+    assert_eq!(
+        parse(html).unwrap_err(),
+        ParseError::InvalidTag {
+            tag: "</ b>".to_string(),
+            position: parse(html).unwrap_err().position(),
+        }
+    );
+    let (dom, diagnostics) = try_parse_with_diagnostics(html);
+    assert_eq!(dom.html(), "a<!-- b-->cd<!--3-->");
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|error| error.position().column)
+            .collect::<Vec<_>>(),
+        [2, 8, 12]
+    );
+    assert_eq!(
+        parse_document(html).html(),
+        "<html><head></head><body>a<!-- b-->cd<!--3--></body></html>"
+    );
+}
+
+#[test]
+fn conditional_comments() {
+    let dom = parse(EMAIL).unwrap();
+    assert_eq!(dom.html(), EMAIL);
+    assert_eq!(try_parse(EMAIL).html(), EMAIL);
+
+    let td = dom.query(&Selector::from("td")).unwrap();
+    let conditions: Vec<_> = td
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            Node::ConditionalComment { condition, body } => Some((condition.as_str(), body)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(conditions.len(), 3);
+    assert_eq!(conditions[0].0, "mso");
+    assert!(matches!(
+        conditions[0].1,
+        ConditionalBody::Raw(raw) if raw == r#"<table role="presentation"><tr><td width="600">"#
+    ));
+    assert!(matches!(conditions[1].1, ConditionalBody::Raw(_)));
+    assert_eq!(conditions[2].0, "gte mso 9");
+    let ConditionalBody::Nodes(nodes) = conditions[2].1 else {
+        panic!("the VML is not parsed");
+    };
+    // The spans of the body are of the whole html.
+    let fill = nodes.query(&Selector::from("v:fill")).unwrap();
+    assert_eq!(
+        &EMAIL[fill.span().unwrap().outer.range()],
+        r#"<v:fill color="red"></v:fill>"#
+    );
+
+    // The revealed html is parsed as usual.
+    assert_eq!(dom.query_all(&Selector::from(".col")).len(), 2);
+    assert_eq!(comments(&td.children), ["[if !mso]><!", "<![endif]"]);
+
+    let row = EMAIL
+        .strip_prefix("<table>")
+        .and_then(|html| html.strip_suffix("</table>"))
+        .unwrap();
+    let html = format!(
+        "<!DOCTYPE html><html><head></head><body><table><tbody>{}</tbody></table></body></html>",
+        row
+    );
+    assert_eq!(parse_document(&html).html(), html);
+}
+
+#[test]
+fn downlevel_revealed() {
+    let html = "<div>\n<![if !mso]>\n<p>Not Outlook</p>\n<![endif]>\n</div>";
+    for mut dom in [parse(html).unwrap(), try_parse(html)] {
+        assert_eq!(dom.html(), html);
+        let div = dom[0].as_element().unwrap();
+        let revealed: Vec<_> = div
+            .children
+            .iter()
+            .filter_map(|node| match node {
+                Node::DownlevelRevealed(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(revealed, ["[if !mso]", "[endif]"]);
+        // They are read by IE unlike the comments.
+        assert_eq!(
+            dom.trim().html(),
+            "<div><![if !mso]><p>Not Outlook</p><![endif]></div>"
+        );
+    }
+    assert_eq!(parse_borrowed(html).unwrap().html(), html);
+    let html = format!(
+        "<!DOCTYPE html><html><head></head><body>{}</body></html>",
+        html
+    );
+    assert_eq!(parse_document(&html).html(), html);
+}
+
+#[test]
+fn conditional_options() {
+    // The bodies are parsed with the options of the html around them.
+    let options = ParseOptions {
+        name_case: NameCase::Html,
+        duplicate_attrs: DuplicateAttrs::Error,
+        passthrough: vec![("{{".to_string(), "}}".to_string())],
+        ..Default::default()
+    };
+    let html = "<!--[if mso]><TD WIDTH=600>{{ cell }}</TD><![endif]-->";
+    let dom = parse_with(html, &options).unwrap();
+    assert_eq!(
+        dom.html(),
+        r#"<!--[if mso]><td width="600">{{ cell }}</td><![endif]-->"#
+    );
+    let Node::ConditionalComment {
+        body: ConditionalBody::Nodes(nodes),
+        ..
+    } = &dom[0]
+    else {
+        panic!("the body is not parsed");
+    };
+    let td = nodes[0].as_element().unwrap();
+    assert!(matches!(&td.children[..], [Node::Passthrough(raw)] if raw == "{{ cell }}"));
+
+    // The duplicate attributes are errors in the body too, which is kept
+    // raw then.
+    let html = "<!--[if mso]><td a=1 a=2></td><![endif]-->";
+    assert!(matches!(
+        &parse_with(html, &options).unwrap()[0],
+        Node::ConditionalComment {
+            body: ConditionalBody::Raw(_),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn edit() {
+    let mut dom = parse(EMAIL).unwrap();
+    // The conditional comments are kept, and their bodies are skipped.
+    dom.trim().remove_by(&Selector::from("v:fill"));
+    assert_eq!(dom.html(), EMAIL);
+
+    dom.execute_for(&Selector::from("td"), |td| {
+        for node in &mut td.children {
+            if let Node::ConditionalComment {
+                body: ConditionalBody::Nodes(nodes),
+                ..
+            } = node
+            {
+                nodes.remove_by(&Selector::from("v:fill"));
+            }
+        }
+    });
+    assert_eq!(
+        dom.html(),
+        EMAIL.replace(r#"<v:fill color="red"></v:fill>"#, "")
+    );
+}
+
+#[test]
+fn borrowed() {
+    let dom = parse_borrowed(EMAIL).unwrap();
+    assert_eq!(dom.html(), EMAIL);
+    let td = dom.query(&Selector::from("td")).unwrap();
+    let Some(borrowed::Node::ConditionalComment { condition, body }) = td.children.last() else {
+        panic!("not a conditional comment");
+    };
+    assert!(matches!(condition, std::borrow::Cow::Borrowed("gte mso 9")));
+    assert!(matches!(body, borrowed::ConditionalBody::Nodes(_)));
+    let owned: Vec<Node> = dom.iter().map(|node| node.to_owned()).collect();
+    assert_eq!(owned.html(), EMAIL);
+}
@@ -0,0 +1,192 @@
+use html_editor::operation::*;
+use html_editor::{parse, parse_document, parse_fragment, Element, Node, QuirksMode};
+
+#[test]
+fn implicit_html_head_body() {
+    assert_eq!(
+        parse_document("Hello").html(),
+        "<html><head></head><body>Hello</body></html>"
+    );
+    assert_eq!(
+        parse_document("<!DOCTYPE html><meta charset=utf-8><p>Hi").html(),
+        r#"<!DOCTYPE html><html><head><meta charset="utf-8"></head><body><p>Hi</p></body></html>"#
+    );
+}
+
+#[test]
+fn adoption_agency() {
+    assert_eq!(
+        parse_document("<b><i>1</b>2</i>").html(),
+        "<html><head></head><body><b><i>1</i></b><i>2</i></body></html>"
+    );
+    assert_eq!(
+        parse_document("<a>1<p>2</a>3</p>").html(),
+        "<html><head></head><body><a>1</a><p><a>2</a>3</p></body></html>"
+    );
+}
+
+#[test]
+fn reconstruct_formatting_elements() {
+    assert_eq!(
+        parse_document("<p><b>1<p>2").html(),
+        "<html><head></head><body><p><b>1</b></p><p><b>2</b></p></body></html>"
+    );
+}
+
+#[test]
+fn foster_parenting() {
+    assert_eq!(
+        parse_document("<table>x<tr><td>1</td></tr></table>").html(),
+        "<html><head></head><body>x<table><tbody><tr><td>1</td></tr></tbody></table></body></html>"
+    );
+}
+
+#[test]
+fn implied_table_elements() {
+    assert_eq!(
+        parse_document("<table><td>1<td>2<tr><td>3</table>").html(),
+        "<html><head></head><body><table><tbody><tr><td>1</td><td>2</td></tr><tr><td>3</td></tr></tbody></table></body></html>"
+    );
+}
+
+#[test]
+fn implied_end_tags() {
+    assert_eq!(
+        parse_document("<ul><li>1<li>2</ul><dl><dt>a<dd>b</dl>").html(),
+        "<html><head></head><body><ul><li>1</li><li>2</li></ul><dl><dt>a</dt><dd>b</dd></dl></body></html>"
+    );
+}
+
+#[test]
+fn text_after_body() {
+    assert_eq!(
+        parse_document("<body>1</body>2<!--c-->").html(),
+        "<html><head></head><body>12<!--c--></body></html>"
+    );
+}
+
+#[test]
+fn element_spans() {
+    let html = "<div><p>a</div>";
+    let dom = parse_document(html);
+    let div = dom.query(&"div".into()).unwrap();
+    let span = div.span().unwrap();
+    assert_eq!(&html[span.outer.range()], html);
+    let p = dom.query(&"p".into()).unwrap();
+    let span = p.span().unwrap();
+    assert_eq!(&html[span.outer.range()], "<p>a");
+    assert_eq!(span.end_tag, None);
+}
+
+#[test]
+fn quirks_mode() {
+    let doctype = |html: &str| match &parse(html).unwrap()[0] {
+        Node::Doctype(doctype) => doctype.quirks_mode(),
+        node => panic!("unexpected node {:?}", node),
+    };
+    assert_eq!(doctype("<!DOCTYPE html>"), QuirksMode::NoQuirks);
+    assert_eq!(
+        doctype("<!DOCTYPE html SYSTEM \"about:legacy-compat\">"),
+        QuirksMode::NoQuirks
+    );
+    assert_eq!(doctype("<!DOCTYPE foo>"), QuirksMode::Quirks);
+    assert_eq!(
+        doctype(r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 3.2 Final//EN">"#),
+        QuirksMode::Quirks
+    );
+    assert_eq!(
+        doctype(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#),
+        QuirksMode::Quirks
+    );
+    assert_eq!(
+        doctype(
+            r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">"#
+        ),
+        QuirksMode::LimitedQuirks
+    );
+    assert_eq!(
+        doctype(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "x">"#),
+        QuirksMode::LimitedQuirks
+    );
+
+    // Tables do not close paragraphs in the quirks mode.
+    assert_eq!(
+        parse_document("<p><table></table>").html(),
+        "<html><head></head><body><p><table></table></p></body></html>"
+    );
+    assert_eq!(
+        parse_document("<!DOCTYPE html><p><table></table>").html(),
+        "<!DOCTYPE html><html><head></head><body><p></p><table></table></body></html>"
+    );
+}
+
+#[test]
+fn fragment() {
+    let fragment = |context: &str, html: &str| {
+        parse_fragment(&Element::new(context, vec![], vec![]), html).html()
+    };
+    assert_eq!(fragment("div", "<p>a<p>b"), "<p>a</p><p>b</p>");
+    assert_eq!(
+        fragment("tbody", "<tr><td>a</td></tr><td>b"),
+        "<tr><td>a</td></tr><tr><td>b</td></tr>"
+    );
+    assert_eq!(fragment("tr", "<td>a<th>b"), "<td>a</td><th>b</th>");
+    assert_eq!(
+        fragment("table", "x<tr><td>a"),
+        "x<tbody><tr><td>a</td></tr></tbody>"
+    );
+    assert_eq!(
+        fragment("select", "<option>a<div>b</div><option>c"),
+        "<option>ab</option><option>c</option>"
+    );
+    assert_eq!(fragment("ul", "<li>a<li>b"), "<li>a</li><li>b</li>");
+    // The content of the text elements is never closed by an end tag.
+    assert_eq!(
+        fragment("textarea", "a</textarea><b>&lt;"),
+        "a&lt;/textarea&gt;&lt;b&gt;&lt;"
+    );
+    let script = parse_fragment(
+        &Element::new("SCRIPT", vec![], vec![]),
+        "if (a < b) {}</script><p>",
+    );
+    assert!(matches!(&script[..], [Node::Text(text)] if text == "if (a < b) {}</script><p>"));
+    // Without the context, the rows are dropped like in `parse_document`.
+    assert_eq!(fragment("div", "<tr><td>a"), "a");
+    assert_eq!(
+        fragment("html", "<p>a"),
+        "<head></head><body><p>a</p></body>"
+    );
+}
+
+#[test]
+fn deeply_nested() {
+    // The scope checks don't scan all the open elements when none of
+    // the elements they look for is open, which would take quadratic
+    // time.
+    let n = 40_000;
+    let body = |html: &str| {
+        let document = parse_document(html).html();
+        let start = document.find("<body>").unwrap() + "<body>".len();
+        document[start..document.len() - "</body></html>".len()].to_string()
+    };
+    let divs = "<div>".repeat(n);
+    let closed = "</div>".repeat(n);
+    assert_eq!(body(&divs), format!("{}{}", divs, closed));
+    assert_eq!(
+        body(&format!("{}{}", divs, "</p>".repeat(n))),
+        format!("{}{}{}", divs, "<p></p>".repeat(n), closed)
+    );
+    assert_eq!(
+        body(&format!(
+            "{}{}",
+            divs,
+            "<form></form><select></select>".repeat(n)
+        )),
+        format!(
+            "{}{}{}",
+            divs,
+            "<form></form><select></select>".repeat(n),
+            closed
+        )
+    );
+}
@@ -78,11 +78,7 @@ fn replace() {
         .unwrap()
         .replace_with(&p_selector, |p| {
             let new_text = format!("{} World!", p.children[0].html());
-            Node::Element(Element {
-                name: "p".to_string(),
-                attrs: vec![],
-                children: vec![Node::Text(new_text)],
-            })
+            Node::Element(Element::new("p", vec![], vec![Node::Text(new_text)]))
         })
         .html();
     assert_eq!(html, REPLACED_HTML);
@@ -0,0 +1,93 @@
+use html_editor::operation::*;
+use html_editor::{parse_bytes, Encoding};
+
+fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for unit in "\u{FEFF}".encode_utf16().chain(text.encode_utf16()) {
+        if big_endian {
+            bytes.extend(unit.to_be_bytes());
+        } else {
+            bytes.extend(unit.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[test]
+fn bom() {
+    let (dom, encoding) = parse_bytes(&utf16("<p>wörld 🎉</p>", false)).unwrap();
+    assert_eq!(encoding, Encoding::Utf16Le);
+    assert_eq!(dom.html(), "<p>wörld 🎉</p>");
+
+    let (dom, encoding) = parse_bytes(&utf16("<p>wörld 🎉</p>", true)).unwrap();
+    assert_eq!(encoding, Encoding::Utf16Be);
+    assert_eq!(dom.html(), "<p>wörld 🎉</p>");
+
+    // The BOM wins over `<meta>`, and is not in the text.
+    let (dom, encoding) = parse_bytes(b"\xEF\xBB\xBF<meta charset=latin1>caf\xC3\xA9").unwrap();
+    assert_eq!(encoding, Encoding::Utf8);
+    assert_eq!(dom.html(), r#"<meta charset="latin1">café"#);
+}
+
+#[test]
+fn meta_charset() {
+    let sniff = |html: &[u8]| Encoding::sniff(html);
+    assert_eq!(
+        sniff(b"<meta charset=\"ISO-8859-1\">"),
+        Encoding::Windows1252
+    );
+    assert_eq!(sniff(b"<META CHARSET='cp1252'/>"), Encoding::Windows1252);
+    assert_eq!(
+        sniff(b"<meta http-equiv=Content-Type content=\"text/html; charset=latin1\">\xE9"),
+        Encoding::Windows1252
+    );
+    // The `content` is only used with `http-equiv`.
+    assert_eq!(
+        sniff(b"<meta content=\"text/html; charset=latin1\">\xE9\xE9"),
+        Encoding::Windows1252
+    );
+    assert_eq!(
+        sniff(b"<meta content=\"text/html; charset=latin1\">\xC3\xA9"),
+        Encoding::Utf8
+    );
+    // The declaration of UTF-16 in ASCII bytes means UTF-8.
+    assert_eq!(sniff(b"<meta charset=utf-16>"), Encoding::Utf8);
+    // The `<meta>` in comments and attribute values is skipped.
+    assert_eq!(
+        sniff(b"<!-- <meta charset=latin1> --><a title='<meta charset=latin1>'>"),
+        Encoding::Utf8
+    );
+    assert_eq!(
+        sniff(b"<!DOCTYPE html><html lang=fr><head><meta charset=windows-1252>"),
+        Encoding::Windows1252
+    );
+    // Only the first 1024 bytes are pre-scanned.
+    let mut html = vec![b' '; 1024];
+    html.extend(b"<meta charset=latin1>");
+    assert_eq!(sniff(&html), Encoding::Utf8);
+}
+
+#[test]
+fn fallback() {
+    // The unsupported encodings are ignored.
+    let (dom, encoding) = parse_bytes(b"<meta charset=Shift_JIS><p>caf\xE9</p>").unwrap();
+    assert_eq!(encoding, Encoding::Windows1252);
+    assert_eq!(dom.html(), r#"<meta charset="Shift_JIS"><p>café</p>"#);
+
+    let (_, encoding) = parse_bytes("<p>café</p>".as_bytes()).unwrap();
+    assert_eq!(encoding, Encoding::Utf8);
+    assert_eq!(encoding.name(), "UTF-8");
+}
+
+#[test]
+fn decode() {
+    assert_eq!(
+        Encoding::Windows1252.decode(b"\x80\x81\x9F\xFF"),
+        "€\u{81}Ÿÿ"
+    );
+    assert_eq!(Encoding::Utf8.decode(b"a\xFFb"), "a\u{FFFD}b");
+    assert_eq!(
+        Encoding::Utf16Le.decode(b"a\x00\x00\xD8b"),
+        "a\u{FFFD}\u{FFFD}"
+    );
+}
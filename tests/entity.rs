@@ -0,0 +1,101 @@
+use html_editor::operation::*;
+use html_editor::{parse, Node};
+
+fn text_of(html: &str) -> String {
+    match &parse(html).unwrap()[0] {
+        Node::Element(el) => match &el.children[0] {
+            Node::Text(text) => text.clone(),
+            node => panic!("{:?} is not text", node),
+        },
+        node => panic!("{:?} is not an element", node),
+    }
+}
+
+fn attr_of(html: &str) -> String {
+    let nodes = parse(html).unwrap();
+    let element = nodes[0].as_element().unwrap();
+    element.attrs[0].1.clone()
+}
+
+#[test]
+fn named_references() {
+    assert_eq!(
+        text_of("<p>a &lt; b &amp;&amp; c &gt; d</p>"),
+        "a < b && c > d"
+    );
+    assert_eq!(
+        text_of("<p>&copy; 2023 &CounterClockwiseContourIntegral;</p>"),
+        "© 2023 ∳"
+    );
+    assert_eq!(text_of("<p>&notin; &notit;</p>"), "∉ ¬it;");
+    assert_eq!(text_of("<p>&unknown; & &;</p>"), "&unknown; & &;");
+}
+
+#[test]
+fn numeric_references() {
+    assert_eq!(text_of("<p>&#39;&#x27;&#X27;&#0039</p>"), "''''");
+    assert_eq!(text_of("<p>&#x1F600;</p>"), "😀");
+    assert_eq!(
+        text_of("<p>&#0;&#xD800;&#x110000;</p>"),
+        "\u{FFFD}\u{FFFD}\u{FFFD}"
+    );
+    assert_eq!(text_of("<p>&#x80;&#150;</p>"), "€–");
+    assert_eq!(text_of("<p>&#; &#x;</p>"), "&#; &#x;");
+}
+
+#[test]
+fn attribute_references() {
+    assert_eq!(attr_of(r#"<a title="&quot;hi&quot;"></a>"#), r#""hi""#);
+    assert_eq!(attr_of(r#"<a href="?a=1&amp;b=2"></a>"#), "?a=1&b=2");
+    // Legacy references without `;` are kept in attributes if followed
+    // by `=` or alphanumeric chars.
+    assert_eq!(attr_of(r#"<a href="?a=1&copy=2"></a>"#), "?a=1&copy=2");
+    assert_eq!(attr_of(r#"<a href="?a=1&copyb"></a>"#), "?a=1&copyb");
+    assert_eq!(attr_of(r#"<a title="&copy 2023"></a>"#), "© 2023");
+}
+
+#[test]
+fn raw_text_is_not_decoded() {
+    assert_eq!(text_of("<script>a &amp;&amp; b</script>"), "a &amp;&amp; b");
+    assert_eq!(
+        text_of("<style>a::after { content: '&lt;' }</style>"),
+        "a::after { content: '&lt;' }"
+    );
+}
+
+#[test]
+fn escape_text() {
+    let node = Node::new_element(
+        "p",
+        vec![],
+        vec![Node::Text("a < b & c > d\u{A0}".to_string())],
+    );
+    assert_eq!(node.html(), "<p>a &lt; b &amp; c &gt; d&nbsp;</p>");
+}
+
+#[test]
+fn escape_attribute() {
+    let node = Node::new_element("a", vec![("title", r#"<"Tom" & 'Jerry'>"#)], vec![]);
+    assert_eq!(
+        node.html(),
+        r#"<a title="&lt;&quot;Tom&quot; &amp; 'Jerry'&gt;"></a>"#
+    );
+}
+
+#[test]
+fn raw_text_is_not_escaped() {
+    let script = Node::new_element("script", vec![], vec![Node::Text("a < b && c".to_string())]);
+    assert_eq!(script.html(), "<script>a < b && c</script>");
+    let style = Node::new_element("style", vec![], vec![Node::Text("a > b {}".to_string())]);
+    assert_eq!(style.html(), "<style>a > b {}</style>");
+}
+
+#[test]
+fn round_trip() {
+    let html =
+        r#"<p title="&quot;&amp;">&lt;b&gt; &amp; &copy;</p><script>if (a < b && c) {}</script>"#;
+    assert_eq!(
+        parse(html).unwrap().html(),
+        r#"<p title="&quot;&amp;">&lt;b&gt; &amp; ©</p><script>if (a < b && c) {}</script>"#
+    );
+}
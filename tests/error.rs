@@ -0,0 +1,216 @@
+use html_editor::operation::*;
+use html_editor::{
+    events, parse, parse_document, try_parse, try_parse_with_diagnostics, Node, ParseError, Parser,
+    Position,
+};
+
+#[test]
+fn unclosed_tag() {
+    let error = parse("<p>\n  <div>").unwrap_err();
+    assert_eq!(
+        error,
+        ParseError::UnclosedTag {
+            name: "div".to_string(),
+            start: Position {
+                offset: 6,
+                line: 2,
+                column: 3
+            }
+        }
+    );
+    assert_eq!(error.to_string(), "<div> is not closed at 2:3");
+}
+
+#[test]
+fn mismatched_end_tag() {
+    let error = parse("<div><span>ok</div>").unwrap_err();
+    match error {
+        ParseError::MismatchedEndTag {
+            start_name,
+            end_name,
+            start,
+            end,
+        } => {
+            assert_eq!(start_name, "span");
+            assert_eq!(end_name, "div");
+            assert_eq!(start.offset, 5);
+            assert_eq!(end.offset, 13);
+        }
+        _ => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[test]
+fn stray_end_tag() {
+    let error = parse("<br>\n</p>").unwrap_err();
+    assert!(matches!(error, ParseError::StrayEndTag { .. }));
+    assert_eq!(error.position().line, 2);
+    assert_eq!(error.position().column, 1);
+    assert_eq!(error.start_tag_position(), None);
+}
+
+#[test]
+fn invalid_tag() {
+    let error = parse("a </ b>").unwrap_err();
+    assert!(matches!(error, ParseError::InvalidTag { .. }));
+    assert_eq!(error.position().offset, 2);
+}
+
+#[test]
+fn malformed_attribute() {
+    let error = parse("<p>\n<a href=>link</a></p>").unwrap_err();
+    assert!(matches!(error, ParseError::MalformedAttribute { .. }));
+    assert_eq!(error.position().line, 2);
+}
+
+#[test]
+fn unterminated_attribute_value() {
+    let html = "<p>a</p>\n<a href=\"x>y</a><p>z</p>";
+    let error = parse(html).unwrap_err();
+    assert!(
+        matches!(&error, ParseError::MalformedAttribute { reason, .. }
+        if reason == "Unterminated attribute value `x>y</a><p>z</p>`")
+    );
+    assert_eq!(error.position().offset, 9);
+    assert_eq!(error.position().line, 2);
+
+    // The tag is dropped with the rest of the html, like browsers do.
+    let (dom, diagnostics) = try_parse_with_diagnostics(html);
+    assert_eq!(dom.html(), "<p>a</p>\n");
+    assert_eq!(diagnostics, [error]);
+    assert_eq!(try_parse("<a href='x").html(), "");
+}
+
+#[test]
+fn multibyte_columns() {
+    let error = parse("<p>你好</b>").unwrap_err();
+    assert_eq!(
+        error.position(),
+        Position {
+            offset: 9,
+            line: 1,
+            column: 6
+        }
+    );
+}
+
+#[test]
+fn std_error() {
+    let error: Box<dyn std::error::Error> = Box::new(parse("<div>").unwrap_err());
+    assert_eq!(error.to_string(), "<div> is not closed at 1:1");
+}
+
+#[test]
+fn hostile_input() {
+    let inputs = [
+        "/>",
+        "a />",
+        "<é a=1/>",
+        "< >",
+        "<  />",
+        "</>",
+        "<!>",
+        "<?xml?>",
+        r#"<?xml a="?>"#,
+        "<a b=>",
+        r#"<a b="c\">"#,
+        "<script></style></script>",
+        "<textarea></x></textarea",
+        "<table><td><b><p>a</table></b>",
+        "<![CDATA[",
+        "<!--",
+    ];
+    for html in inputs {
+        let _ = parse(html);
+        let _ = try_parse(html);
+        let _ = parse_document(html);
+        let _ = events(html).count();
+        let mut parser = Parser::fault_tolerant();
+        for chunk in html.as_bytes().chunks(1) {
+            parser.feed_bytes(chunk).unwrap();
+        }
+        parser.finish().unwrap();
+    }
+}
+
+#[test]
+fn fault_tolerant_recovery() {
+    // The errors of `parse()` are fixed by `try_parse()`.
+    assert!(parse("<a href=>link</a>").is_err());
+    assert_eq!(try_parse("<a href=>link</a>").html(), "<a href>link</a>");
+
+    assert!(parse(r#"<?xml version="?>"#).is_err());
+    assert!(matches!(
+        &try_parse(r#"<?xml version="?>"#)[0],
+        Node::Doctype(_)
+    ));
+
+    // The trailing `/` of an end tag is ignored.
+    assert_eq!(parse("<div></div/>").unwrap().html(), "<div></div>");
+    assert_eq!(try_parse("<div>a</div />b").html(), "<div>a</div>b");
+    assert_eq!(try_parse("<p>a</p/><br>").html(), "<p>a</p><br>");
+
+    assert!(parse("a </ b>").is_err());
+    assert!(
+        matches!(&try_parse("a </ b>")[..], [Node::Text(_), Node::Comment(comment)] if comment == " b")
+    );
+
+    // A tag starts with an ASCII letter, but the rest of its name may
+    // be any chars.
+    assert!(matches!(&try_parse("<é a=1/>")[..], [Node::Text(text)] if text == "<é a=1/>"));
+    let dom = try_parse("<aé a=1/>");
+    let element = dom[0].as_element().unwrap();
+    assert_eq!(element.name, "aé");
+    assert_eq!(element.attrs, vec![("a".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn diagnostics() {
+    let html = "<ul>\n<li><b>a<li id=1 id=2>b</ul>\n<h1>c</h2></span>\n<p>d<div>e";
+    let (dom, diagnostics) = try_parse_with_diagnostics(html);
+    assert_eq!(dom.html(), try_parse(html).html());
+    let at = |line, column| Position {
+        offset: html
+            .split('\n')
+            .take(line - 1)
+            .map(|line| line.len() + 1)
+            .sum::<usize>()
+            + column
+            - 1,
+        line,
+        column,
+    };
+    assert_eq!(
+        diagnostics,
+        [
+            ParseError::UnclosedTag {
+                name: "b".to_string(),
+                start: at(2, 5),
+            },
+            ParseError::DuplicateAttribute {
+                name: "id".to_string(),
+                position: at(2, 9),
+            },
+            ParseError::MismatchedEndTag {
+                start_name: "h1".to_string(),
+                end_name: "h2".to_string(),
+                start: at(3, 1),
+                end: at(3, 6),
+            },
+            ParseError::StrayEndTag {
+                name: "span".to_string(),
+                end: at(3, 11),
+            },
+            ParseError::UnclosedTag {
+                name: "div".to_string(),
+                start: at(4, 5),
+            },
+        ]
+    );
+
+    // The valid html has no diagnostics, even with the omitted end tags.
+    let (_, diagnostics) =
+        try_parse_with_diagnostics("<ul><li>a<li>b</ul><p>c<table><tr><td>d</table>");
+    assert!(diagnostics.is_empty());
+    assert!(try_parse_with_diagnostics("<div></div>").1.is_empty());
+}
@@ -0,0 +1,81 @@
+use html_editor::{events, events_with, Doctype, Event, NameCase, ParseOptions};
+
+#[test]
+fn all_events() {
+    let html = r#"<!DOCTYPE html><!--c--><p class="a">x &amp; y<br/></p><![CDATA[z]]><?pi data?>"#;
+    let events: Vec<Event> = events(html).map(|event| event.unwrap().0).collect();
+    assert_eq!(
+        events,
+        vec![
+            Event::Doctype(Doctype::Html),
+            Event::Comment("c".to_string()),
+            Event::StartTag {
+                name: "p".to_string(),
+                attrs: vec![("class".to_string(), "a".to_string())],
+                self_closing: false,
+            },
+            Event::Text("x & y".to_string()),
+            Event::StartTag {
+                name: "br".to_string(),
+                attrs: vec![],
+                self_closing: true,
+            },
+            Event::EndTag {
+                name: "p".to_string()
+            },
+            Event::CData("z".to_string()),
+            Event::ProcessingInstruction {
+                target: "pi".to_string(),
+                data: " data".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn spans() {
+    let html = "<div>\n  <span>Hi</span>\n</div>";
+    for event in events(html) {
+        let (event, span) = event.unwrap();
+        let source = &html[span.range()];
+        match event {
+            Event::StartTag { name, .. } => assert_eq!(source, format!("<{}>", name)),
+            Event::EndTag { name } => assert_eq!(source, format!("</{}>", name)),
+            Event::Text(text) => assert_eq!(source, text),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+}
+
+#[test]
+fn count_tags() {
+    let html = "<ul><li>1</li><li>2</li><li>3</li></ul>".repeat(100);
+    let count = events(&html)
+        .filter(|event| matches!(event, Ok((Event::StartTag { name, .. }, _)) if name == "li"))
+        .count();
+    assert_eq!(count, 300);
+}
+
+#[test]
+fn lazy() {
+    // The events before the malformed tag are yielded first.
+    let mut events = events("<p>a</p><a href=>");
+    assert!(matches!(
+        events.next(),
+        Some(Ok((Event::StartTag { .. }, _)))
+    ));
+    assert!(matches!(events.next(), Some(Ok((Event::Text(_), _)))));
+    assert!(matches!(events.next(), Some(Ok((Event::EndTag { .. }, _)))));
+    assert!(matches!(events.next(), Some(Err(_))));
+    assert!(events.next().is_none());
+}
+
+#[test]
+fn with_options() {
+    let options = ParseOptions {
+        name_case: NameCase::Html,
+        ..Default::default()
+    };
+    let (event, _) = events_with("<DIV>", &options).next().unwrap().unwrap();
+    assert!(matches!(event, Event::StartTag { name, .. } if name == "div"));
+}
@@ -0,0 +1,136 @@
+use html_editor::operation::*;
+use html_editor::{
+    parse, parse_document, parse_fragment, parse_with, try_parse, Element, NameCase, Namespace,
+    ParseOptions,
+};
+
+fn namespace(dom: &Vec<html_editor::Node>, selector: &str) -> Namespace {
+    dom.query(&Selector::from(selector))
+        .unwrap()
+        .namespace
+        .clone()
+}
+
+#[test]
+fn namespaces() {
+    let html = r#"<div><svg><rect/><foreignObject><p>a</p></foreignObject></svg><math><mi>x</mi></math></div>"#;
+    let dom = parse(html).unwrap();
+    assert_eq!(namespace(&dom, "div"), Namespace::Html);
+    assert_eq!(namespace(&dom, "svg"), Namespace::Svg);
+    assert_eq!(namespace(&dom, "rect"), Namespace::Svg);
+    assert_eq!(namespace(&dom, "foreignObject"), Namespace::Svg);
+    assert_eq!(namespace(&dom, "p"), Namespace::Html);
+    assert_eq!(namespace(&dom, "math"), Namespace::MathMl);
+    assert_eq!(namespace(&dom, "mi"), Namespace::MathMl);
+    assert_eq!(Namespace::Svg.url(), "http://www.w3.org/2000/svg");
+
+    // `<svg>` is a MathML element in `<math>`, except in `<annotation-xml>`.
+    let dom =
+        parse("<math><svg></svg><annotation-xml><svg></svg></annotation-xml></math>").unwrap();
+    let svgs = dom.query_all(&Selector::from("svg"));
+    assert_eq!(svgs[0].namespace, Namespace::MathMl);
+    assert_eq!(svgs[1].namespace, Namespace::Svg);
+}
+
+#[test]
+fn self_closing() {
+    // The self-closing tags are only void in the foreign content.
+    let dom = try_parse("<svg><path/><circle/></svg><div/>text");
+    assert_eq!(
+        dom.html(),
+        "<svg><path></path><circle></circle></svg><div></div>text"
+    );
+    assert_eq!(
+        parse_document("<svg><g/><rect/></svg><div/>text").html(),
+        "<html><head></head><body><svg><g></g><rect></rect></svg><div>text</div></body></html>"
+    );
+
+    // Neither void nor raw text elements are special in the foreign content.
+    let html = "<svg><style>a &lt; b</style><image></image></svg>";
+    assert_eq!(parse(html).unwrap().html(), html);
+}
+
+#[test]
+fn break_out() {
+    let html = "<svg><g><p>a</p><font color=red>b</font></svg>";
+    assert_eq!(
+        try_parse(html).html(),
+        r#"<svg><g></g></svg><p>a</p><font color="red">b</font>"#
+    );
+    assert_eq!(
+        parse_document(html).html(),
+        r#"<html><head></head><body><svg><g></g></svg><p>a</p><font color="red">b</font></body></html>"#
+    );
+
+    // The HTML integration points keep the HTML content.
+    let html = "<svg><foreignObject><p>a</p></foreignObject><title><b>b</b></title></svg>";
+    assert_eq!(try_parse(html).html(), html);
+    let html = r#"<math><mi><b>x</b></mi><annotation-xml encoding="text/html"><p>y</p></annotation-xml></math>"#;
+    assert_eq!(
+        parse_document(html).html(),
+        format!("<html><head></head><body>{}</body></html>", html)
+    );
+}
+
+#[test]
+fn name_case() {
+    let html = r#"<SVG VIEWBOX="0 0 1 1"><CLIPPATH/></SVG><div viewBox></div><math definitionURL=a></math>"#;
+    let expected = r#"<svg viewBox="0 0 1 1"><clipPath></clipPath></svg><div viewbox></div><math definitionURL="a"></math>"#;
+    let options = ParseOptions {
+        name_case: NameCase::Html,
+        ..Default::default()
+    };
+    assert_eq!(parse_with(html, &options).unwrap().html(), expected);
+    assert_eq!(
+        parse_document(html).html(),
+        format!("<html><head></head><body>{}</body></html>", expected)
+    );
+}
+
+#[test]
+fn fragment() {
+    let mut svg = Element::new("svg", vec![], vec![]);
+    svg.namespace = Namespace::Svg;
+    let dom = parse_fragment(&svg, "<rect/><p>a");
+    assert_eq!(dom.html(), "<rect></rect><p>a</p>");
+    assert_eq!(namespace(&dom, "rect"), Namespace::Svg);
+    assert_eq!(namespace(&dom, "p"), Namespace::Html);
+}
+
+#[test]
+fn selector() {
+    let dom = parse_document("<a>1</a><svg><a>2</a><rect/></svg><math><mi>x</mi></math>");
+    let count = |selector: &str| dom.query_all(&Selector::from(selector)).len();
+    assert_eq!(count("a"), 2);
+    assert_eq!(count("svg|a"), 1);
+    assert_eq!(count("html|a"), 1);
+    assert_eq!(count("*|a"), 2);
+    assert_eq!(count("svg|*"), 3);
+    assert_eq!(count("math|mi"), 1);
+    assert_eq!(count("xlink|a"), 0);
+    assert_eq!(count("*"), 9);
+    assert_eq!(
+        format!("{:?}", Selector::from("svg|rect")),
+        r#"Selector([CompoundSelector([Namespace(Some(Svg)), Tag("rect")])])"#
+    );
+}
+
+#[test]
+fn unmatched_end_tags() {
+    // The end tags which close no foreign element don't scan all the
+    // open ones, which would take quadratic time.
+    let n = 40_000;
+    let html = format!("{}{}", "<svg>".repeat(n), "</x>".repeat(n));
+    assert_eq!(
+        try_parse(&html).html(),
+        format!("{}{}", "<svg>".repeat(n), "</svg>".repeat(n))
+    );
+    let html = format!(
+        "<svg><foreignObject><div><svg>{}{}",
+        "<g>".repeat(n),
+        "</foreignObject>".repeat(n)
+    );
+    let dom = try_parse(&html);
+    assert_eq!(dom.query_all(&Selector::from("svg|g")).len(), n);
+    assert!(dom.html().ends_with("</svg></div></foreignObject></svg>"));
+}
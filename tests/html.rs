@@ -21,3 +21,35 @@ fn trimmed_html() {
         r#"<div><span id="class">Hello</span><span class="id">World</span></div>"#
     );
 }
+
+#[test]
+fn xml_nodes() {
+    let cases = [
+        r#"<?xml version="1.0"?><svg/>"#,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+        r#"<?xml-stylesheet href="a.css" type="text/css"?>"#,
+        "<?php echo '>'; ?>",
+        "<?target?>",
+        "<svg><![CDATA[ x < y && y > z ]]></svg>",
+        "<![CDATA[]]>",
+    ];
+    for html in cases {
+        let html = html.replace("<svg/>", "<svg></svg>");
+        assert_eq!(parse(&html).unwrap().html(), html);
+    }
+}
+
+#[test]
+fn doctypes() {
+    let cases = [
+        "<!DOCTYPE html>",
+        r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd">"#,
+        r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#,
+        r#"<!DOCTYPE html SYSTEM "about:legacy-compat">"#,
+        r#"<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" 'http://a"b'>"#,
+    ];
+    for html in cases {
+        assert_eq!(parse(html).unwrap().html(), html);
+    }
+    assert_eq!(parse("<!doctype html>").unwrap().html(), "<!DOCTYPE html>");
+}
@@ -0,0 +1,202 @@
+use html_editor::operation::*;
+use html_editor::{
+    parse_with, try_parse_with, try_parse_with_options_and_diagnostics, ConditionalBody, Limit,
+    Limits, Node, ParseError, ParseOptions, Parser,
+};
+
+fn options(limits: Limits) -> ParseOptions {
+    ParseOptions {
+        limits,
+        ..Default::default()
+    }
+}
+
+fn exceeded(html: &str, options: &ParseOptions) -> (Limit, usize, usize) {
+    match parse_with(html, options).unwrap_err() {
+        ParseError::LimitExceeded {
+            limit,
+            max,
+            position,
+        } => (limit, max, position.offset),
+        error => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn input_len() {
+    let options = options(Limits {
+        max_input_len: Some(12),
+        ..Default::default()
+    });
+    assert_eq!(
+        parse_with("<p>ok</p>", &options).unwrap().html(),
+        "<p>ok</p>"
+    );
+    let html = "<p>Hello, wörld!</p>";
+    assert_eq!(exceeded(html, &options), (Limit::InputLen, 12, 11));
+    // The char crossing the limit is dropped as a whole.
+    assert_eq!(try_parse_with(html, &options).html(), "<p>Hello, w</p>");
+}
+
+#[test]
+fn depth() {
+    let options = options(Limits {
+        max_depth: Some(2),
+        ..Default::default()
+    });
+    // The void elements and the text are not nested any deeper.
+    let html = "<div><p>a<br>b</p></div><div></div>";
+    assert_eq!(parse_with(html, &options).unwrap().html(), html);
+
+    let html = "<div><ul><li><b>a</b></li></ul><p>b</p></div>";
+    assert_eq!(exceeded(html, &options), (Limit::Depth, 2, 9));
+    assert_eq!(
+        try_parse_with(html, &options).html(),
+        "<div><ul>a</ul><p>b</p></div>"
+    );
+
+    // The end tags of the dropped elements are dropped too.
+    let shallow = ParseOptions {
+        limits: Limits {
+            max_depth: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let html = "<div><p>x<b>y</b></p>z<i>w</div><p>v</p>";
+    let (dom, diagnostics) = try_parse_with_options_and_diagnostics(html, &shallow);
+    assert_eq!(dom.html(), "<div>xyzw</div><p>v</p>");
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        [
+            "Nesting depth exceeds the limit of 1 at 1:6",
+            "Nesting depth exceeds the limit of 1 at 1:10",
+            "Nesting depth exceeds the limit of 1 at 1:23",
+        ]
+    );
+
+    // Deep html is never a problem for the fault tolerant parsers.
+    let html = "<div>".repeat(100_000);
+    let dom = try_parse_with(&html, &options);
+    assert_eq!(dom.html(), "<div><div></div></div>");
+}
+
+#[test]
+fn nodes() {
+    let options = options(Limits {
+        max_nodes: Some(3),
+        ..Default::default()
+    });
+    let html = "<p>a<!-- b --></p><p>c</p>";
+    assert_eq!(exceeded(html, &options), (Limit::Nodes, 3, 18));
+    let (dom, diagnostics) = try_parse_with_options_and_diagnostics(html, &options);
+    assert_eq!(dom.html(), "<p>a<!-- b --></p>");
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        ["Node count exceeds the limit of 3 at 1:19"]
+    );
+}
+
+#[test]
+fn conditional_comments() {
+    let options = options(Limits {
+        max_depth: Some(4),
+        max_nodes: Some(10),
+        ..Default::default()
+    });
+    // The bodies are counted against the limits of the whole html.
+    let html = "<div><!--[if mso]><p><b>a</b></p><![endif]--></div>";
+    assert_eq!(parse_with(html, &options).unwrap().html(), html);
+    let html = format!(
+        "<!--[if mso]>{}{}<![endif]-->",
+        "<div>".repeat(1000),
+        "</div>".repeat(1000)
+    );
+    assert_eq!(exceeded(&html, &options), (Limit::Depth, 4, 33));
+    let html = format!("<!--[if mso]>{}<![endif]-->", "<br>".repeat(10));
+    assert_eq!(exceeded(&html, &options), (Limit::Nodes, 10, 49));
+
+    // The body exceeding the limits is kept raw by the fault tolerant
+    // parsers.
+    let (dom, diagnostics) = try_parse_with_options_and_diagnostics(&html, &options);
+    assert_eq!(dom.html(), html);
+    assert!(matches!(
+        &dom[0],
+        Node::ConditionalComment {
+            body: ConditionalBody::Raw(_),
+            ..
+        }
+    ));
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        ["Node count exceeds the limit of 10 at 1:50"]
+    );
+}
+
+#[test]
+fn attrs() {
+    let options = options(Limits {
+        max_attrs: Some(2),
+        max_attr_len: Some(4),
+        ..Default::default()
+    });
+    let html = r#"<a id="x" class="y" href="z"></a>"#;
+    assert_eq!(exceeded(html, &options), (Limit::Attrs, 2, 0));
+    assert_eq!(
+        try_parse_with(html, &options).html(),
+        r#"<a id="x" class="y"></a>"#
+    );
+
+    let html = r#"<img alt="short"><img alt="ab€d">"#;
+    assert_eq!(exceeded(html, &options), (Limit::AttrLen, 4, 0));
+    let (dom, diagnostics) = try_parse_with_options_and_diagnostics(html, &options);
+    assert_eq!(dom.html(), r#"<img alt="shor"><img alt="ab">"#);
+    assert_eq!(diagnostics.len(), 2);
+}
+
+#[test]
+fn text_len() {
+    let options = options(Limits {
+        max_text_len: Some(3),
+        ..Default::default()
+    });
+    let html = "<p>abc</p><!--abc-->";
+    assert_eq!(parse_with(html, &options).unwrap().html(), html);
+
+    let html = "<p>abcd</p><!--abcd--><script>abcd</script>ab";
+    assert_eq!(exceeded(html, &options), (Limit::TextLen, 3, 3));
+    assert_eq!(
+        try_parse_with(html, &options).html(),
+        "<p>abc</p><!--abc--><script>abc</script>ab"
+    );
+    assert_eq!(try_parse_with("é&amp;é", &options).html(), "é&amp;");
+}
+
+#[test]
+fn parser() {
+    let options = options(Limits {
+        max_input_len: Some(8),
+        ..Default::default()
+    });
+    let mut parser = Parser::with_options(options.clone(), false);
+    parser.feed("<p>ab").unwrap();
+    let error = parser.feed("cdef</p>").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Input length exceeds the limit of 8 at 1:9"
+    );
+
+    let mut parser = Parser::with_options(options, true);
+    parser.feed("<p>ab").unwrap();
+    parser.feed("cdef</p>").unwrap();
+    assert_eq!(parser.finish().unwrap().html(), "<p>abcde</p>");
+}
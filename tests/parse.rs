@@ -1,5 +1,8 @@
 use html_editor::operation::*;
-use html_editor::{parse, try_parse};
+use html_editor::{
+    parse, parse_document, parse_with, try_parse, try_parse_with, Doctype, DuplicateAttrs,
+    NameCase, Node, ParseError, ParseOptions, Parser,
+};
 
 #[test]
 fn paired_tag() {
@@ -73,7 +76,7 @@ fn complex() {
     let mut dom = parse(
         r#"
             <input value="<p value='haha'></p>" disable placeholder=input>
-            <input value="\"\"''/>">
+            <input value="&quot;&quot;''/>">
             <!-- <p></p> -->
             <!------------->
             <a b="" c="d"></a>
@@ -127,3 +130,365 @@ fn xml() {
     .unwrap();
     html.trim();
 }
+
+#[test]
+fn implied_end_tags() {
+    let cases = [
+        ("<ul><li>a<li>b</ul>", "<ul><li>a</li><li>b</li></ul>"),
+        ("<p>one<p>two", "<p>one</p><p>two</p>"),
+        ("<p>one<div>two</div>", "<p>one</p><div>two</div>"),
+        ("<div><p>one<p>two</div>", "<div><p>one</p><p>two</p></div>"),
+        (
+            "<dl><dt>a<dd>b<dt>c<dd>d</dl>",
+            "<dl><dt>a</dt><dd>b</dd><dt>c</dt><dd>d</dd></dl>",
+        ),
+        (
+            "<select><option>a<option>b<optgroup><option>c<optgroup></select>",
+            "<select><option>a</option><option>b</option><optgroup><option>c</option></optgroup><optgroup></optgroup></select>",
+        ),
+        (
+            "<table><tr><td>1<td>2<tr><th>3</table>",
+            "<table><tr><td>1</td><td>2</td></tr><tr><th>3</th></tr></table>",
+        ),
+        (
+            "<table><thead><tr><th>a<tbody><tr><td>b<tfoot><tr><td>c</table>",
+            "<table><thead><tr><th>a</th></tr></thead><tbody><tr><td>b</td></tr></tbody><tfoot><tr><td>c</td></tr></tfoot></table>",
+        ),
+        (
+            "<ruby>a<rb>b<rt>c<rp>d<rtc>e</ruby>",
+            "<ruby>a<rb>b</rb><rt>c</rt><rp>d</rp><rtc>e</rtc></ruby>",
+        ),
+    ];
+    for (html, expected) in cases {
+        assert_eq!(try_parse(html).html(), expected, "{}", html);
+    }
+}
+
+#[test]
+fn implied_end_tags_with_scope() {
+    // Nested lists do not close the outer `li`.
+    assert_eq!(
+        try_parse("<ul><li>a<ul><li>b</ul><li>c</ul>").html(),
+        "<ul><li>a<ul><li>b</li></ul></li><li>c</li></ul>"
+    );
+    // `p` in a button is not closed by the elements outside.
+    assert_eq!(
+        try_parse("<p><button><div>a</div></button></p>").html(),
+        "<p><button><div>a</div></button></p>"
+    );
+    // Stray `</p>` is treated as `<p></p>`.
+    assert_eq!(try_parse("<div></p></div>").html(), "<div><p></p></div>");
+    // Tables in cells are not affected by the outer rows.
+    assert_eq!(
+        try_parse("<table><tr><td><table><tr><td>a</table><td>b</table>").html(),
+        "<table><tr><td><table><tr><td>a</td></tr></table></td><td>b</td></tr></table>"
+    );
+}
+
+#[test]
+fn large_text_content() {
+    // The text is not read again for every `>` in it, which would take
+    // quadratic time.
+    let css = "ul > li > a { color: red }\n".repeat(20_000);
+    let html = format!("<style>{}</style><p>a</p>", css);
+    let dom = parse(&html).unwrap();
+    let style = dom[0].as_element().unwrap();
+    assert!(matches!(&style.children[..], [Node::Text(text)] if *text == css));
+    assert_eq!(dom[1].html(), "<p>a</p>");
+
+    let script = format!("<script>{}</script >", ">".repeat(40_000));
+    assert_eq!(try_parse(&script).html(), script.replace(" >", ">"));
+    let title = format!("<title>{}</title>", "a<b>".repeat(20_000));
+    assert_eq!(
+        parse(&title).unwrap()[0]
+            .as_element()
+            .unwrap()
+            .children
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn text_content_elements() {
+    let cases = [
+        (
+            "<textarea><b>&amp;</textarea>",
+            "<textarea>&lt;b&gt;&amp;</textarea>",
+        ),
+        ("<title>a<b</title>", "<title>a&lt;b</title>"),
+        ("<xmp><p>a</p></xmp>", "<xmp><p>a</p></xmp>"),
+        ("<iframe><p></iframe>", "<iframe><p></iframe>"),
+        ("<noscript><img></noscript>", "<noscript><img></noscript>"),
+        ("<script>a</b></SCRIPT >", "<script>a</b></script>"),
+        ("<style>a{}</style\n>", "<style>a{}</style>"),
+    ];
+    for (html, expected) in cases {
+        assert_eq!(parse(html).unwrap().html(), expected, "{}", html);
+    }
+    // The end tags of other elements do not close them.
+    assert_eq!(
+        parse("<script></scripts></script>").unwrap().html(),
+        "<script></scripts></script>"
+    );
+    // The uppercase names are raw text too, whose content is not escaped.
+    for html in [
+        "<SCRIPT>a<b && c</SCRIPT>",
+        "<Style>a > b { content: '&amp;' }</Style>",
+    ] {
+        assert_eq!(parse(html).unwrap().html(), html);
+        assert_eq!(try_parse(html).html(), html);
+        assert_eq!(html_editor::parse_borrowed(html).unwrap().html(), html);
+    }
+    // `<plaintext>` is never closed.
+    assert_eq!(
+        try_parse("<plaintext></plaintext><p>").html(),
+        "<plaintext></plaintext><p></plaintext>"
+    );
+}
+
+#[test]
+fn cdata_and_processing_instructions() {
+    let dom = parse(r#"<?xml-stylesheet href="a.css"?><svg><![CDATA[<a>]]></svg>"#).unwrap();
+    match &dom[0] {
+        Node::ProcessingInstruction { target, data } => {
+            assert_eq!(target, "xml-stylesheet");
+            assert_eq!(data, r#" href="a.css""#);
+        }
+        node => panic!("unexpected node {:?}", node),
+    }
+    match &dom[1] {
+        Node::Element(svg) => {
+            assert!(matches!(&svg.children[..], [Node::CData(text)] if text == "<a>"))
+        }
+        node => panic!("unexpected node {:?}", node),
+    }
+    // An XML declaration without the version is not valid.
+    assert!(matches!(
+        &parse(r#"<?xml encoding="UTF-8"?>"#).unwrap()[0],
+        Node::ProcessingInstruction { target, .. } if target == "xml"
+    ));
+    assert!(matches!(
+        &parse(r#"<?xml version="1.1"?>"#).unwrap()[0],
+        Node::Doctype(Doctype::Xml { version, encoding: None, standalone: None, .. }) if version == "1.1"
+    ));
+
+    // They are written back as is.
+    for html in [
+        "<?xml version='1.0' encoding='utf-8'?>",
+        "<?xml version=\"1.0\"  standalone=\"yes\" ?>",
+        "<?pi   data?><?pi\tdata ?><?pi ?><?pi?>",
+    ] {
+        assert_eq!(parse(html).unwrap().html(), html);
+        assert_eq!(html_editor::parse_borrowed(html).unwrap().html(), html);
+    }
+    let mut dom = parse("<?xml version='1.0' encoding='utf-8'?>").unwrap();
+    let Node::Doctype(Doctype::Xml { encoding, .. }) = &mut dom[0] else {
+        panic!("not an XML declaration");
+    };
+    *encoding = None;
+    assert_eq!(dom.html(), r#"<?xml version="1.0"?>"#);
+    let pi = Node::ProcessingInstruction {
+        target: "pi".to_string(),
+        data: "data".to_string(),
+    };
+    assert_eq!(pi.html(), "<?pi data?>");
+}
+
+#[test]
+fn name_case() {
+    let html = r#"<DIV Class="a"><BR><svg viewBox="0 0 1 1"><foreignObject/><LinearGradient/></svg></DIV>"#;
+    let options = ParseOptions {
+        name_case: NameCase::Html,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_with(html, &options).unwrap().html(),
+        r#"<div class="a"><br><svg viewBox="0 0 1 1"><foreignObject></foreignObject><linearGradient></linearGradient></svg></div>"#
+    );
+    let selector = Selector::from("div.a");
+    assert!(try_parse_with(html, &options).query(&selector).is_some());
+
+    // The names are kept as written by default.
+    let xml = "<Message><Log:Warning Level='1'>Hi</Log:Warning></Message>";
+    assert_eq!(
+        parse(xml).unwrap().html(),
+        r#"<Message><Log:Warning Level="1">Hi</Log:Warning></Message>"#
+    );
+    assert!(try_parse(html).query(&selector).is_none());
+}
+
+#[test]
+fn deeply_nested() {
+    // Deep enough to overflow the stack if the nodes are built or
+    // dropped recursively.
+    let depth = 50_000;
+    let html = format!("{}text{}", "<div>".repeat(depth), "</div>".repeat(depth));
+
+    let dom = parse(&html).unwrap();
+    let mut node = &dom[0];
+    let mut level = 1;
+    while let Some(child) = node
+        .as_element()
+        .and_then(|element| element.children.first())
+    {
+        node = child;
+        level += 1;
+    }
+    assert_eq!(level, depth + 1);
+    assert!(matches!(node, Node::Text(text) if text == "text"));
+
+    let unclosed = "<div><span>".repeat(depth);
+    assert_eq!(try_parse(&unclosed).len(), 1);
+    assert!(parse(&unclosed).is_err());
+
+    // Nor are they written, cloned, queried or edited recursively, even
+    // on a small stack.
+    let operations = move || {
+        let mut dom = parse(&html).unwrap();
+        assert_eq!(dom.html(), html);
+        assert_eq!(dom.xml(), html);
+        assert_eq!(dom.clone().html(), html);
+        let borrowed = html_editor::parse_borrowed(&html).unwrap();
+        assert_eq!(borrowed.html(), html);
+        assert_eq!(borrowed.clone().xml(), html);
+        assert_eq!(borrowed[0].to_owned().html(), html);
+        assert_eq!(borrowed.query_all(&Selector::from("div")).len(), depth);
+
+        let selector = Selector::from("div");
+        assert_eq!(dom.query_all(&selector).len(), depth);
+        assert!(dom.query(&Selector::from("span")).is_none());
+        assert!(dom.query_mut(&selector).is_some());
+        let mut count = 0;
+        dom.execute_for(&selector, |_| count += 1);
+        assert_eq!(count, depth);
+        dom.trim().insert_to(&selector, Node::Text("!".to_string()));
+        assert_eq!(dom.html().matches('!').count(), depth);
+        dom.replace_with(&Selector::from("div"), |_| Node::Text("x".to_string()));
+        assert_eq!(dom.html(), "x");
+        let mut dom = parse(&html).unwrap();
+        dom.remove_by(&Selector::from("div"));
+        assert!(dom.is_empty());
+    };
+    std::thread::Builder::new()
+        .stack_size(2 << 20)
+        .spawn(operations)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn attribute_tokenization() {
+    let attrs = |html: &str| -> Vec<(String, String)> {
+        let dom = parse(html).unwrap();
+        dom[0].as_element().unwrap().attrs.clone()
+    };
+    let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    };
+
+    // One attribute per line, in the source order.
+    let html = "<input\n    type=\"text\"\n\tname = q\r\n    disabled\n>";
+    assert_eq!(
+        attrs(html),
+        pairs(&[("type", "text"), ("name", "q"), ("disabled", "")])
+    );
+    let dom = parse(html).unwrap();
+    let spans = &dom[0].element_span().unwrap().attrs;
+    assert_eq!(&html[spans[0].range()], "type=\"text\"");
+    assert_eq!(&html[spans[1].range()], "name = q");
+    assert_eq!(&html[spans[2].range()], "disabled");
+
+    // No backslash escapes, and the quotes only matter at the start of
+    // the values.
+    assert_eq!(attrs(r#"<a title="a\">b</a>"#), pairs(&[("title", "a\\")]));
+    assert_eq!(
+        attrs("<a title=it's class='x'>b</a>"),
+        pairs(&[("title", "it's"), ("class", "x")])
+    );
+    assert_eq!(
+        attrs(r#"<a/b c="d"e=f>g</a>"#),
+        pairs(&[("b", ""), ("c", "d"), ("e", "f")])
+    );
+}
+
+#[test]
+fn duplicate_attributes() {
+    let html = "<p ID=a id=b class=c></p>";
+    let options = |duplicate_attrs| ParseOptions {
+        name_case: NameCase::Html,
+        duplicate_attrs,
+        ..Default::default()
+    };
+    let attrs = |options: &ParseOptions| {
+        let dom = parse_with(html, options).unwrap();
+        dom[0].as_element().unwrap().attrs.clone()
+    };
+    assert_eq!(
+        attrs(&options(DuplicateAttrs::First)),
+        vec![
+            ("id".to_string(), "a".to_string()),
+            ("class".to_string(), "c".to_string())
+        ]
+    );
+    assert_eq!(
+        attrs(&options(DuplicateAttrs::Last)),
+        vec![
+            ("id".to_string(), "b".to_string()),
+            ("class".to_string(), "c".to_string())
+        ]
+    );
+
+    let error = parse_with(html, &options(DuplicateAttrs::Error)).unwrap_err();
+    assert!(matches!(&error, ParseError::DuplicateAttribute { name, .. } if name == "id"));
+    assert_eq!(error.to_string(), "Duplicate attribute `id` at 1:1");
+    assert_eq!(
+        try_parse_with(html, &options(DuplicateAttrs::Error)).html(),
+        r#"<p id="a" class="c"></p>"#
+    );
+
+    // The names are different if they are not normalized.
+    assert_eq!(parse(html).unwrap()[0].as_element().unwrap().attrs.len(), 3);
+}
+
+#[test]
+fn stray_less_than() {
+    let html = "<p>if a < b and c > d, or x <= 3 or 1<2</p>";
+    for dom in [parse(html).unwrap(), try_parse(html)] {
+        let p = dom[0].as_element().unwrap();
+        assert!(
+            matches!(&p.children[..], [Node::Text(text)] if text == "if a < b and c > d, or x <= 3 or 1<2")
+        );
+        assert_eq!(
+            dom.html(),
+            "<p>if a &lt; b and c &gt; d, or x &lt;= 3 or 1&lt;2</p>"
+        );
+    }
+    assert_eq!(
+        parse_document("a < b").html(),
+        "<html><head></head><body>a &lt; b</body></html>"
+    );
+
+    // The `<` followed by a letter, `/`, `!` or `?` still starts a tag.
+    assert_eq!(try_parse("a <b>c</b> <").html(), "a <b>c</b> &lt;");
+    assert!(parse("a <b>").is_err());
+
+    // A `<` in a tag is a part of it, like in browsers.
+    let dom = try_parse("<img alt=1<2><a href=x<b>y</a>");
+    let img = dom[0].as_element().unwrap();
+    assert_eq!(img.attrs, [("alt".to_string(), "1<2".to_string())]);
+    let a = dom[1].as_element().unwrap();
+    assert_eq!(a.attrs, [("href".to_string(), "x<b".to_string())]);
+    assert_eq!(a.children.html(), "y");
+
+    // The `<` at the end of a chunk waits for the next char.
+    let mut parser = Parser::new();
+    parser.feed("1 <").unwrap();
+    parser.feed(" 2 <").unwrap();
+    parser.feed("b>3</b>").unwrap();
+    assert_eq!(parser.finish().unwrap().html(), "1 &lt; 2 <b>3</b>");
+}
@@ -0,0 +1,200 @@
+use html_editor::operation::*;
+use html_editor::{events_with, parse_with, try_parse_with, Event, Node, ParseOptions, Parser};
+
+fn options() -> ParseOptions {
+    let pairs = [("{%", "%}"), ("{{", "}}"), ("<%", "%>"), ("<?php", "?>")];
+    ParseOptions {
+        passthrough: pairs
+            .iter()
+            .map(|(start, end)| (start.to_string(), end.to_string()))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn passthrough(nodes: &[Node]) -> Vec<&str> {
+    let mut found = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Passthrough(raw) => found.push(raw.as_str()),
+            Node::Element(element) => found.extend(passthrough(&element.children)),
+            _ => {}
+        }
+    }
+    found
+}
+
+#[test]
+fn between_tags() {
+    let html = "<ul>{% for item in items %}<li>{{ item.name }} &amp; {{ item | upper }}</li>{% endfor %}</ul>";
+    for dom in [
+        parse_with(html, &options()).unwrap(),
+        try_parse_with(html, &options()),
+    ] {
+        assert_eq!(dom.html(), html);
+        assert_eq!(
+            passthrough(&dom),
+            [
+                "{% for item in items %}",
+                "{{ item.name }}",
+                "{{ item | upper }}",
+                "{% endfor %}"
+            ]
+        );
+    }
+}
+
+#[test]
+fn markup_in_regions() {
+    // The `<`, `>` and the character references in the regions are not
+    // read as markup.
+    let html = "<p>{{ a<b && c>d ? \"&lt;\" : '</p>' }}</p><%= link_to \"<b>\" %><?php echo $a > 1 ? '<br>' : ''; ?>";
+    let dom = parse_with(html, &options()).unwrap();
+    assert_eq!(dom.html(), html);
+    assert_eq!(
+        passthrough(&dom),
+        [
+            "{{ a<b && c>d ? \"&lt;\" : '</p>' }}",
+            "<%= link_to \"<b>\" %>",
+            "<?php echo $a > 1 ? '<br>' : ''; ?>"
+        ]
+    );
+}
+
+#[test]
+fn in_attributes() {
+    let html = r#"<a href="/users/{{ user.id }}?tab=<%= tab %>" {% if active %}class="on"{% endif %} title='{{ "a > b" }}'>x</a><input {{ attrs }} value="1">"#;
+    for dom in [
+        parse_with(html, &options()).unwrap(),
+        try_parse_with(html, &options()),
+    ] {
+        assert_eq!(dom.html(), html);
+        let a = dom[0].as_element().unwrap();
+        assert_eq!(a.attrs.len(), 3);
+        // The values with regions are kept as written.
+        assert_eq!(
+            a.attrs[0],
+            (
+                "href".to_string(),
+                "/users/{{ user.id }}?tab=<%= tab %>".to_string()
+            )
+        );
+        // The regions between the attributes are opaque.
+        assert_eq!(
+            a.attrs[1],
+            (
+                r#"{% if active %}class="on"{% endif %}"#.to_string(),
+                String::new()
+            )
+        );
+        assert_eq!(
+            a.attrs[2],
+            ("title".to_string(), r#"{{ "a > b" }}"#.to_string())
+        );
+        assert_eq!(a.children.html(), "x");
+
+        let input = dom[1].as_element().unwrap();
+        assert_eq!(
+            input.attrs,
+            [
+                ("{{ attrs }}".to_string(), String::new()),
+                ("value".to_string(), "1".to_string())
+            ]
+        );
+    }
+}
+
+#[test]
+fn query_and_edit_attributes() {
+    let html = r#"<a class="btn {{ cls }}" href="/{{ user.id }}?a=1&amp;b={{ b }}" title='{{ "x" }}'>x</a>"#;
+    let mut dom = parse_with(html, &options()).unwrap();
+    let a = dom.query(&Selector::from(".btn")).unwrap();
+    // The values are not decoded.
+    assert_eq!(
+        a.attrs[1],
+        (
+            "href".to_string(),
+            "/{{ user.id }}?a=1&amp;b={{ b }}".to_string()
+        )
+    );
+    assert_eq!(a.attrs[2].1, r#"{{ "x" }}"#);
+
+    // The changed values are escaped as usual.
+    dom.execute_for(&Selector::from("a"), |a| {
+        a.attrs[2].1 = r#"a "b""#.to_string();
+    });
+    assert_eq!(
+        dom.html(),
+        r#"<a class="btn {{ cls }}" href="/{{ user.id }}?a=1&amp;b={{ b }}" title="a &quot;b&quot;">x</a>"#
+    );
+    dom.execute_for(&Selector::from("a"), |a| {
+        a.attrs[1].1 = "/{{ user.id }}?a=1&b=2".to_string();
+        a.attrs.remove(0);
+    });
+    assert_eq!(
+        dom.html(),
+        r#"<a href="/{{ user.id }}?a=1&amp;b=2" title="a &quot;b&quot;">x</a>"#
+    );
+}
+
+#[test]
+fn byte_for_byte() {
+    let html = r#"<div class="card" {{ extra }}>{% if x > 0 %}<b>{{ x }}</b>{% else %}none{% endif %}</div>"#;
+    let dom = parse_with(html, &options()).unwrap();
+    assert_eq!(dom.html(), html);
+    // The identical fragments are never deduplicated.
+    let html = r#"<p {% if a %}hidden{% endif %} {% if b %}title="b"{% endif %}></p>"#;
+    assert_eq!(parse_with(html, &options()).unwrap().html(), html);
+}
+
+#[test]
+fn not_recognized() {
+    // Not in the comments and the text content of `<script>`.
+    let html = "<!-- {{ a }} --><script>let a = '{{ b }}';</script><p>{ {c} }</p>";
+    let dom = parse_with(html, &options()).unwrap();
+    assert_eq!(dom.html(), html);
+    assert!(passthrough(&dom).is_empty());
+    // Without the option, they are text and attributes as usual.
+    let html = "<p {{ a }}>{{ b }}</p>";
+    let dom = parse_with(html, &ParseOptions::default()).unwrap();
+    assert_eq!(dom[0].as_element().unwrap().attrs.len(), 3);
+    assert!(passthrough(&dom).is_empty());
+}
+
+#[test]
+fn unterminated() {
+    let dom = try_parse_with("<p>{{ a &amp; b</p>", &options());
+    // The region takes the rest of the html.
+    assert_eq!(dom.html(), "<p>{{ a &amp; b</p></p>");
+    assert_eq!(passthrough(&dom), ["{{ a &amp; b</p>"]);
+}
+
+#[test]
+fn events_and_streaming() {
+    let html = "a {{ b }}<i>{% c %}</i>";
+    let events: Vec<Event> = events_with(html, &options())
+        .map(|event| event.unwrap().0)
+        .collect();
+    assert_eq!(
+        events,
+        [
+            Event::Text("a ".to_string()),
+            Event::Passthrough("{{ b }}".to_string()),
+            Event::StartTag {
+                name: "i".to_string(),
+                attrs: Vec::new(),
+                self_closing: false
+            },
+            Event::Passthrough("{% c %}".to_string()),
+            Event::EndTag {
+                name: "i".to_string()
+            },
+        ]
+    );
+
+    let mut parser = Parser::with_options(options(), false);
+    for ch in html.chars() {
+        parser.feed(&ch.to_string()).unwrap();
+    }
+    assert_eq!(parser.finish().unwrap().html(), html);
+}
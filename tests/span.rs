@@ -0,0 +1,106 @@
+use html_editor::{events, operation::*, parse, try_parse, Node, Span};
+
+const HTML: &str = r#"<div id="app">
+    <img src="a.png" alt=a>
+    <p class="text" hidden>Hello</p>
+    <br/>
+</div>"#;
+
+#[test]
+fn element_span() {
+    let dom = parse(HTML).unwrap();
+    let div = dom[0].element_span().unwrap();
+    assert_eq!(div.outer, Span::new(0, HTML.len()));
+    assert_eq!(&HTML[div.start_tag.range()], r#"<div id="app">"#);
+    assert_eq!(&HTML[div.end_tag.unwrap().range()], "</div>");
+
+    let p = dom.query(&Selector::from("p")).unwrap().span().unwrap();
+    assert_eq!(
+        &HTML[p.outer.range()],
+        r#"<p class="text" hidden>Hello</p>"#
+    );
+}
+
+#[test]
+fn attribute_span() {
+    let dom = parse(HTML).unwrap();
+    let img = dom.query(&Selector::from("img")).unwrap();
+    let span = img.span().unwrap();
+    assert_eq!(span.end_tag, None);
+    assert_eq!(&HTML[span.outer.range()], r#"<img src="a.png" alt=a>"#);
+    assert_eq!(span.attrs.len(), 2);
+    for ((key, value), span) in img.attrs.iter().zip(&span.attrs) {
+        let source = &HTML[span.range()];
+        assert!(
+            source == format!(r#"{}="{}""#, key, value) || source == format!("{}={}", key, value)
+        );
+    }
+
+    let p = dom.query(&Selector::from("p")).unwrap();
+    for ((key, _), span) in p.attrs.iter().zip(&p.span().unwrap().attrs) {
+        assert!(HTML[span.range()].starts_with(key.as_str()));
+    }
+}
+
+#[test]
+fn self_closing_span() {
+    let dom = parse(HTML).unwrap();
+    let br = dom.query(&Selector::from("br")).unwrap().span().unwrap();
+    assert_eq!(&HTML[br.outer.range()], "<br/>");
+}
+
+#[test]
+fn auto_closed_span() {
+    let html = "<div><a>Ipsum";
+    let dom = try_parse(html);
+    let div = dom[0].element_span().unwrap();
+    assert_eq!(div.outer, Span::new(0, html.len()));
+    assert_eq!(div.end_tag, None);
+}
+
+#[test]
+fn surgical_edit() {
+    let html = r#"<ul><li class="a">1</li><li class="b">2</li></ul>"#;
+    let dom = parse(html).unwrap();
+    let span = dom.query(&Selector::from(".b")).unwrap().span().unwrap();
+    let mut edited = html.to_string();
+    edited.replace_range(span.attrs[0].range(), r#"class="c""#);
+    assert_eq!(
+        edited,
+        r#"<ul><li class="a">1</li><li class="c">2</li></ul>"#
+    );
+}
+
+#[test]
+fn created_node_has_no_span() {
+    let node = Node::new_element("div", vec![("id", "app")], vec![]);
+    assert!(node.element_span().is_none());
+    assert!(Node::Text("text".to_string()).element_span().is_none());
+}
+
+#[test]
+fn only_elements_have_spans() {
+    let html = "<!DOCTYPE html><!-- a --><p>b</p>c<![CDATA[d]]>";
+    let dom = parse(html).unwrap();
+    let spans: Vec<bool> = dom
+        .iter()
+        .map(|node| node.element_span().is_some())
+        .collect();
+    assert_eq!(spans, [false, false, true, false, false]);
+    // The spans of the other nodes are reported by the events.
+    let spans: Vec<&str> = events(html)
+        .map(|event| &html[event.unwrap().1.range()])
+        .collect();
+    assert_eq!(
+        spans,
+        [
+            "<!DOCTYPE html>",
+            "<!-- a -->",
+            "<p>",
+            "b",
+            "</p>",
+            "c",
+            "<![CDATA[d]]>"
+        ]
+    );
+}
@@ -0,0 +1,99 @@
+use html_editor::operation::*;
+use html_editor::{parse, try_parse, Parser};
+
+const HTML: &str = r#"<!DOCTYPE html>
+<html>
+    <head>
+        <title>Café &amp; Bar</title>
+        <script>if (a < b && c > d) { "</div>" }</script>
+    </head>
+    <body>
+        <!-- comment -->
+        <div id="app" class='a b'>
+            <p>Hello, <b>wörld</b>! 🎉</p>
+            <img src="a.png" alt="a > b">
+            <br/>
+        </div>
+    </body>
+</html>"#;
+
+fn feed_by_chunks(mut parser: Parser, html: &str, size: usize) -> Parser {
+    let chars: Vec<char> = html.chars().collect();
+    for chunk in chars.chunks(size) {
+        let _ = parser.feed(&String::from_iter(chunk));
+    }
+    parser
+}
+
+#[test]
+fn same_as_parse() {
+    let expected = parse(HTML).unwrap().html();
+    for size in 1..10 {
+        let parser = feed_by_chunks(Parser::new(), HTML, size);
+        assert_eq!(parser.finish().unwrap().html(), expected, "{}", size);
+    }
+}
+
+#[test]
+fn same_as_try_parse() {
+    let html = "<ul><li>1<li>2</ul><div><p>a<p>b</span></div><table><tr><td>1<td>2</table><i>";
+    let expected = try_parse(html).html();
+    for size in 1..10 {
+        let parser = feed_by_chunks(Parser::fault_tolerant(), html, size);
+        assert_eq!(parser.finish().unwrap().html(), expected, "{}", size);
+    }
+}
+
+#[test]
+fn same_spans() {
+    let expected = parse(HTML).unwrap();
+    let dom = feed_by_chunks(Parser::new(), HTML, 3).finish().unwrap();
+    let selector = "p".into();
+    assert_eq!(
+        dom.query(&selector).unwrap().span(),
+        expected.query(&selector).unwrap().span()
+    );
+}
+
+#[test]
+fn bytes() {
+    let expected = parse(HTML).unwrap().html();
+    let bytes = HTML.as_bytes();
+    for size in 1..10 {
+        let mut parser = Parser::new();
+        for chunk in bytes.chunks(size) {
+            parser.feed_bytes(chunk).unwrap();
+        }
+        assert_eq!(parser.finish().unwrap().html(), expected, "{}", size);
+    }
+
+    let mut parser = Parser::new();
+    parser.feed_bytes(b"<p>a\xFFb</p>\xE2\x82").unwrap();
+    assert_eq!(parser.finish().unwrap().html(), "<p>a\u{FFFD}b</p>\u{FFFD}");
+}
+
+#[test]
+fn reader() {
+    let mut parser = Parser::new();
+    parser.read_from(HTML.as_bytes()).unwrap();
+    assert_eq!(parser.finish().unwrap().html(), parse(HTML).unwrap().html());
+
+    let mut parser = Parser::new();
+    let error = parser.read_from("<div></span>".as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn errors() {
+    let html = "<div>\n<span></div>";
+    let expected = parse(html).unwrap_err();
+    let mut parser = Parser::new();
+    parser.feed("<div>\n<span></d").unwrap();
+    assert_eq!(parser.feed("iv>").unwrap_err(), expected);
+    assert_eq!(parser.feed("more").unwrap_err(), expected);
+    assert_eq!(parser.finish().unwrap_err(), expected);
+
+    let mut parser = Parser::new();
+    parser.feed("<div>").unwrap();
+    assert_eq!(parser.finish().unwrap_err(), parse("<div>").unwrap_err());
+}
@@ -0,0 +1,84 @@
+use html_editor::operation::*;
+use html_editor::{parse, parse_borrowed, parse_document, try_parse, Element, Node};
+
+const HTML: &str = r#"<div id="app"><template id="row"><tr><td class="cell">A</td></tr><template><b class="cell">B</b></template></template><p class="cell">C</p></div>"#;
+
+fn template(dom: &Vec<Node>) -> &Element {
+    dom.query(&Selector::from("template")).unwrap()
+}
+
+#[test]
+fn content() {
+    for dom in [parse(HTML).unwrap(), try_parse(HTML)] {
+        assert_eq!(dom.html(), HTML);
+        // The template content is skipped by the queries.
+        let cells = dom.query_all(&Selector::from(".cell"));
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0].name, "p");
+        assert_eq!(dom.query_all(&Selector::from("template")).len(), 1);
+
+        let template = template(&dom);
+        assert!(template.children.is_empty());
+        let content = template.template_content.as_ref().unwrap();
+        assert_eq!(content.query_all(&Selector::from(".cell")).len(), 1);
+        // The nested template has its own content.
+        let nested = content.query(&Selector::from("template")).unwrap();
+        assert_eq!(
+            nested.template_content.as_ref().unwrap().html(),
+            r#"<b class="cell">B</b>"#
+        );
+    }
+    let div = parse(HTML).unwrap().remove(0);
+    assert!(div.as_element().unwrap().template_content.is_none());
+}
+
+#[test]
+fn document() {
+    let html = "<template><tr><td>a</td></tr></template><p>b</p>";
+    let dom = parse_document(html);
+    assert_eq!(
+        dom.html(),
+        "<html><head><template><tr><td>a</td></tr></template></head><body><p>b</p></body></html>"
+    );
+    let template = template(&dom);
+    assert!(template.children.is_empty());
+    assert!(dom.query(&Selector::from("td")).is_none());
+    let content = template.template_content.as_ref().unwrap();
+    assert_eq!(content.query(&Selector::from("td")).unwrap().name, "td");
+}
+
+#[test]
+fn edit() {
+    let mut dom = parse(HTML).unwrap();
+    dom.remove_by(&Selector::from(".cell"));
+    assert_eq!(dom.html(), HTML.replace(r#"<p class="cell">C</p>"#, ""));
+
+    dom.execute_for(&Selector::from("#row"), |template| {
+        let content = template.template_content.as_mut().unwrap();
+        content.remove_by(&Selector::from("template"));
+        content.insert_to(
+            &Selector::from("tr"),
+            Node::new_element("td", vec![], vec![]),
+        );
+    });
+    assert_eq!(
+        dom.html(),
+        r#"<div id="app"><template id="row"><tr><td class="cell">A</td><td></td></tr></template></div>"#
+    );
+
+    // The children of the templates created by hand are not moved.
+    let node = Node::new_element("template", vec![], vec![Node::Text("a".to_string())]);
+    assert_eq!(node.html(), "<template>a</template>");
+    assert_eq!(node.query_all(&Selector::from("template")).len(), 1);
+}
+
+#[test]
+fn borrowed() {
+    let dom = parse_borrowed(HTML).unwrap();
+    assert_eq!(dom.html(), HTML);
+    assert_eq!(dom.query_all(&Selector::from(".cell")).len(), 1);
+    let template = dom.query(&Selector::from("template")).unwrap();
+    assert!(template.children.is_empty());
+    let owned = template.to_owned();
+    assert_eq!(owned.template_content.as_ref().unwrap().len(), 2);
+}
@@ -0,0 +1,133 @@
+use html_editor::operation::*;
+use html_editor::{parse_xml, Doctype, Namespace, Node, ParseError};
+
+const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+    <channel>
+        <title>News &amp; Views</title>
+        <link>https://example.com/</link>
+        <atom:link href="https://example.com/rss" rel="self"/>
+        <item><script>a &lt; b</script><style/></item>
+    </channel>
+</rss>"#;
+
+#[test]
+fn generic_tags() {
+    let dom = parse_xml(RSS).unwrap();
+    assert!(matches!(&dom[0], Node::Doctype(Doctype::Xml { .. })));
+    let link = dom.query(&Selector::from("link")).unwrap();
+    assert_eq!(link.children.len(), 1);
+    let script = dom.query(&Selector::from("script")).unwrap();
+    assert_eq!(script.xml(), "<script>a &lt; b</script>");
+    assert_eq!(
+        dom.query(&Selector::from("item")).unwrap().children.len(),
+        2
+    );
+
+    // The names are case-sensitive.
+    assert!(parse_xml("<Feed></feed>").is_err());
+    assert!(parse_xml("<Feed></Feed>").is_ok());
+}
+
+#[test]
+fn namespaces() {
+    let dom = parse_xml(RSS).unwrap();
+    let rss = dom.query(&Selector::from("rss")).unwrap();
+    assert_eq!(rss.namespace, Namespace::Null);
+    let atom = dom.query(&Selector::from("atom:link")).unwrap();
+    assert_eq!(
+        atom.namespace,
+        Namespace::Other("http://www.w3.org/2005/Atom".to_string())
+    );
+
+    let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><use xlink:href="#a"/><g xmlns=""><rect/></g></svg>"##;
+    let dom = parse_xml(svg).unwrap();
+    assert_eq!(dom.query(&Selector::from("svg|use")).unwrap().name, "use");
+    assert_eq!(
+        dom.query(&Selector::from("rect")).unwrap().namespace,
+        Namespace::Null
+    );
+    assert_eq!(dom.xml(), svg);
+
+    let error = parse_xml("<a><b:c/></a>").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Namespace prefix `b` is not declared at 1:4"
+    );
+    assert!(parse_xml(r#"<a b:c="d"/>"#).is_err());
+    assert!(parse_xml(r#"<a xml:lang="en"/>"#).is_ok());
+}
+
+#[test]
+fn well_formed() {
+    let not_well_formed = |xml: &str| match parse_xml(xml) {
+        Err(ParseError::NotWellFormed { reason, .. }) => reason,
+        result => panic!("{:?}", result),
+    };
+    assert_eq!(
+        not_well_formed("<a b=c/>"),
+        "Value of attribute `b` is not quoted"
+    );
+    assert_eq!(
+        not_well_formed("<a disabled/>"),
+        "Value of attribute `disabled` is not quoted"
+    );
+    assert_eq!(
+        not_well_formed("<a/><b/>"),
+        "Root element is followed by <b>"
+    );
+    assert_eq!(
+        not_well_formed("text<a/>"),
+        "Text outside of the root element"
+    );
+    assert_eq!(not_well_formed("<!-- a -->"), "No root element");
+
+    assert!(matches!(
+        parse_xml(r#"<a b="1" b="2"/>"#),
+        Err(ParseError::DuplicateAttribute { .. })
+    ));
+    assert!(matches!(
+        parse_xml("<a><br></a>"),
+        Err(ParseError::MismatchedEndTag { .. })
+    ));
+    assert!(parse_xml("<!-- a -->\n<a>1 > 0</a>\n<?pi?>").is_ok());
+
+    // The markup characters must be escaped, and only the references of
+    // XML are defined.
+    assert_eq!(not_well_formed("<a>1 < 2</a>"), "`<` is not escaped");
+    assert_eq!(not_well_formed("<a>x<y</a>"), "Tag `<y` is not closed");
+    assert_eq!(not_well_formed("<a>x & y</a>"), "`&` is not escaped");
+    assert_eq!(not_well_formed("<a>]]></a>"), "`]]>` is not escaped");
+    assert_eq!(
+        not_well_formed("<a>&nbsp;</a>"),
+        "Undefined entity `&nbsp;`"
+    );
+    assert_eq!(
+        not_well_formed(r#"<a b="x<y"/>"#),
+        "`<` is not escaped in the value of attribute `b`"
+    );
+    assert_eq!(
+        not_well_formed("<a>&#0;</a>"),
+        "Invalid character reference `&#0;`"
+    );
+    assert_eq!(not_well_formed("<a></ a>"), "Invalid end tag `</ a>`");
+    assert_eq!(not_well_formed("<a></a/>"), "Invalid end tag `</a/>`");
+    assert!(parse_xml("<a></a >").is_ok());
+    let error = parse_xml("<a>\n  x &copy;</a>").unwrap_err();
+    assert_eq!(error.to_string(), "Undefined entity `&copy;` at 2:5");
+}
+
+#[test]
+fn references() {
+    let dom = parse_xml(r#"<a b="&apos;&#65;&lt;">&amp;&#x41;&apos;&quot;&gt;</a>"#).unwrap();
+    let a = dom[0].as_element().unwrap();
+    assert_eq!(a.attrs[0].1, "'A<");
+    assert_eq!(a.children.html(), "&amp;A'\"&gt;");
+}
+
+#[test]
+fn serialize() {
+    let dom = parse_xml(r#"<a b="" c='&lt;"'><br/><p></p>&#xA0;</a>"#).unwrap();
+    assert_eq!(dom.xml(), "<a b=\"\" c=\"&lt;&quot;\"><br/><p/>\u{A0}</a>");
+    assert_eq!(parse_xml(RSS).unwrap().xml(), RSS);
+}